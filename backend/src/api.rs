@@ -1,14 +1,47 @@
-use actix_web::{delete, get, post, web, HttpResponse, Responder};
-use common::models::{Card, Deck, PostDeck, RevisionCard};
-use common::query_params::CardReadQuery;
-use common::FlipMode;
+use std::cmp::max;
+use std::collections::HashMap;
+
+use actix_files::NamedFile;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use common::models::{
+    BulkOperation, BulkPayload, Card, CardMatch, Deck, DeckCard, DeckCardMatch, DuplicateCluster,
+    FeedbackPayload, Media, MergePayload, PostDeck, Review, RevisionCard, SplitPayload,
+    TransferPayload,
+};
+use common::query_params::{
+    CardReadQuery, CombinedRevisionQuery, DuplicateScope, DuplicatesQuery, MediaUploadQuery,
+    NewCardQuery, RevisionQuery, TimezoneQuery,
+};
+use common::speech::{is_valid_lang, is_valid_rate};
+use common::stats::Stats;
 use diesel::dsl::{exists, select, sql, sql_query};
 use diesel::prelude::*;
+use diesel::sql_types::{Float, Integer, Nullable, Text};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use serde::Deserialize;
 
 use crate::auth::Authenticated;
+use crate::bulk::apply_bulk;
 use crate::db::*;
+use crate::duplicates::{cluster, find_duplicate, normalized_pairs};
+use crate::media::{content_hash, is_valid_hash, lock_blob, sniff_content_type, store};
 use crate::revision::*;
+use crate::search::{headline, matches, rank_desc, to_tsquery_input};
+use crate::settings::Settings;
+use crate::stats::{answer_times, compute_activity, compute_stats, local_date, SLOWEST_CARDS};
+use crate::transfer::transfer_cards;
+
+fn owns_deck(conn: &PgConnection, user_id: i32, deck_id: i32) -> bool {
+    use common::schema::decks;
+
+    let deck_query = decks::table
+        .filter(decks::id.eq(deck_id))
+        .filter(decks::user_id.eq(user_id));
+    select(exists(deck_query)).get_result(conn).unwrap()
+}
 
 #[get("/")]
 async fn read_decks(auth: Authenticated, pool: web::Data<DbPool>) -> impl Responder {
@@ -83,13 +116,49 @@ async fn update_deck(
         // TODO probably could handle during deserialization?
         payload.name = Some(name.trim().to_string());
     }
+    for lang in [&payload.front_lang, &payload.back_lang]
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        if !is_valid_lang(lang) {
+            return HttpResponse::BadRequest().finish();
+        }
+    }
+    if let Some(speech_rate) = payload.speech_rate {
+        if !is_valid_rate(speech_rate) {
+            return HttpResponse::BadRequest().finish();
+        }
+    }
+    if let Some(Some(ratio)) = payload.new_card_ratio {
+        if !(0..=100).contains(&ratio) {
+            return HttpResponse::BadRequest().finish();
+        }
+    }
+    if let Some(Some(slow_answer_ms)) = payload.slow_answer_ms {
+        if slow_answer_ms <= 0 {
+            return HttpResponse::BadRequest().finish();
+        }
+    }
     // TODO should enforce the same min / max `revision_length` as on frontend.
     let target = decks::table
         .filter(decks::id.eq(deck_id))
         .filter(decks::user_id.eq(auth.get_user(&conn).id));
-    let deck = diesel::update(target)
-        .set(payload)
-        .get_result::<Deck>(&conn)
+    let deck = conn
+        .transaction(|| {
+            let was_reversing = decks::table
+                .find(deck_id)
+                .select(decks::reverse_siblings)
+                .first::<bool>(&conn)?;
+            let deck = diesel::update(target)
+                .set(&payload)
+                .get_result::<Deck>(&conn)?;
+            // Switching siblings on: the back -> front direction starts where the card is at.
+            if deck.reverse_siblings && !was_reversing {
+                start_reverse_siblings(&conn, deck.id)?;
+            }
+            Ok::<Deck, diesel::result::Error>(deck)
+        })
         .unwrap();
 
     HttpResponse::Ok().json(deck)
@@ -117,29 +186,46 @@ async fn delete_deck(
 async fn read_cards(
     auth: Authenticated,
     pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
     path: web::Path<(i32,)>,
     query: web::Query<CardReadQuery>,
 ) -> impl Responder {
     use common::schema::{cards, decks};
 
     let (deck_id,) = path.into_inner();
+    let per_page = query.per_page.unwrap_or(settings.per_page);
     let conn = pool.get().unwrap();
     let user_id = auth.get_user(&conn).id;
-    let search_term = format!("%{}%", query.search_term);
 
-    let page: Page<Card> = cards::table
+    let deck_cards = cards::table
         .inner_join(decks::table)
         .filter(cards::deck_id.eq(deck_id))
-        .filter(
-            cards::front
-                .ilike(&search_term)
-                .or(cards::back.ilike(&search_term)),
-        )
-        .filter(decks::user_id.eq(user_id))
-        .select(cards::table::all_columns())
-        .paginate(query.page, query.per_page)
-        .load_and_count_pages(&conn)
-        .unwrap();
+        .filter(decks::user_id.eq(user_id));
+
+    let page: Page<CardMatch> = if let Some(tsquery) = to_tsquery_input(&query.search_term) {
+        deck_cards
+            .filter(matches(&tsquery))
+            .select((
+                cards::table::all_columns(),
+                headline("cards.front", &tsquery),
+                headline("cards.back", &tsquery),
+            ))
+            .order_by((rank_desc(&tsquery), cards::id))
+            .paginate(query.page, per_page)
+            .load_and_count_pages(&conn)
+            .unwrap()
+    } else {
+        deck_cards
+            .select((
+                cards::table::all_columns(),
+                sql::<Nullable<Text>>("NULL"),
+                sql::<Nullable<Text>>("NULL"),
+            ))
+            .order_by(cards::id)
+            .paginate(query.page, per_page)
+            .load_and_count_pages(&conn)
+            .unwrap()
+    };
 
     HttpResponse::Ok().json(page)
 }
@@ -217,9 +303,10 @@ async fn update_card(
 
 #[post("/{id}/cards/")]
 async fn new_card(
-    auth: Authenticated, // TODO
+    auth: Authenticated,
     pool: web::Data<DbPool>,
     path: web::Path<(i32,)>,
+    query: web::Query<NewCardQuery>,
     payload: web::Json<CardPayload>,
 ) -> impl Responder {
     use common::schema::{cards, decks};
@@ -227,25 +314,36 @@ async fn new_card(
     let (deck_id,) = path.into_inner();
     let payload = payload.into_inner();
     let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
 
-    let deck_query = decks::table
-        .filter(decks::id.eq(deck_id))
-        .filter(decks::user_id.eq(auth.get_user(&conn).id));
-    let valid_deck = select(exists(deck_query)).get_result(&conn).unwrap();
-
-    if valid_deck {
-        let card: Card = diesel::insert_into(cards::table)
-            .values((
-                cards::front.eq(&payload.front),
-                cards::back.eq(&payload.back),
-                cards::deck_id.eq(&deck_id),
-            ))
-            .get_result(&conn)
-            .unwrap();
-        HttpResponse::Ok().json(card)
-    } else {
-        HttpResponse::BadRequest().finish()
+    if !owns_deck(&conn, user_id, deck_id) {
+        return HttpResponse::BadRequest().finish();
     }
+
+    if !query.allow_duplicate {
+        let mut candidates = cards::table
+            .inner_join(decks::table)
+            .filter(decks::user_id.eq(user_id))
+            .select(cards::table::all_columns())
+            .into_boxed();
+        if query.duplicate_scope == DuplicateScope::Deck {
+            candidates = candidates.filter(cards::deck_id.eq(deck_id));
+        }
+        let candidates = candidates.load::<Card>(&conn).unwrap();
+        if let Some(existing) = find_duplicate(&payload.front, &candidates, |card| &card.front) {
+            return HttpResponse::Conflict().json(existing);
+        }
+    }
+
+    let card: Card = diesel::insert_into(cards::table)
+        .values((
+            cards::front.eq(&payload.front),
+            cards::back.eq(&payload.back),
+            cards::deck_id.eq(&deck_id),
+        ))
+        .get_result(&conn)
+        .unwrap();
+    HttpResponse::Ok().json(card)
 }
 
 #[delete("/{deck_id}/cards/{card_id}/")]
@@ -276,23 +374,533 @@ async fn delete_card(
     HttpResponse::Ok()
 }
 
+#[post("/{id}/cards/bulk/")]
+async fn bulk_update_cards(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    payload: web::Json<BulkPayload>,
+) -> impl Responder {
+    use common::schema::{cards, decks};
+
+    let (deck_id,) = path.into_inner();
+    let mut payload = payload.into_inner();
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+
+    payload.card_ids.sort_unstable();
+    payload.card_ids.dedup();
+    if payload.card_ids.is_empty() {
+        return HttpResponse::BadRequest().finish();
+    }
+    match &mut payload.operation {
+        BulkOperation::AddTag { tag } | BulkOperation::RemoveTag { tag } => {
+            *tag = tag.trim().to_string();
+            if tag.is_empty() {
+                return HttpResponse::BadRequest().finish();
+            }
+        }
+        BulkOperation::Move {
+            deck_id: target_deck_id,
+        } if !owns_deck(&conn, user_id, *target_deck_id) => {
+            return HttpResponse::BadRequest().finish();
+        }
+        BulkOperation::Move {
+            deck_id: target_deck_id,
+        } if !auth.allows_deck(*target_deck_id) => {
+            return HttpResponse::Forbidden().finish();
+        }
+        _ => {}
+    }
+
+    // All or nothing: any card that isn't in this user's deck fails the whole request.
+    let updated = conn
+        .transaction(|| {
+            let owned: i64 = cards::table
+                .inner_join(decks::table)
+                .filter(cards::id.eq_any(&payload.card_ids))
+                .filter(cards::deck_id.eq(deck_id))
+                .filter(decks::user_id.eq(user_id))
+                .count()
+                .get_result(&conn)?;
+            if owned as usize != payload.card_ids.len() {
+                return Ok(None);
+            }
+            apply_bulk(&conn, &payload.card_ids, &payload.operation).map(Some)
+        })
+        .unwrap();
+
+    match updated {
+        Some(updated) => HttpResponse::Ok().json(updated),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[post("/{id}/cards/transfer/")]
+async fn transfer_deck_cards(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    payload: web::Json<TransferPayload>,
+) -> impl Responder {
+    use common::schema::{cards, decks};
+
+    let (deck_id,) = path.into_inner();
+    let mut payload = payload.into_inner();
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+
+    payload.card_ids.sort_unstable();
+    payload.card_ids.dedup();
+    if payload.card_ids.is_empty()
+        || payload.target_deck_id == deck_id
+        || !owns_deck(&conn, user_id, payload.target_deck_id)
+    {
+        return HttpResponse::BadRequest().finish();
+    }
+    if !auth.allows_deck(payload.target_deck_id) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let owned: i64 = cards::table
+        .inner_join(decks::table)
+        .filter(cards::id.eq_any(&payload.card_ids))
+        .filter(cards::deck_id.eq(deck_id))
+        .filter(decks::user_id.eq(user_id))
+        .count()
+        .get_result(&conn)
+        .unwrap();
+    if owned as usize != payload.card_ids.len() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let transferred = transfer_cards(
+        &conn,
+        &payload.card_ids,
+        payload.target_deck_id,
+        payload.mode,
+        payload.keep_history,
+    )
+    .unwrap();
+    HttpResponse::Ok().json(transferred)
+}
+
+#[post("{id}/merge/")]
+async fn merge_deck(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    payload: web::Json<MergePayload>,
+) -> impl Responder {
+    use common::schema::{cards, decks};
+
+    let (deck_id,) = path.into_inner();
+    let source_deck_id = payload.source_deck_id;
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+
+    if source_deck_id == deck_id
+        || !owns_deck(&conn, user_id, deck_id)
+        || !owns_deck(&conn, user_id, source_deck_id)
+    {
+        return HttpResponse::BadRequest().finish();
+    }
+    if !auth.allows_deck(source_deck_id) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let deck = conn
+        .transaction(|| {
+            diesel::update(cards::table.filter(cards::deck_id.eq(source_deck_id)))
+                .set(cards::deck_id.eq(deck_id))
+                .execute(&conn)?;
+            diesel::delete(decks::table.filter(decks::id.eq(source_deck_id))).execute(&conn)?;
+            decks::table.find(deck_id).first::<Deck>(&conn)
+        })
+        .unwrap();
+    HttpResponse::Ok().json(deck)
+}
+
+#[post("{id}/split/")]
+async fn split_deck(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    payload: web::Json<SplitPayload>,
+) -> impl Responder {
+    use common::schema::{cards, decks};
+
+    let (deck_id,) = path.into_inner();
+    // The new deck would be out of reach of a token restricted to this one.
+    if auth.deck_id().is_some() {
+        return HttpResponse::Forbidden().finish();
+    }
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+
+    let name = payload.name.trim();
+    let tsquery = to_tsquery_input(&payload.search_term);
+    let source = decks::table
+        .filter(decks::id.eq(deck_id))
+        .filter(decks::user_id.eq(user_id))
+        .first::<Deck>(&conn)
+        .optional()
+        .unwrap();
+    let (source, tsquery) = match (source, tsquery) {
+        (Some(source), Some(tsquery)) if !name.is_empty() => (source, tsquery),
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+
+    let card_ids = cards::table
+        .filter(cards::deck_id.eq(deck_id))
+        .filter(matches(&tsquery))
+        .select(cards::id)
+        .load::<i32>(&conn)
+        .unwrap();
+    if card_ids.is_empty() {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    // The new deck takes after the one it was split from.
+    let deck = conn
+        .transaction(|| {
+            let deck = diesel::insert_into(decks::table)
+                .values((
+                    decks::name.eq(name),
+                    decks::user_id.eq(user_id),
+                    decks::revision_length.eq(source.revision_length),
+                    decks::flip_mode.eq(source.flip_mode),
+                    decks::autoplay_front.eq(source.autoplay_front),
+                    decks::autoplay_back.eq(source.autoplay_back),
+                    decks::front_lang.eq(&source.front_lang),
+                    decks::back_lang.eq(&source.back_lang),
+                    decks::front_voice.eq(&source.front_voice),
+                    decks::back_voice.eq(&source.back_voice),
+                    decks::speech_rate.eq(source.speech_rate),
+                    decks::review_order.eq(source.review_order),
+                    decks::new_card_order.eq(source.new_card_order),
+                    decks::new_card_ratio.eq(source.new_card_ratio),
+                    decks::reverse_siblings.eq(source.reverse_siblings),
+                    decks::slow_answer_ms.eq(source.slow_answer_ms),
+                ))
+                .get_result::<Deck>(&conn)?;
+            diesel::update(cards::table.filter(cards::id.eq_any(&card_ids)))
+                .set(cards::deck_id.eq(deck.id))
+                .execute(&conn)?;
+            Ok::<Deck, diesel::result::Error>(deck)
+        })
+        .unwrap();
+    HttpResponse::Ok().json(deck)
+}
+
+fn find_owned_card(conn: &PgConnection, user_id: i32, deck_id: i32, card_id: i32) -> Option<Card> {
+    use common::schema::{cards, decks};
+
+    cards::table
+        .inner_join(decks::table)
+        .filter(cards::id.eq(card_id))
+        .filter(cards::deck_id.eq(deck_id))
+        .filter(decks::user_id.eq(user_id))
+        .select(cards::table::all_columns())
+        .first::<Card>(conn)
+        .optional()
+        .unwrap()
+}
+
+#[post("/{deck_id}/cards/{card_id}/media/")]
+async fn upload_media(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    path: web::Path<(i32, i32)>,
+    query: web::Query<MediaUploadQuery>,
+    body: web::Bytes,
+) -> impl Responder {
+    use common::schema::media;
+
+    let (deck_id, card_id) = path.into_inner();
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+    if find_owned_card(&conn, user_id, deck_id, card_id).is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    if body.len() > settings.media.max_bytes {
+        return HttpResponse::PayloadTooLarge().finish();
+    }
+    let content_type = match sniff_content_type(&body) {
+        Some(content_type) => content_type,
+        None => return HttpResponse::UnsupportedMediaType().finish(),
+    };
+
+    // Under the blob's lock so the garbage collection can't delete the file before the row exists.
+    let media = conn
+        .transaction::<_, diesel::result::Error, _>(|| {
+            lock_blob(&conn, &content_hash(&body))?;
+            let hash = store(&settings.media, &body).unwrap();
+            diesel::insert_into(media::table)
+                .values((
+                    media::card_id.eq(card_id),
+                    media::side.eq(query.side),
+                    media::hash.eq(&hash),
+                    media::content_type.eq(content_type),
+                    media::size.eq(body.len() as i32),
+                ))
+                .get_result::<Media>(&conn)
+        })
+        .unwrap();
+    HttpResponse::Ok().json(media)
+}
+
+#[get("/{deck_id}/cards/{card_id}/media/")]
+async fn read_card_media(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+) -> impl Responder {
+    let (deck_id, card_id) = path.into_inner();
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+    let card = match find_owned_card(&conn, user_id, deck_id, card_id) {
+        Some(card) => card,
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    let results = Media::belonging_to(&card)
+        .order_by(common::schema::media::id)
+        .load::<Media>(&conn)
+        .unwrap();
+    HttpResponse::Ok().json(results)
+}
+
+#[get("{id}/media/")]
+async fn read_deck_media(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+) -> impl Responder {
+    use common::schema::{cards, decks, media};
+
+    let (deck_id,) = path.into_inner();
+    let conn = pool.get().unwrap();
+    let results = media::table
+        .inner_join(cards::table.inner_join(decks::table))
+        .filter(cards::deck_id.eq(deck_id))
+        .filter(decks::user_id.eq(auth.get_user(&conn).id))
+        .select(media::table::all_columns())
+        .order_by(media::id)
+        .load::<Media>(&conn)
+        .unwrap();
+    HttpResponse::Ok().json(results)
+}
+
+#[delete("/media/{id}/")]
+async fn delete_media(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+) -> impl Responder {
+    use common::schema::{cards, decks, media};
+
+    let (media_id,) = path.into_inner();
+    let conn = pool.get().unwrap();
+    let owned = media::table
+        .inner_join(cards::table.inner_join(decks::table))
+        .filter(media::id.eq(media_id))
+        .filter(decks::user_id.eq(auth.get_user(&conn).id))
+        .select(media::id)
+        .load::<i32>(&conn)
+        .unwrap();
+    // The file itself goes with the next garbage collection, if nothing else uses it.
+    diesel::delete(media::table.filter(media::id.eq_any(owned)))
+        .execute(&conn)
+        .unwrap();
+
+    HttpResponse::Ok()
+}
+
+#[get("/media/{hash}")]
+async fn serve_media(
+    req: HttpRequest,
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    path: web::Path<(String,)>,
+) -> impl Responder {
+    use common::schema::{cards, decks, media};
+
+    let (hash,) = path.into_inner();
+    if !is_valid_hash(&hash) {
+        return HttpResponse::NotFound().finish();
+    }
+    let conn = pool.get().unwrap();
+    // Only for users with a card that has this file attached.
+    let content_type = media::table
+        .inner_join(cards::table.inner_join(decks::table))
+        .filter(media::hash.eq(&hash))
+        .filter(decks::user_id.eq(auth.get_user(&conn).id))
+        .select(media::content_type)
+        .first::<String>(&conn)
+        .optional()
+        .unwrap();
+
+    match content_type {
+        Some(content_type) => match NamedFile::open_async(settings.media.dir.join(&hash)).await {
+            Ok(file) => file
+                .set_content_type(content_type.parse().unwrap())
+                .into_response(&req),
+            Err(_) => HttpResponse::NotFound().finish(),
+        },
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[get("/cards/search/")]
+async fn search_cards(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    query: web::Query<CardReadQuery>,
+) -> impl Responder {
+    use common::schema::{cards, decks};
+
+    let per_page = query.per_page.unwrap_or(settings.per_page);
+    let tsquery = match to_tsquery_input(&query.search_term) {
+        Some(tsquery) => tsquery,
+        None => return HttpResponse::Ok().json(Page::<DeckCardMatch>::new(vec![], 0, false)),
+    };
+    let conn = pool.get().unwrap();
+    let page: Page<DeckCardMatch> = cards::table
+        .inner_join(decks::table)
+        .filter(decks::user_id.eq(auth.get_user(&conn).id))
+        .filter(matches(&tsquery))
+        .select((
+            decks::name,
+            (
+                cards::table::all_columns(),
+                headline("cards.front", &tsquery),
+                headline("cards.back", &tsquery),
+            ),
+        ))
+        .order_by((rank_desc(&tsquery), cards::id))
+        .paginate(query.page, per_page)
+        .load_and_count_pages(&conn)
+        .unwrap();
+
+    HttpResponse::Ok().json(page)
+}
+
+#[derive(QueryableByName)]
+struct SimilarPair {
+    #[sql_type = "Integer"]
+    first_id: i32,
+    #[sql_type = "Integer"]
+    second_id: i32,
+}
+
+// How close (`pg_trgm` similarity of the fronts, 0 to 1) two cards must be to get reported.
+const SIMILARITY_THRESHOLD: f32 = 0.6;
+
+#[get("/cards/duplicates/")]
+async fn read_duplicates(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    query: web::Query<DuplicatesQuery>,
+) -> impl Responder {
+    use common::schema::{cards, decks};
+
+    let conn = pool.get().unwrap();
+    let user_id = auth.get_user(&conn).id;
+
+    // `%` narrows down candidates through the trigram index before the exact threshold.
+    let similar = sql_query(
+        r#"
+        SELECT a.id AS first_id, b.id AS second_id
+        FROM cards a
+        JOIN decks da ON a.deck_id = da.id
+        JOIN cards b ON lower(a.front) % lower(b.front) AND a.id < b.id
+        JOIN decks db ON b.deck_id = db.id
+        WHERE
+            da.user_id = $1
+            AND db.user_id = $1
+            AND similarity(lower(a.front), lower(b.front)) >= $2
+            AND ($3 IS NULL OR a.deck_id = $3 OR b.deck_id = $3);
+    "#,
+    )
+    .bind::<Integer, _>(user_id)
+    .bind::<Float, _>(SIMILARITY_THRESHOLD)
+    .bind::<Nullable<Integer>, _>(query.deck_id)
+    .load::<SimilarPair>(&conn)
+    .unwrap();
+
+    let user_cards = cards::table
+        .inner_join(decks::table)
+        .filter(decks::user_id.eq(user_id))
+        .select((decks::name, cards::table::all_columns()))
+        .order_by(cards::id)
+        .load::<DeckCard>(&conn)
+        .unwrap();
+
+    let mut pairs: Vec<(i32, i32)> = similar
+        .iter()
+        .map(|pair| (pair.first_id, pair.second_id))
+        .collect();
+    let deck_of: HashMap<i32, i32> = user_cards
+        .iter()
+        .map(|deck_card| (deck_card.card.id, deck_card.card.deck_id))
+        .collect();
+    pairs.extend(
+        normalized_pairs(
+            user_cards
+                .iter()
+                .map(|deck_card| (deck_card.card.id, deck_card.card.front.as_str())),
+        )
+        .into_iter()
+        .filter(|(a, b)| match query.deck_id {
+            Some(deck_id) => deck_of[a] == deck_id || deck_of[b] == deck_id,
+            None => true,
+        }),
+    );
+
+    let mut by_id: HashMap<i32, DeckCard> = user_cards
+        .into_iter()
+        .map(|deck_card| (deck_card.card.id, deck_card))
+        .collect();
+    let clusters: Vec<DuplicateCluster> = cluster(&pairs)
+        .into_iter()
+        .map(|ids| DuplicateCluster {
+            cards: ids.iter().filter_map(|id| by_id.remove(id)).collect(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(clusters)
+}
+
 #[post("/cards/{id}/feedback/")]
 async fn post_feedback(
-    _auth: Authenticated, // TODO
+    auth: Authenticated,
     pool: web::Data<DbPool>,
     path: web::Path<(i32,)>,
-    feedback: String,
+    payload: web::Json<FeedbackPayload>,
 ) -> impl Responder {
-    use common::schema::cards::dsl::*;
+    use common::schema::{cards, decks};
 
     let conn = pool.get().unwrap();
     let card_id = path.into_inner().0;
-    // TODO only need to select `revision_weight` really
-    // TODO probably best to assert this is from a deck of the right user.
-    let mut card = cards.filter(id.eq(card_id)).first::<Card>(&conn).unwrap();
-    add_feedback(&conn, &mut card, &feedback);
+    let card = cards::table
+        .inner_join(decks::table)
+        .filter(cards::id.eq(card_id))
+        .filter(decks::user_id.eq(auth.get_user(&conn).id))
+        .select((cards::table::all_columns(), decks::table::all_columns()))
+        .first::<(Card, Deck)>(&conn)
+        .optional()
+        .unwrap();
 
-    HttpResponse::Ok().body("ok")
+    if let Some((card, deck)) = card {
+        add_feedback(&conn, &card, &deck, &payload).unwrap();
+        HttpResponse::Ok().body("ok")
+    } else {
+        HttpResponse::NotFound().finish()
+    }
 }
 
 #[get("{id}/revision/")]
@@ -300,39 +908,202 @@ async fn get_revision_cards(
     auth: Authenticated,
     pool: web::Data<DbPool>,
     path: web::Path<(i32,)>,
+    query: web::Query<RevisionQuery>,
 ) -> impl Responder {
-    use common::schema::{cards, decks};
+    use common::schema::decks;
 
     let (deck_id,) = path.into_inner();
     let conn = pool.get().unwrap();
-    let (revision_length, flip_mode) = decks::table
+    let deck = decks::table
         .filter(decks::id.eq(deck_id))
         .filter(decks::user_id.eq(auth.get_user(&conn).id))
-        .select((decks::revision_length, decks::flip_mode))
-        .first::<(i16, FlipMode)>(&conn)
+        .first::<Deck>(&conn)
         .unwrap();
-    let ids = cards::table
-        // TODO it's not really that great to get all of Deck when
-        // just want to know that it's a deck of the right `user_id`.
-        .inner_join(decks::table)
-        .filter(cards::deck_id.eq(deck_id))
+    let now = Utc::now().naive_utc();
+    let mut rng = rand::thread_rng();
+    let draws = if query.cram {
+        // Cramming goes through the whole selection in random order, sessions are just as long.
+        let mut ids = cram_card_ids(&conn, deck.id, &query, now).unwrap();
+        ids.shuffle(&mut rng);
+        ids.truncate(max(deck.revision_length, 0) as usize);
+        ids.into_iter().map(|id| (id, rng.gen())).collect()
+    } else {
+        let candidates = load_candidates(&conn, &deck).unwrap();
+        order_candidates(candidates, &deck, now, &mut rng)
+    };
+
+    HttpResponse::Ok().json(load_revision_cards(&conn, &draws, &[deck]))
+}
+
+#[get("/revision/")]
+async fn get_combined_revision_cards(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    query: web::Query<CombinedRevisionQuery>,
+) -> impl Responder {
+    use common::schema::decks;
+
+    let tz = match query.tz.parse::<Tz>() {
+        Ok(tz) => tz,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+    let conn = pool.get().unwrap();
+    let deck_ids = query.deck_id_list();
+    let mut deck_query = decks::table
         .filter(decks::user_id.eq(auth.get_user(&conn).id))
-        .order_by(sql::<i32>("-random() * revision_weight"))
-        .select(cards::id)
-        .limit(revision_length.into())
-        .load::<i32>(&conn)
+        .order_by(decks::id)
+        .into_boxed();
+    if !deck_ids.is_empty() {
+        deck_query = deck_query.filter(decks::id.eq_any(deck_ids));
+    }
+    let decks = deck_query.load::<Deck>(&conn).unwrap();
+
+    // Each deck picks its share by its own settings, out of what's due by the end of the day.
+    let now = Utc::now().naive_utc();
+    let today = local_date(&now, &tz);
+    let mut rng = rand::thread_rng();
+    let queues = decks
+        .iter()
+        .map(|deck| {
+            let mut candidates = load_candidates(&conn, deck).unwrap();
+            candidates.retain(|c| c.due.is_none_or(|due| local_date(&due, &tz) <= today));
+            order_candidates(candidates, deck, now, &mut rng)
+        })
+        .collect();
+    let draws = mix_queues(queues);
+
+    HttpResponse::Ok().json(load_revision_cards(&conn, &draws, &decks))
+}
+
+fn load_revision_cards(
+    conn: &PgConnection,
+    draws: &[(i32, bool)],
+    decks: &[Deck],
+) -> Vec<RevisionCard> {
+    // The cards drawn for a session, in that order, ready for revision.
+    use common::schema::cards;
+
+    let ids: Vec<i32> = draws.iter().map(|(id, _)| *id).collect();
+    let mut results = cards::table
+        .filter(cards::id.eq_any(&ids))
+        .load::<Card>(conn)
         .unwrap();
+    results.sort_by_key(|card| ids.iter().position(|&id| id == card.id));
+    let media = Media::belonging_to(&results)
+        .load::<Media>(conn)
+        .unwrap()
+        .grouped_by(&results);
+
+    results
+        .iter()
+        .zip(media)
+        .map(|(card, media)| {
+            let deck = decks.iter().find(|deck| deck.id == card.deck_id).unwrap();
+            let reverse = draws.contains(&(card.id, true));
+            make_revision_card(card, &media, deck, reverse)
+        })
+        .collect()
+}
+
+fn load_stats(conn: &PgConnection, user_id: i32, deck_id: Option<i32>, tz: &Tz) -> Stats {
+    use common::schema::{cards, decks, reviews};
 
-    let results = cards::table
-        .filter(cards::id.eq_any(ids))
-        .order_by(sql::<i32>("random()"))
-        .load::<Card>(&conn)
+    let mut review_query = reviews::table
+        .inner_join(cards::table.inner_join(decks::table))
+        .filter(decks::user_id.eq(user_id))
+        .select(reviews::table::all_columns())
+        .into_boxed();
+    let mut card_query = cards::table
+        .inner_join(decks::table)
+        .filter(decks::user_id.eq(user_id))
+        .select((cards::created, cards::due))
+        .into_boxed();
+    if let Some(deck_id) = deck_id {
+        review_query = review_query.filter(cards::deck_id.eq(deck_id));
+        card_query = card_query.filter(cards::deck_id.eq(deck_id));
+    }
+
+    let reviews = review_query.load::<Review>(conn).unwrap();
+    let cards = card_query
+        .load::<(NaiveDateTime, Option<NaiveDateTime>)>(conn)
         .unwrap();
+    let mut stats = compute_stats(&reviews, &cards, tz, Utc::now().naive_utc());
 
-    let revision_cards: Vec<RevisionCard> = results
+    // Decks and fronts of the reviewed cards, for times per deck and per card.
+    let card_ids: Vec<i32> = reviews.iter().map(|r| r.card_id).collect();
+    let labels: HashMap<i32, (i32, String, String)> = cards::table
+        .inner_join(decks::table)
+        .filter(cards::id.eq_any(card_ids))
+        .select((cards::id, (decks::id, decks::name, cards::front)))
+        .load::<(i32, (i32, String, String))>(conn)
+        .unwrap()
         .into_iter()
-        .map(|card| make_revision_card(&card, flip_mode))
         .collect();
+    stats.deck_times = answer_times(&reviews, |r| {
+        let (deck_id, name, _) = labels.get(&r.card_id)?;
+        Some((*deck_id, name.as_str()))
+    });
+    stats.card_times = answer_times(&reviews, |r| {
+        let (_, _, front) = labels.get(&r.card_id)?;
+        Some((r.card_id, front.as_str()))
+    });
+    stats.card_times.truncate(SLOWEST_CARDS);
+    stats
+}
+
+#[get("/stats/")]
+async fn read_stats(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    query: web::Query<TimezoneQuery>,
+) -> impl Responder {
+    let tz = match query.tz.parse::<Tz>() {
+        Ok(tz) => tz,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let conn = pool.get().unwrap();
+    let stats = load_stats(&conn, auth.get_user(&conn).id, None, &tz);
+
+    HttpResponse::Ok().json(stats)
+}
+
+#[get("{id}/stats/")]
+async fn read_deck_stats(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    query: web::Query<TimezoneQuery>,
+) -> impl Responder {
+    let (deck_id,) = path.into_inner();
+    let tz = match query.tz.parse::<Tz>() {
+        Ok(tz) => tz,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let conn = pool.get().unwrap();
+    let stats = load_stats(&conn, auth.get_user(&conn).id, Some(deck_id), &tz);
+
+    HttpResponse::Ok().json(stats)
+}
+
+#[get("/stats/activity/")]
+async fn read_activity(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    query: web::Query<TimezoneQuery>,
+) -> impl Responder {
+    use common::schema::{cards, decks, reviews};
+
+    let tz = match query.tz.parse::<Tz>() {
+        Ok(tz) => tz,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let conn = pool.get().unwrap();
+    let reviewed = reviews::table
+        .inner_join(cards::table.inner_join(decks::table))
+        .filter(decks::user_id.eq(auth.get_user(&conn).id))
+        .select(reviews::reviewed)
+        .load::<NaiveDateTime>(&conn)
+        .unwrap();
 
-    HttpResponse::Ok().json(revision_cards)
+    HttpResponse::Ok().json(compute_activity(&reviewed, &tz, Utc::now().naive_utc()))
 }
@@ -1,25 +1,43 @@
 use std::rc::Rc;
+use std::time::Instant;
 
 use actix_files::NamedFile;
 use actix_identity::{Identity, RequestIdentity};
 use actix_web::{
+    cookie::{Cookie, SameSite},
+    delete,
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error, get,
-    http::{header, StatusCode},
+    error::{self, ResponseError},
+    get,
+    http::{header, Method, StatusCode},
     middleware::ErrorHandlerResponse,
-    post, web, Error, FromRequest, HttpMessage, HttpResponse, Responder,
+    post, web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder,
 };
-use bcrypt::verify;
+use bcrypt::{hash, verify, DEFAULT_COST};
 use chrono::{Duration, Utc};
-use common::models::{Session, User};
+use common::models::{
+    ApiToken, ApiTokenInfo, AuthInfo, CreatedApiToken, EmailPayload, LoginOptions, LoginOutcome,
+    NewApiToken, PasswordChangePayload, PasswordResetPayload, PasswordResetRequest, RecoveryCodes,
+    Session, SessionInfo, TotpCodePayload, TotpEnrollment, TotpLoginPayload, TotpStatus, User,
+};
+use common::TokenScope;
 use derive_more::{Display, Error};
+use diesel::dsl::{exists, select};
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
 use futures::future::{ready, Ready};
 use futures_util::future::{FutureExt, LocalBoxFuture};
 use rand::{distributions::Alphanumeric, Rng};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
+use crate::csrf::constant_time_eq;
 use crate::db::DbPool;
+use crate::mail::{self, Email, Mailer};
+use crate::oidc;
+use crate::rate_limit::LoginLimiter;
+use crate::settings::{RegistrationMode, Settings};
+use crate::totp;
 
 #[derive(Debug, Display, Error)]
 #[display(fmt = "auth error")]
@@ -31,8 +49,30 @@ impl error::ResponseError for AuthError {
     }
 }
 
+// Plenty to never be guessed, for sessions and API tokens alike.
+const TOKEN_LENGTH: usize = 40;
+// API tokens start with this, to tell them apart in scripts and leaks.
+const API_TOKEN_PREFIX: &str = "anq_";
+// How long the second step of a 2FA login can wait, and how many wrong codes it takes.
+const LOGIN_CHALLENGE_MINUTES: i64 = 5;
+const MAX_LOGIN_CHALLENGE_ATTEMPTS: i16 = 5;
+// How long a password reset link works.
+const PASSWORD_RESET_MINUTES: i64 = 60;
+// How long the OpenID Connect provider gets to send users back.
+const OIDC_LOGIN_MINUTES: i64 = 10;
+// Ties the provider's redirect back to the browser that went there.
+const OIDC_STATE_COOKIE: &str = "oidc-state";
+// Sessions' `last_seen` gets written when at least this old rather than on every request.
+const LAST_SEEN_RESOLUTION_MINUTES: i64 = 5;
+
 pub struct AuthData {
-    pub session: Session,
+    pub user_id: i32,
+    // The cookie's session, none for API tokens.
+    pub session: Option<Session>,
+    // Read-write for sessions.
+    pub scope: TokenScope,
+    // The only deck an API token may touch, if restricted to one.
+    pub deck_id: Option<i32>,
 }
 pub type AuthenticationInfo = Rc<AuthData>;
 
@@ -56,16 +96,36 @@ where
         let srv = self.service.clone();
 
         async move {
-            let token = req.get_identity();
-            if let Some(token) = token {
+            let pool = req.app_data::<web::Data<DbPool>>().unwrap().clone();
+            if let Some(token) = bearer_token(&req) {
+                // Scripts, which don't bother with cookies.
+                let conn = pool.get().unwrap();
+                if let Some(api_token) = get_api_token(&conn, &token) {
+                    if !token_allows(&api_token, req.method(), req.path()) {
+                        return Err(error::ErrorForbidden("Jeton sans accès à cette ressource"));
+                    }
+                    req.extensions_mut()
+                        .insert::<AuthenticationInfo>(Rc::new(AuthData {
+                            user_id: api_token.user_id,
+                            session: None,
+                            scope: api_token.scope,
+                            deck_id: api_token.deck_id,
+                        }));
+                }
+            } else if let Some(token) = req.get_identity() {
                 // See if we can match it to a user.
-                let pool = req.app_data::<web::Data<DbPool>>().unwrap();
+                let settings = req.app_data::<web::Data<Settings>>().unwrap();
                 let conn = pool.get().unwrap();
-                let session = get_current_session(&conn, &token);
+                let session = get_current_session(&conn, &token, settings.session_hours);
 
                 if let Some(session) = session {
                     req.extensions_mut()
-                        .insert::<AuthenticationInfo>(Rc::new(AuthData { session }));
+                        .insert::<AuthenticationInfo>(Rc::new(AuthData {
+                            user_id: session.user_id,
+                            session: Some(session),
+                            scope: TokenScope::ReadWrite,
+                            deck_id: None,
+                        }));
                 }
             }
 
@@ -117,12 +177,28 @@ impl Authenticated {
     // TODO `user` should maybe just live on AuthenticationInfo
     pub fn get_user(&self, conn: &PgConnection) -> User {
         use common::schema::users::dsl::*;
-        let session = &self.0.session;
         users
-            .filter(id.eq(session.user_id))
+            .filter(id.eq(self.0.user_id))
             .first::<User>(conn)
             .unwrap()
     }
+
+    pub fn session_id(&self) -> Option<i32> {
+        self.0.session.as_ref().map(|session| session.id)
+    }
+
+    pub fn scope(&self) -> TokenScope {
+        self.0.scope
+    }
+
+    pub fn deck_id(&self) -> Option<i32> {
+        self.0.deck_id
+    }
+
+    pub fn allows_deck(&self, deck_id: i32) -> bool {
+        // For handlers reaching past the deck in their URL, which `token_allows` can't see.
+        self.0.deck_id.is_none_or(|allowed| allowed == deck_id)
+    }
 }
 
 impl FromRequest for Authenticated {
@@ -152,6 +228,10 @@ impl std::ops::Deref for Authenticated {
 
 pub fn redirect_on_autherror<B, E>(res: ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, E> {
     // Feed me to ErrorHandlers to redirect to /login/ on 401.
+    // Scripts with a bad API token get the 401 as is, there's no one to log in.
+    if res.request().headers().contains_key(header::AUTHORIZATION) {
+        return Ok(ErrorHandlerResponse::Response(res.map_into_left_body()));
+    }
     let redirect = HttpResponse::SeeOther()
         .insert_header((header::LOCATION, "/login/"))
         .finish();
@@ -175,73 +255,1475 @@ async fn login_get() -> impl Responder {
 
 #[post("/login/")]
 async fn login(
+    req: HttpRequest,
     req_id: Identity,
     pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    limiter: web::Data<LoginLimiter>,
     form: web::Json<LoginFormData>,
 ) -> impl Responder {
     use common::schema::users::dsl::*;
 
+    let ip = limiter.client_ip(req.peer_addr(), &req.connection_info());
+    if let Err(e) = limiter.check_username(&ip, &form.username, Instant::now()) {
+        log::warn!(
+            "login for `{}` from {} refused, too many failures",
+            form.username,
+            ip
+        );
+        return e.error_response();
+    }
+
     let conn = pool.get().unwrap();
     let user = users
         .filter(username.eq(&form.username))
-        .first::<User>(&conn);
+        .first::<User>(&conn)
+        .optional()
+        .unwrap();
+    // Unknown usernames take as long as wrong passwords, so timing doesn't tell them apart.
+    let valid = match &user {
+        Some(user) => verify(&form.password, &user.password).unwrap(),
+        None => {
+            hash(&form.password, DEFAULT_COST).unwrap();
+            false
+        }
+    };
+    let user = match user {
+        Some(user) if valid => user,
+        _ => {
+            log::warn!("failed login for `{}` from {}", form.username, ip);
+            if let Some(lockout) = limiter.record_failure(&ip, &form.username, Instant::now()) {
+                log::warn!(
+                    "`{}` locked out from {} for {}s",
+                    form.username,
+                    ip,
+                    lockout.as_secs()
+                );
+            }
+            req_id.forget();
+            return HttpResponse::Forbidden().body("Nom d'utilisateur ou mot de passe invalide");
+        }
+    };
+    limiter.record_success(&ip, &form.username);
 
-    let err_message;
-    if let Ok(user) = user {
-        if verify(&form.password, &user.password).unwrap() {
-            let session = new_session(&user, &conn);
-            req_id.remember(session.token);
-            return HttpResponse::Ok().finish();
-        } else {
-            err_message = "Mot de passe invalide".to_string();
+    // With 2FA on, the session waits for `login_totp`.
+    if user.totp_enabled {
+        let challenge = new_login_challenge(&conn, &user);
+        return HttpResponse::Ok().json(LoginOutcome::TotpRequired { challenge });
+    }
+    log::info!("`{}` logged in from {}", user.username, ip);
+    let token = new_session(&conn, &user, &user_agent(&req), settings.session_hours);
+    req_id.remember(token);
+    HttpResponse::Ok().json(LoginOutcome::LoggedIn)
+}
+
+#[post("/login/totp/")]
+async fn login_totp(
+    req: HttpRequest,
+    req_id: Identity,
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    limiter: web::Data<LoginLimiter>,
+    payload: web::Json<TotpLoginPayload>,
+) -> impl Responder {
+    // Second step of logging in with 2FA, with the challenge `login` handed out.
+    use common::schema::{login_challenges, users};
+
+    let conn = pool.get().unwrap();
+    let min_ts = Utc::now().naive_utc() - Duration::minutes(LOGIN_CHALLENGE_MINUTES);
+    let challenge = login_challenges::table
+        .filter(login_challenges::token_hash.eq(hash_token(&payload.challenge)))
+        .filter(login_challenges::created.gt(min_ts))
+        .filter(login_challenges::failed_attempts.lt(MAX_LOGIN_CHALLENGE_ATTEMPTS))
+        .select((login_challenges::id, login_challenges::user_id))
+        .first::<(i32, i32)>(&conn)
+        .optional()
+        .unwrap();
+    let (challenge_id, challenge_user_id) = match challenge {
+        Some(challenge) => challenge,
+        None => {
+            return HttpResponse::Forbidden().body("Connexion expirée, veuillez recommencer");
         }
-    } else {
-        err_message = "Nom d'utilisateur invalide".to_string();
+    };
+    let user = users::table
+        .find(challenge_user_id)
+        .first::<User>(&conn)
+        .unwrap();
+
+    let challenge_target = login_challenges::table.find(challenge_id);
+    if !check_second_factor(&conn, &user, &payload.code) {
+        log::warn!(
+            "failed 2FA code for `{}` from {}",
+            user.username,
+            limiter.client_ip(req.peer_addr(), &req.connection_info()),
+        );
+        diesel::update(challenge_target)
+            .set(login_challenges::failed_attempts.eq(login_challenges::failed_attempts + 1))
+            .execute(&conn)
+            .unwrap();
+        return HttpResponse::Forbidden().body("Code invalide");
     }
-    req_id.forget();
-    HttpResponse::Forbidden().body(err_message)
+    diesel::delete(challenge_target).execute(&conn).unwrap();
+    let token = new_session(&conn, &user, &user_agent(&req), settings.session_hours);
+    req_id.remember(token);
+    HttpResponse::Ok().json(LoginOutcome::LoggedIn)
+}
+
+#[get("/login/options/")]
+async fn read_login_options(settings: web::Data<Settings>) -> impl Responder {
+    HttpResponse::Ok().json(LoginOptions {
+        oidc_label: settings.oidc.as_ref().map(|oidc| oidc.label.clone()),
+    })
+}
+
+#[get("/login/oidc/")]
+async fn oidc_login(pool: web::Data<DbPool>, settings: web::Data<Settings>) -> impl Responder {
+    // Off to the provider, with what's needed to check its answer put aside.
+    use common::schema::oidc_logins::dsl::*;
+
+    let config = match &settings.oidc {
+        Some(config) => config,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let request = match oidc::begin(config, &settings.public_url).await {
+        Ok(request) => request,
+        Err(e) => {
+            log::error!("can't reach the OIDC provider: {}", e);
+            return HttpResponse::BadGateway().body("Fournisseur d'identité injoignable");
+        }
+    };
+
+    let conn = pool.get().unwrap();
+    let now = Utc::now().naive_utc();
+    diesel::delete(oidc_logins.filter(created.le(now - Duration::minutes(OIDC_LOGIN_MINUTES))))
+        .execute(&conn)
+        .unwrap();
+    diesel::insert_into(oidc_logins)
+        .values((
+            state_hash.eq(hash_token(&request.state)),
+            nonce.eq(&request.nonce),
+            pkce_verifier.eq(&request.pkce_verifier),
+            created.eq(now),
+        ))
+        .execute(&conn)
+        .unwrap();
+
+    // Lax, or it wouldn't come along on the provider's redirect back.
+    let state_cookie = Cookie::build(OIDC_STATE_COOKIE, request.state)
+        .path("/login/oidc/")
+        .secure(settings.cookies.secure)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(actix_web::cookie::time::Duration::minutes(
+            OIDC_LOGIN_MINUTES,
+        ))
+        .finish();
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, request.url))
+        .cookie(state_cookie)
+        .finish()
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    state: String,
+    code: Option<String>,
+    error: Option<String>,
+}
+
+#[get("/login/oidc/callback/")]
+async fn oidc_callback(
+    req: HttpRequest,
+    req_id: Identity,
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    query: web::Query<OidcCallbackQuery>,
+) -> impl Responder {
+    use common::schema::oidc_logins;
+
+    let config = match &settings.oidc {
+        Some(config) => config,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    // Someone else's login finished in this browser would log it in as them.
+    let same_browser = req
+        .cookie(OIDC_STATE_COOKIE)
+        .is_some_and(|cookie| constant_time_eq(cookie.value().as_bytes(), query.state.as_bytes()));
+    if !same_browser {
+        return HttpResponse::Forbidden().body("Connexion expirée, veuillez recommencer");
+    }
+
+    let conn = pool.get().unwrap();
+    let min_ts = Utc::now().naive_utc() - Duration::minutes(OIDC_LOGIN_MINUTES);
+    let pending = diesel::delete(
+        oidc_logins::table
+            .filter(oidc_logins::state_hash.eq(hash_token(&query.state)))
+            .filter(oidc_logins::created.gt(min_ts)),
+    )
+    .returning((oidc_logins::nonce, oidc_logins::pkce_verifier))
+    .get_result::<(String, String)>(&conn)
+    .optional()
+    .unwrap();
+    let (pending_nonce, pending_verifier) = match pending {
+        Some(pending) => pending,
+        None => return HttpResponse::Forbidden().body("Connexion expirée, veuillez recommencer"),
+    };
+    let code = match (&query.code, &query.error) {
+        (Some(code), None) => code,
+        _ => {
+            log::warn!("OIDC provider refused a login: {:?}", query.error);
+            return HttpResponse::Forbidden()
+                .body("Connexion refusée par le fournisseur d'identité");
+        }
+    };
+
+    let identity = match oidc::finish(
+        config,
+        &settings.public_url,
+        code,
+        &pending_nonce,
+        &pending_verifier,
+    )
+    .await
+    {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::warn!("OIDC login failed: {}", e);
+            return HttpResponse::Forbidden()
+                .body("Connexion refusée par le fournisseur d'identité");
+        }
+    };
+    let user = match find_oidc_user(&conn, &identity) {
+        Some(user) => user,
+        None if settings.registration == RegistrationMode::Open => {
+            provision_oidc_user(&conn, &identity)
+        }
+        None => {
+            log::warn!(
+                "OIDC login for unknown subject `{}` of `{}`",
+                identity.subject,
+                identity.issuer
+            );
+            return HttpResponse::Forbidden().body("Aucun compte n'est lié à cette identité");
+        }
+    };
+
+    log::info!("`{}` logged in through OIDC", user.username);
+    let token = new_session(&conn, &user, &user_agent(&req), settings.session_hours);
+    req_id.remember(token);
+    let mut state_cookie = Cookie::build(OIDC_STATE_COOKIE, "")
+        .path("/login/oidc/")
+        .finish();
+    state_cookie.make_removal();
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, "/app/decks/"))
+        .cookie(state_cookie)
+        .finish()
+}
+
+fn find_oidc_user(conn: &PgConnection, identity: &oidc::OidcIdentity) -> Option<User> {
+    use common::schema::users::dsl::*;
+
+    users
+        .filter(oidc_issuer.eq(&identity.issuer))
+        .filter(oidc_subject.eq(&identity.subject))
+        .first::<User>(conn)
+        .optional()
+        .unwrap()
+}
+
+fn provision_oidc_user(conn: &PgConnection, identity: &oidc::OidcIdentity) -> User {
+    // Named after what the provider calls them, numbered while that's taken.
+    use common::schema::users::dsl::*;
+
+    let base = identity
+        .preferred_username
+        .as_deref()
+        .or(identity.email.as_deref())
+        .unwrap_or(&identity.subject)
+        .to_string();
+    // Never typed in, only there so the password column isn't empty.
+    let hashed = hash(random_token(), DEFAULT_COST).unwrap();
+    (1..)
+        .find_map(|n| {
+            let name = match n {
+                1 => base.clone(),
+                n => format!("{}-{}", base, n),
+            };
+            let created = diesel::insert_into(users)
+                .values((
+                    username.eq(&name),
+                    password.eq(&hashed),
+                    oidc_issuer.eq(&identity.issuer),
+                    oidc_subject.eq(&identity.subject),
+                ))
+                .on_conflict_do_nothing()
+                .get_result::<User>(conn)
+                .optional()
+                .unwrap();
+            if created.is_some() {
+                log::info!(
+                    "created `{}` for OIDC subject `{}` of `{}`",
+                    name,
+                    identity.subject,
+                    identity.issuer
+                );
+                return created;
+            }
+            // The name is taken, unless this same login just went through alongside.
+            find_oidc_user(conn, identity)
+        })
+        .unwrap()
 }
 
 #[get("/logout/")]
-async fn logout(id: Identity) -> impl Responder {
-    id.forget();
+async fn logout(req_id: Identity, pool: web::Data<DbPool>) -> impl Responder {
+    use common::schema::sessions::dsl::*;
+
+    // Over for good, not just on this browser.
+    if let Some(token) = req_id.identity() {
+        let conn = pool.get().unwrap();
+        diesel::delete(sessions.filter(token_hash.eq(hash_token(&token))))
+            .execute(&conn)
+            .unwrap();
+    }
+    req_id.forget();
     HttpResponse::SeeOther()
         .insert_header((header::LOCATION, "/login/"))
         .finish()
 }
 
-fn get_current_session(conn: &PgConnection, try_token: &str) -> Option<Session> {
+#[get("/reset-password/")]
+async fn reset_password_get() -> impl Responder {
+    // Same as `login_get`.
+    NamedFile::open_async("./frontend/dist/index.html").await
+}
+
+#[post("/password-reset/")]
+async fn request_password_reset(
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+    mailer: web::Data<dyn Mailer>,
+    payload: web::Json<PasswordResetRequest>,
+) -> impl Responder {
+    // Answers the same whether there's such an account or not, so it can't be used to find out.
+    use common::schema::{password_resets, users};
+
+    let conn = pool.get().unwrap();
+    let username_or_email = payload.login.trim();
+    let user = users::table
+        .filter(
+            users::username
+                .eq(username_or_email)
+                .or(users::email.eq(username_or_email)),
+        )
+        .first::<User>(&conn)
+        .optional()
+        .unwrap();
+    let user = match user {
+        Some(user) if user.email.is_some() => user,
+        _ => return HttpResponse::Ok().finish(),
+    };
+
+    // Only the latest link works, and old ones from everyone get cleared out.
+    let now = Utc::now().naive_utc();
+    diesel::delete(
+        password_resets::table.filter(
+            password_resets::user_id
+                .eq(user.id)
+                .or(password_resets::created.le(now - Duration::minutes(PASSWORD_RESET_MINUTES))),
+        ),
+    )
+    .execute(&conn)
+    .unwrap();
+    let token = random_token();
+    diesel::insert_into(password_resets::table)
+        .values((
+            password_resets::user_id.eq(user.id),
+            password_resets::token_hash.eq(hash_token(&token)),
+            password_resets::created.eq(now),
+        ))
+        .execute(&conn)
+        .unwrap();
+
+    let email = Email {
+        to: user.email.clone().unwrap(),
+        subject: "Réinitialisation du mot de passe".to_string(),
+        body: format!(
+            "Bonjour {},\n\n\
+            Pour choisir un nouveau mot de passe, suivez ce lien dans les {} minutes :\n\n\
+            {}/reset-password/?token={}\n\n\
+            Si vous n'avez rien demandé, ignorez simplement ce message.\n",
+            user.username,
+            PASSWORD_RESET_MINUTES,
+            settings.public_url.trim_end_matches('/'),
+            token,
+        ),
+    };
+    let mailer = mailer.clone();
+    match web::block(move || mailer.send(&email)).await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => log::error!("can't send password reset email: {}", e),
+        Err(e) => log::error!("can't send password reset email: {}", e),
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[post("/password-reset/confirm/")]
+async fn reset_password(
+    pool: web::Data<DbPool>,
+    payload: web::Json<PasswordResetPayload>,
+) -> impl Responder {
+    use common::schema::password_resets;
+
+    if payload.password.is_empty() {
+        return HttpResponse::BadRequest().body("Mot de passe vide");
+    }
+    let conn = pool.get().unwrap();
+    let min_ts = Utc::now().naive_utc() - Duration::minutes(PASSWORD_RESET_MINUTES);
+    let reset_user_id = password_resets::table
+        .filter(password_resets::token_hash.eq(hash_token(&payload.token)))
+        .filter(password_resets::created.gt(min_ts))
+        .select(password_resets::user_id)
+        .first::<i32>(&conn)
+        .optional()
+        .unwrap();
+    match reset_user_id {
+        Some(reset_user_id) => {
+            set_password(&conn, reset_user_id, &payload.password, None);
+            HttpResponse::Ok().finish()
+        }
+        None => HttpResponse::Forbidden().body("Lien expiré ou déjà utilisé"),
+    }
+}
+
+fn set_password(conn: &PgConnection, user_id: i32, new_password: &str, keep_session: Option<i32>) {
+    // Logs out everywhere but `keep_session`, and voids pending resets and 2FA logins.
+    use common::schema::{login_challenges, password_resets, sessions, users};
+
+    let hashed = hash(new_password, DEFAULT_COST).unwrap();
+    conn.transaction(|| {
+        diesel::update(users::table.find(user_id))
+            .set(users::password.eq(hashed))
+            .execute(conn)?;
+        diesel::delete(password_resets::table.filter(password_resets::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(login_challenges::table.filter(login_challenges::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(
+            sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::id.nullable().ne(keep_session)),
+        )
+        .execute(conn)
+    })
+    .unwrap();
+}
+
+#[get("/sessions/")]
+async fn read_sessions(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    settings: web::Data<Settings>,
+) -> impl Responder {
     use common::schema::sessions::dsl::*;
 
-    // TODO prolly could have this in config / env
-    let min_ts = Utc::now().naive_utc() - Duration::hours(36);
-    sessions
-        .filter(token.eq(try_token))
-        .filter(created.gt(min_ts))
-        .first::<Session>(conn)
-        .ok()
+    let conn = pool.get().unwrap();
+    let min_ts = Utc::now().naive_utc() - Duration::hours(settings.session_hours);
+    let infos: Vec<SessionInfo> = sessions
+        .filter(user_id.eq(auth.user_id))
+        .filter(last_seen.gt(min_ts))
+        .order_by(last_seen.desc())
+        .load::<Session>(&conn)
+        .unwrap()
+        .into_iter()
+        .map(|session| SessionInfo {
+            id: session.id,
+            device: device_label(&session.user_agent),
+            current: Some(session.id) == auth.session_id(),
+            user_agent: session.user_agent,
+            created: session.created,
+            last_seen: session.last_seen,
+        })
+        .collect();
+    HttpResponse::Ok().json(infos)
 }
 
-// TODO probably should return a Result
-fn new_session(user: &User, conn: &PgConnection) -> Session {
+#[delete("/sessions/{session_id}/")]
+async fn delete_session(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+) -> impl Responder {
     use common::schema::sessions::dsl::*;
 
-    diesel::delete(sessions.filter(user_id.eq(user.id)))
+    let (session_id,) = path.into_inner();
+    let conn = pool.get().unwrap();
+    let deleted = diesel::delete(
+        sessions
+            .filter(id.eq(session_id))
+            .filter(user_id.eq(auth.user_id)),
+    )
+    .execute(&conn)
+    .unwrap();
+    if deleted == 0 {
+        return HttpResponse::NotFound().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[delete("/sessions/")]
+async fn delete_other_sessions(auth: Authenticated, pool: web::Data<DbPool>) -> impl Responder {
+    // Log out everywhere but here.
+    use common::schema::sessions::dsl::*;
+
+    let conn = pool.get().unwrap();
+    diesel::delete(
+        sessions
+            .filter(user_id.eq(auth.user_id))
+            .filter(id.ne(auth.session_id().unwrap_or_default())),
+    )
+    .execute(&conn)
+    .unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[get("/me/")]
+async fn read_me(auth: Authenticated, pool: web::Data<DbPool>) -> impl Responder {
+    // Who's asking and what they may do, e.g. for scripts to check their token.
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    HttpResponse::Ok().json(AuthInfo {
+        username: user.username,
+        email: user.email,
+        scope: auth.scope(),
+        deck_id: auth.deck_id(),
+    })
+}
+
+#[post("/password/")]
+async fn change_password(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    payload: web::Json<PasswordChangePayload>,
+) -> impl Responder {
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    if payload.new_password.is_empty() {
+        return HttpResponse::BadRequest().body("Mot de passe vide");
+    }
+    if !verify(&payload.old_password, &user.password).unwrap() {
+        return HttpResponse::Forbidden().body("Mot de passe actuel incorrect");
+    }
+    set_password(&conn, user.id, &payload.new_password, auth.session_id());
+    HttpResponse::Ok().finish()
+}
+
+#[post("/email/")]
+async fn update_email(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    payload: web::Json<EmailPayload>,
+) -> impl Responder {
+    use common::schema::users::dsl::*;
+
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    let new_email = payload
+        .email
+        .as_deref()
+        .map(str::trim)
+        .filter(|new_email| !new_email.is_empty());
+    if let Some(new_email) = new_email {
+        if !mail::is_valid_address(new_email) {
+            return HttpResponse::BadRequest().body("Adresse e-mail invalide");
+        }
+    }
+    // Left to the unique constraint, a check beforehand could race another request.
+    let updated = diesel::update(&user)
+        .set(email.eq(new_email))
+        .execute(&conn);
+    if let Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) = updated {
+        return HttpResponse::Conflict().body("Adresse e-mail déjà utilisée");
+    }
+    updated.unwrap();
+    HttpResponse::Ok().finish()
+}
+
+#[get("/totp/")]
+async fn read_totp_status(auth: Authenticated, pool: web::Data<DbPool>) -> impl Responder {
+    use common::schema::recovery_codes;
+
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    let recovery_codes_left = recovery_codes::table
+        .filter(recovery_codes::user_id.eq(user.id))
+        .count()
+        .get_result(&conn)
+        .unwrap();
+    HttpResponse::Ok().json(TotpStatus {
+        enabled: user.totp_enabled,
+        recovery_codes_left,
+    })
+}
+
+#[post("/totp/enroll/")]
+async fn enroll_totp(auth: Authenticated, pool: web::Data<DbPool>) -> impl Responder {
+    // A fresh secret, which only takes effect once `confirm_totp` sees a code made with it.
+    use common::schema::users;
+
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    if user.totp_enabled {
+        return HttpResponse::Conflict().body("Déjà activée");
+    }
+    let secret = totp::generate_secret();
+    diesel::update(users::table.find(user.id))
+        .set(users::totp_secret.eq(totp::base32_encode(&secret)))
+        .execute(&conn)
+        .unwrap();
+    let uri = totp::otpauth_uri(&secret, &user.username);
+    HttpResponse::Ok().json(TotpEnrollment {
+        secret: totp::base32_encode(&secret),
+        qr_svg: totp::qr_svg(&uri),
+        uri,
+    })
+}
+
+#[post("/totp/confirm/")]
+async fn confirm_totp(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    payload: web::Json<TotpCodePayload>,
+) -> impl Responder {
+    use common::schema::{recovery_codes, users};
+
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    let secret = match (&user.totp_secret, user.totp_enabled) {
+        (Some(secret), false) => totp::base32_decode(secret).unwrap(),
+        _ => return HttpResponse::Conflict().finish(),
+    };
+    let step = match totp::verify(&secret, &payload.code, unix_now(), None) {
+        Some(step) => step,
+        None => return HttpResponse::BadRequest().body("Code invalide"),
+    };
+
+    let codes = totp::generate_recovery_codes();
+    conn.transaction(|| {
+        diesel::update(users::table.find(user.id))
+            .set((users::totp_enabled.eq(true), users::totp_last_step.eq(step)))
+            .execute(&conn)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+            .execute(&conn)?;
+        let rows: Vec<_> = codes
+            .iter()
+            .map(|code| {
+                (
+                    recovery_codes::user_id.eq(user.id),
+                    recovery_codes::code_hash.eq(hash_token(&totp::normalize_recovery_code(code))),
+                )
+            })
+            .collect();
+        diesel::insert_into(recovery_codes::table)
+            .values(&rows)
+            .execute(&conn)
+    })
+    .unwrap();
+    HttpResponse::Ok().json(RecoveryCodes { codes })
+}
+
+#[post("/totp/disable/")]
+async fn disable_totp(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    payload: web::Json<TotpCodePayload>,
+) -> impl Responder {
+    // Takes a code as well, a forgotten open session shouldn't be enough.
+    use common::schema::{recovery_codes, users};
+
+    let conn = pool.get().unwrap();
+    let user = auth.get_user(&conn);
+    if !user.totp_enabled {
+        return HttpResponse::Conflict().finish();
+    }
+    if !check_second_factor(&conn, &user, &payload.code) {
+        return HttpResponse::BadRequest().body("Code invalide");
+    }
+    conn.transaction(|| {
+        diesel::update(users::table.find(user.id))
+            .set((
+                users::totp_secret.eq(None::<String>),
+                users::totp_enabled.eq(false),
+                users::totp_last_step.eq(None::<i64>),
+            ))
+            .execute(&conn)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+            .execute(&conn)
+    })
+    .unwrap();
+    HttpResponse::Ok().finish()
+}
+
+fn check_second_factor(conn: &PgConnection, user: &User, code: &str) -> bool {
+    // A code from the app, or else one of the recovery codes, which then gets used up.
+    use common::schema::{recovery_codes, users};
+
+    if let Some(secret) = user.totp_secret.as_deref().and_then(totp::base32_decode) {
+        if let Some(step) = totp::verify(&secret, code, unix_now(), user.totp_last_step) {
+            diesel::update(users::table.find(user.id))
+                .set(users::totp_last_step.eq(step))
+                .execute(conn)
+                .unwrap();
+            return true;
+        }
+    }
+    let used = diesel::delete(
+        recovery_codes::table
+            .filter(recovery_codes::user_id.eq(user.id))
+            .filter(recovery_codes::code_hash.eq(hash_token(&totp::normalize_recovery_code(code)))),
+    )
+    .execute(conn)
+    .unwrap();
+    used > 0
+}
+
+fn new_login_challenge(conn: &PgConnection, user: &User) -> String {
+    use common::schema::login_challenges::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    diesel::delete(
+        login_challenges.filter(created.le(now - Duration::minutes(LOGIN_CHALLENGE_MINUTES))),
+    )
+    .execute(conn)
+    .unwrap();
+    let challenge = random_token();
+    diesel::insert_into(login_challenges)
+        .values((
+            user_id.eq(user.id),
+            token_hash.eq(hash_token(&challenge)),
+            created.eq(now),
+        ))
         .execute(conn)
         .unwrap();
+    challenge
+}
+
+fn unix_now() -> u64 {
+    Utc::now().timestamp() as u64
+}
 
-    let tok: String = rand::thread_rng()
+#[get("/tokens/")]
+async fn read_api_tokens(auth: Authenticated, pool: web::Data<DbPool>) -> impl Responder {
+    let conn = pool.get().unwrap();
+    HttpResponse::Ok().json(load_api_token_infos(&conn, auth.user_id, None))
+}
+
+#[post("/tokens/")]
+async fn new_api_token(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    payload: web::Json<NewApiToken>,
+) -> impl Responder {
+    use common::schema::{api_tokens, decks};
+
+    if payload.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Nom vide");
+    }
+    let conn = pool.get().unwrap();
+    if let Some(deck_id) = payload.deck_id {
+        let deck_query = decks::table
+            .filter(decks::id.eq(deck_id))
+            .filter(decks::user_id.eq(auth.user_id));
+        if !select(exists(deck_query))
+            .get_result::<bool>(&conn)
+            .unwrap()
+        {
+            return HttpResponse::NotFound().finish();
+        }
+    }
+
+    let token = format!("{}{}", API_TOKEN_PREFIX, random_token());
+    let token_id: i32 = diesel::insert_into(api_tokens::table)
+        .values((
+            api_tokens::user_id.eq(auth.user_id),
+            api_tokens::name.eq(payload.name.trim()),
+            api_tokens::token_hash.eq(hash_token(&token)),
+            api_tokens::scope.eq(payload.scope),
+            api_tokens::deck_id.eq(payload.deck_id),
+            api_tokens::created.eq(Utc::now().naive_utc()),
+        ))
+        .returning(api_tokens::id)
+        .get_result(&conn)
+        .unwrap();
+    let info = load_api_token_infos(&conn, auth.user_id, Some(token_id)).remove(0);
+    HttpResponse::Ok().json(CreatedApiToken { token, info })
+}
+
+#[delete("/tokens/{token_id}/")]
+async fn delete_api_token(
+    auth: Authenticated,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+) -> impl Responder {
+    use common::schema::api_tokens::dsl::*;
+
+    let (token_id,) = path.into_inner();
+    let conn = pool.get().unwrap();
+    let deleted = diesel::delete(
+        api_tokens
+            .filter(id.eq(token_id))
+            .filter(user_id.eq(auth.user_id)),
+    )
+    .execute(&conn)
+    .unwrap();
+    if deleted == 0 {
+        return HttpResponse::NotFound().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+fn load_api_token_infos(
+    conn: &PgConnection,
+    owner_id: i32,
+    only_id: Option<i32>,
+) -> Vec<ApiTokenInfo> {
+    use common::schema::{api_tokens, decks};
+
+    let mut query = api_tokens::table
+        .left_join(decks::table)
+        .filter(api_tokens::user_id.eq(owner_id))
+        .select((api_tokens::all_columns, decks::name.nullable()))
+        .order_by(api_tokens::created.desc())
+        .into_boxed();
+    if let Some(only_id) = only_id {
+        query = query.filter(api_tokens::id.eq(only_id));
+    }
+    query
+        .load::<(ApiToken, Option<String>)>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|(api_token, deck_name)| ApiTokenInfo {
+            id: api_token.id,
+            name: api_token.name,
+            scope: api_token.scope,
+            deck_id: api_token.deck_id,
+            deck_name,
+            created: api_token.created,
+            last_used: api_token.last_used,
+        })
+        .collect()
+}
+
+pub fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    value
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+fn get_api_token(conn: &PgConnection, try_token: &str) -> Option<ApiToken> {
+    use common::schema::api_tokens::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let api_token = api_tokens
+        .filter(token_hash.eq(hash_token(try_token)))
+        .first::<ApiToken>(conn)
+        .ok()?;
+    let stale = api_token
+        .last_used
+        .is_none_or(|used| now - used > Duration::minutes(LAST_SEEN_RESOLUTION_MINUTES));
+    if stale {
+        return diesel::update(&api_token)
+            .set(last_used.eq(now))
+            .get_result(conn)
+            .ok();
+    }
+    Some(api_token)
+}
+
+fn token_allows(api_token: &ApiToken, method: &Method, path: &str) -> bool {
+    // Where API tokens can go: never account management, only reading unless scoped for
+    // writing, and only their deck's URLs when restricted to one.
+    let account_paths = [
+        "/api/sessions/",
+        "/api/tokens/",
+        "/api/totp/",
+        "/api/password/",
+        "/api/email/",
+    ];
+    if account_paths.iter().any(|prefix| path.starts_with(prefix)) {
+        return false;
+    }
+    if path == "/api/me/" {
+        return true;
+    }
+    if api_token.scope == TokenScope::Read && !matches!(*method, Method::GET | Method::HEAD) {
+        return false;
+    }
+    match api_token.deck_id {
+        Some(deck_id) => path.starts_with(&format!("/api/decks/{}/", deck_id)),
+        None => path.starts_with("/api/") || path.starts_with("/media/"),
+    }
+}
+
+fn random_token() -> String {
+    rand::thread_rng()
         .sample_iter(&Alphanumeric)
-        .take(25)
+        .take(TOKEN_LENGTH)
         .map(char::from)
-        .collect();
+        .collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    // Tokens are long and random, a plain hash is enough to keep a database leak from
+    // handing out working cookies.
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn user_agent(req: &HttpRequest) -> String {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn device_label(user_agent: &str) -> String {
+    // Rough guess of browser and OS, good enough to tell one's own devices apart.
+    // Order matters, e.g. Chrome claims to be Safari and Edge claims to be Chrome.
+    let browser = [
+        ("Firefox/", "Firefox"),
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .into_iter()
+    .find(|(marker, _)| user_agent.contains(marker))
+    .map(|(_, name)| name);
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iPhone"),
+        ("iPad", "iPad"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ]
+    .into_iter()
+    .find(|(marker, _)| user_agent.contains(marker))
+    .map(|(_, name)| name);
+    match (browser, os) {
+        (Some(browser), Some(os)) => format!("{} sur {}", browser, os),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Appareil inconnu".to_string(),
+    }
+}
+
+fn get_current_session(
+    conn: &PgConnection,
+    try_token: &str,
+    session_hours: i64,
+) -> Option<Session> {
+    use common::schema::sessions::dsl::*;
+
+    // Sliding expiry, sessions last `session_hours` past their latest use.
+    let now = Utc::now().naive_utc();
+    let session = sessions
+        .filter(token_hash.eq(hash_token(try_token)))
+        .filter(last_seen.gt(now - Duration::hours(session_hours)))
+        .first::<Session>(conn)
+        .ok()?;
+    if now - session.last_seen > Duration::minutes(LAST_SEEN_RESOLUTION_MINUTES) {
+        return diesel::update(&session)
+            .set(last_seen.eq(now))
+            .get_result(conn)
+            .ok();
+    }
+    Some(session)
+}
+
+// TODO probably should return a Result
+pub fn new_session(conn: &PgConnection, user: &User, agent: &str, session_hours: i64) -> String {
+    // Returns the token for the cookie, only its hash gets stored.
+    use common::schema::sessions::dsl::*;
+
+    // Other sessions stay, only the user's expired ones get cleared out.
+    let now = Utc::now().naive_utc();
+    diesel::delete(
+        sessions
+            .filter(user_id.eq(user.id))
+            .filter(last_seen.le(now - Duration::hours(session_hours))),
+    )
+    .execute(conn)
+    .unwrap();
+
+    let tok = random_token();
 
     diesel::insert_into(sessions)
         .values((
             user_id.eq(user.id),
-            token.eq(tok),
-            created.eq(Utc::now().naive_utc()),
+            token_hash.eq(hash_token(&tok)),
+            created.eq(now),
+            user_agent.eq(agent),
+            last_seen.eq(now),
         ))
-        .get_result(conn)
-        .unwrap()
+        .execute(conn)
+        .unwrap();
+    tok
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_identity::IdentityService;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::App;
+    use common::models::PasswordResetPayload;
+
+    use super::*;
+    use crate::mail::MailError;
+    use crate::test_utils::{self, OidcProvider};
+
+    const NEW_PASSWORD: &str = "nouveau mot de passe";
+    const COOKIE_KEY: &str = "a test cookie key, at least 32 bytes";
+
+    // Keeps what would have been sent.
+    #[derive(Default)]
+    struct StubMailer {
+        sent: Mutex<Vec<Email>>,
+    }
+
+    impl Mailer for StubMailer {
+        fn send(&self, email: &Email) -> Result<(), MailError> {
+            self.sent.lock().unwrap().push(Email {
+                to: email.to.clone(),
+                subject: email.subject.clone(),
+                body: email.body.clone(),
+            });
+            Ok(())
+        }
+    }
+
+    fn user_with_email(conn: &PgConnection) -> User {
+        let user = test_utils::new_user(conn);
+        diesel::update(&user)
+            .set(common::schema::users::email.eq(format!("{}@example.com", user.username)))
+            .get_result(conn)
+            .unwrap()
+    }
+
+    fn link_token(email: &Email, settings: &Settings) -> String {
+        let link = format!("{}/reset-password/?token=", settings.public_url);
+        let start = email.body.find(&link).expect("no reset link") + link.len();
+        email.body[start..]
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    fn confirm(token: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/password-reset/confirm/")
+            .set_json(PasswordResetPayload {
+                token: token.to_string(),
+                password: NEW_PASSWORD.to_string(),
+            })
+    }
+
+    fn request_reset(username_or_email: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/password-reset/")
+            .set_json(PasswordResetRequest {
+                login: username_or_email.to_string(),
+            })
+    }
+
+    fn password_of(conn: &PgConnection, user: &User) -> String {
+        use common::schema::users;
+
+        users::table
+            .find(user.id)
+            .select(users::password)
+            .first(conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn mails_a_reset_link_that_works_once() {
+        let settings = test_utils::settings();
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let user = user_with_email(&conn);
+        let mailer = Arc::new(StubMailer::default());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings.clone()))
+                .app_data(web::Data::from(mailer.clone() as Arc<dyn Mailer>))
+                .service(request_password_reset)
+                .service(reset_password),
+        )
+        .await;
+
+        // Nothing tells unknown accounts apart.
+        let res = call_service(&app, request_reset("personne").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(mailer.sent.lock().unwrap().is_empty());
+
+        let res = call_service(&app, request_reset(&user.username).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let email = mailer.sent.lock().unwrap().pop().unwrap();
+        assert_eq!(Some(&email.to), user.email.as_ref());
+        let token = link_token(&email, &settings);
+        assert_eq!(token.len(), TOKEN_LENGTH);
+
+        let res = call_service(&app, confirm(&token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(verify(NEW_PASSWORD, &password_of(&conn, &user)).unwrap());
+
+        let res = call_service(&app, confirm(&token).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn rejects_replaced_and_expired_reset_links() {
+        use common::schema::password_resets;
+
+        let settings = test_utils::settings();
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let user = user_with_email(&conn);
+        let mailer = Arc::new(StubMailer::default());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings.clone()))
+                .app_data(web::Data::from(mailer.clone() as Arc<dyn Mailer>))
+                .service(request_password_reset)
+                .service(reset_password),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let res = call_service(&app, request_reset(&user.username).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        let tokens: Vec<String> = mailer
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|email| link_token(email, &settings))
+            .collect();
+
+        // Only the latest link works.
+        let res = call_service(&app, confirm(&tokens[0]).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let expired = Utc::now().naive_utc() - Duration::minutes(PASSWORD_RESET_MINUTES + 1);
+        diesel::update(password_resets::table.filter(password_resets::user_id.eq(user.id)))
+            .set(password_resets::created.eq(expired))
+            .execute(&conn)
+            .unwrap();
+        let res = call_service(&app, confirm(&tokens[1]).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(verify(test_utils::PASSWORD, &password_of(&conn, &user)).unwrap());
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn refuses_an_email_address_already_in_use() {
+        let settings = test_utils::settings();
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let user = test_utils::new_user(&conn);
+        let other = user_with_email(&conn);
+        let policy = settings
+            .cookies
+            .identity_policy(&[COOKIE_KEY.as_bytes().to_vec()]);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings.clone()))
+                .app_data(web::Data::new(LoginLimiter::new(&settings.rate_limit)))
+                .wrap(AuthenticateMiddlewareFactory::default())
+                .wrap(IdentityService::new(policy))
+                .service(login)
+                .service(web::scope("/api").service(update_email)),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/login/")
+            .set_json(serde_json::json!({
+                "username": user.username,
+                "password": test_utils::PASSWORD,
+            }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res.response().cookies().next().unwrap().into_owned();
+
+        let update = |address: Option<String>| {
+            TestRequest::post()
+                .uri("/api/email/")
+                .cookie(cookie.clone())
+                .set_json(EmailPayload { email: address })
+                .to_request()
+        };
+        let res = call_service(&app, update(other.email.clone())).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let own = format!("{}@example.com", user.username);
+        let res = call_service(&app, update(Some(own))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    fn oidc_settings(provider: &OidcProvider, registration: RegistrationMode) -> Settings {
+        Settings {
+            oidc: Some(provider.config()),
+            registration,
+            ..test_utils::settings()
+        }
+    }
+
+    fn oidc_callback_request<B>(
+        provider: &OidcProvider,
+        sent_off: &ServiceResponse<B>,
+        subject: &str,
+        name: &str,
+    ) -> TestRequest {
+        // What the browser does between `oidc_login` and `oidc_callback`.
+        assert_eq!(sent_off.status(), StatusCode::FOUND);
+        let location = sent_off.headers().get(header::LOCATION).unwrap();
+        let redirect = provider.authorize(location.to_str().unwrap(), subject, name);
+        let state_cookie = sent_off
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == OIDC_STATE_COOKIE)
+            .unwrap()
+            .into_owned();
+        TestRequest::get()
+            .uri(&format!(
+                "{}?{}",
+                redirect.path(),
+                redirect.query().unwrap()
+            ))
+            .cookie(state_cookie)
+    }
+
+    fn oidc_users(conn: &PgConnection, provider: &OidcProvider, subject: &str) -> Vec<User> {
+        use common::schema::users;
+
+        users::table
+            .filter(users::oidc_issuer.eq(provider.issuer()))
+            .filter(users::oidc_subject.eq(subject))
+            .load(conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn provisions_oidc_users_when_registration_is_open() {
+        let provider = OidcProvider::start().await;
+        let settings = oidc_settings(&provider, RegistrationMode::Open);
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let policy = settings
+            .cookies
+            .identity_policy(&[COOKIE_KEY.as_bytes().to_vec()]);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings.clone()))
+                .wrap(IdentityService::new(policy))
+                .service(oidc_login)
+                .service(oidc_callback),
+        )
+        .await;
+        let subject = test_utils::unique_name("sub");
+        let name = test_utils::unique_name("oidc");
+
+        for _ in 0..2 {
+            let start = TestRequest::get().uri("/login/oidc/").to_request();
+            let sent_off = call_service(&app, start).await;
+            let req = oidc_callback_request(&provider, &sent_off, &subject, &name);
+            let res = call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            assert!(res.response().cookies().any(|c| c.name() == "auth-cookie"));
+        }
+        // The same user the second time.
+        let users = oidc_users(&conn, &provider, &subject);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].username, name);
+
+        // Someone else going by the same name gets a number.
+        let other_subject = test_utils::unique_name("sub");
+        let start = TestRequest::get().uri("/login/oidc/").to_request();
+        let sent_off = call_service(&app, start).await;
+        let req = oidc_callback_request(&provider, &sent_off, &other_subject, &name);
+        let res = call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let users = oidc_users(&conn, &provider, &other_subject);
+        assert_eq!(users[0].username, format!("{}-2", name));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn only_logs_in_linked_oidc_users_when_registration_is_closed() {
+        use common::schema::users;
+
+        let provider = OidcProvider::start().await;
+        let settings = oidc_settings(&provider, RegistrationMode::Closed);
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let policy = settings
+            .cookies
+            .identity_policy(&[COOKIE_KEY.as_bytes().to_vec()]);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings.clone()))
+                .wrap(IdentityService::new(policy))
+                .service(oidc_login)
+                .service(oidc_callback),
+        )
+        .await;
+        let subject = test_utils::unique_name("sub");
+        let user = test_utils::new_user(&conn);
+        let link = |issuer: &str| {
+            diesel::update(&user)
+                .set((
+                    users::oidc_issuer.eq(issuer),
+                    users::oidc_subject.eq(&subject),
+                ))
+                .execute(&conn)
+                .unwrap();
+        };
+
+        // Unknown, then known but from another issuer, then linked for real.
+        for (issuer, status) in [
+            (None, StatusCode::FORBIDDEN),
+            (Some("https://elsewhere.example.com"), StatusCode::FORBIDDEN),
+            (Some(provider.issuer()), StatusCode::SEE_OTHER),
+        ] {
+            if let Some(issuer) = issuer {
+                link(issuer);
+            }
+            let start = TestRequest::get().uri("/login/oidc/").to_request();
+            let sent_off = call_service(&app, start).await;
+            let req = oidc_callback_request(&provider, &sent_off, &subject, "nouveau");
+            let res = call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), status, "issuer {:?}", issuer);
+        }
+        let linked = oidc_users(&conn, &provider, &subject);
+        assert_eq!(linked.len(), 1);
+        assert_eq!(linked[0].id, user.id);
+    }
+
+    #[actix_web::test]
+    async fn rejects_oidc_callbacks_from_another_browser() {
+        // Turned away before the database or the provider come into it.
+        let settings = Settings {
+            database_url: "postgres://localhost/nowhere".to_string(),
+            oidc: Some(crate::oidc::OidcConfig {
+                issuer_url: "http://127.0.0.1:9".to_string(),
+                ..crate::oidc::OidcConfig::default()
+            }),
+            ..Settings::default()
+        };
+        let policy = settings
+            .cookies
+            .identity_policy(&[COOKIE_KEY.as_bytes().to_vec()]);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(crate::db::new_db_pool(&settings)))
+                .app_data(web::Data::new(settings))
+                .wrap(IdentityService::new(policy))
+                .service(oidc_callback),
+        )
+        .await;
+
+        let uri = "/login/oidc/callback/?state=theirs&code=code";
+        for cookie in [Some("mine"), Some("theirs-"), None] {
+            let mut req = TestRequest::get().uri(uri);
+            if let Some(state) = cookie {
+                req = req.cookie(Cookie::new(OIDC_STATE_COOKIE, state));
+            }
+            let res = call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    fn api_token(scope: TokenScope, deck_id: Option<i32>) -> ApiToken {
+        ApiToken {
+            id: 1,
+            user_id: 1,
+            name: "script".to_string(),
+            token_hash: String::new(),
+            scope,
+            deck_id,
+            created: Utc::now().naive_utc(),
+            last_used: None,
+        }
+    }
+
+    #[test]
+    fn read_tokens_only_read() {
+        let token = api_token(TokenScope::Read, None);
+        assert!(token_allows(&token, &Method::GET, "/api/decks/"));
+        assert!(token_allows(&token, &Method::HEAD, "/media/abc"));
+        for method in [Method::POST, Method::PATCH, Method::DELETE] {
+            assert!(
+                !token_allows(&token, &method, "/api/decks/1/cards/"),
+                "{}",
+                method
+            );
+        }
+        let token = api_token(TokenScope::ReadWrite, None);
+        assert!(token_allows(&token, &Method::POST, "/api/decks/1/cards/"));
+    }
+
+    #[test]
+    fn deck_tokens_stay_in_their_deck() {
+        let token = api_token(TokenScope::ReadWrite, Some(1));
+        assert!(token_allows(&token, &Method::GET, "/api/decks/1/cards/"));
+        assert!(token_allows(&token, &Method::POST, "/api/decks/1/cards/"));
+        for path in [
+            "/api/decks/12/cards/",
+            "/api/decks/",
+            "/api/decks/1",
+            "/media/abc",
+        ] {
+            assert!(!token_allows(&token, &Method::GET, path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn tokens_never_reach_account_management() {
+        let paths = [
+            "/api/tokens/",
+            "/api/tokens/1/",
+            "/api/sessions/",
+            "/api/password/",
+            "/api/email/",
+            "/api/totp/",
+        ];
+        for token in [
+            api_token(TokenScope::Read, None),
+            api_token(TokenScope::ReadWrite, None),
+            api_token(TokenScope::ReadWrite, Some(1)),
+        ] {
+            for path in paths {
+                for method in [Method::GET, Method::POST, Method::DELETE] {
+                    assert!(!token_allows(&token, &method, path), "{} {}", method, path);
+                }
+            }
+            assert!(token_allows(&token, &Method::GET, "/api/me/"));
+        }
+    }
 }
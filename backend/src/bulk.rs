@@ -0,0 +1,62 @@
+use common::models::BulkOperation;
+use diesel::dsl::{not, sql};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Text};
+
+use crate::revision::DEFAULT_WEIGHT;
+
+pub fn apply_bulk(
+    conn: &PgConnection,
+    card_ids: &[i32],
+    operation: &BulkOperation,
+) -> QueryResult<usize> {
+    // Carry out `operation` on every card in `card_ids`, whose ownership the caller checked.
+    use common::schema::{cards, reverse_siblings};
+
+    let target = cards::table.filter(cards::id.eq_any(card_ids));
+    match operation {
+        BulkOperation::Delete => diesel::delete(target).execute(conn),
+        BulkOperation::Move { deck_id } => diesel::update(target)
+            .set(cards::deck_id.eq(deck_id))
+            .execute(conn),
+        BulkOperation::AddTag { tag } => {
+            diesel::update(target.filter(not(cards::tags.contains(vec![tag.clone()]))))
+                .set(
+                    cards::tags.eq(sql::<Array<Text>>("array_append(tags, ")
+                        .bind::<Text, _>(tag.clone())
+                        .sql(")")),
+                )
+                .execute(conn)
+        }
+        BulkOperation::RemoveTag { tag } => diesel::update(target)
+            .set(
+                cards::tags.eq(sql::<Array<Text>>("array_remove(tags, ")
+                    .bind::<Text, _>(tag.clone())
+                    .sql(")")),
+            )
+            .execute(conn),
+        BulkOperation::ResetScheduling => {
+            // Reverse siblings go back to new as well, by not having any scheduling at all.
+            diesel::delete(
+                reverse_siblings::table.filter(reverse_siblings::card_id.eq_any(card_ids)),
+            )
+            .execute(conn)?;
+            diesel::update(target)
+                .set((
+                    cards::revision_weight.eq(DEFAULT_WEIGHT),
+                    cards::due.eq(None::<chrono::NaiveDateTime>),
+                ))
+                .execute(conn)
+        }
+        // Postgres reads the old values on the right-hand side, so this is a proper swap.
+        BulkOperation::SwapSides => diesel::update(target)
+            .set((cards::front.eq(cards::back), cards::back.eq(cards::front)))
+            .execute(conn),
+        BulkOperation::Suspend => diesel::update(target)
+            .set(cards::suspended.eq(true))
+            .execute(conn),
+        BulkOperation::Unsuspend => diesel::update(target)
+            .set(cards::suspended.eq(false))
+            .execute(conn),
+    }
+}
@@ -0,0 +1,316 @@
+use std::fs;
+use std::future::{ready, Ready};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use actix_identity::{CookieIdentityPolicy, IdentityPolicy};
+use actix_web::cookie::SameSite;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpMessage};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+
+// `actix-identity` derives its signing and encryption keys from at least that many bytes.
+pub const MIN_COOKIE_KEY_BYTES: usize = 32;
+// Length of keys generated on first run.
+const GENERATED_KEY_LENGTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSiteMode {
+    Strict,
+    Lax,
+    None,
+}
+
+impl FromStr for SameSiteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(SameSiteMode::Strict),
+            "lax" => Ok(SameSiteMode::Lax),
+            "none" => Ok(SameSiteMode::None),
+            _ => Err("expected `strict`, `lax` or `none`".to_string()),
+        }
+    }
+}
+
+impl From<SameSiteMode> for SameSite {
+    fn from(mode: SameSiteMode) -> Self {
+        match mode {
+            SameSiteMode::Strict => SameSite::Strict,
+            SameSiteMode::Lax => SameSite::Lax,
+            SameSiteMode::None => SameSite::None,
+        }
+    }
+}
+
+// The `[cookies]` part of `Settings`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CookieConfig {
+    // Signs new cookies, read from `key_file` (created on first run) when not set.
+    pub key: Option<String>,
+    pub key_file: PathBuf,
+    // Keys rotated out, cookies signed with them are still accepted and get signed anew.
+    pub old_keys: Vec<String>,
+    // Only over HTTPS, turn off for development over plain HTTP.
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSiteMode,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        CookieConfig {
+            key: None,
+            key_file: PathBuf::from("cookie.key"),
+            old_keys: Vec::new(),
+            secure: true,
+            http_only: true,
+            same_site: SameSiteMode::Lax,
+        }
+    }
+}
+
+impl CookieConfig {
+    pub fn load_keys(&self) -> io::Result<Vec<Vec<u8>>> {
+        // The current key first, then the old ones.
+        let key = match &self.key {
+            Some(key) => key.clone(),
+            None => read_or_create_key_file(&self.key_file)?,
+        };
+        if key.len() < MIN_COOKIE_KEY_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} holds a key shorter than {} bytes",
+                    self.key_file.display(),
+                    MIN_COOKIE_KEY_BYTES
+                ),
+            ));
+        }
+        Ok(std::iter::once(&key)
+            .chain(&self.old_keys)
+            .map(|key| key.as_bytes().to_vec())
+            .collect())
+    }
+
+    pub fn identity_policy(&self, keys: &[Vec<u8>]) -> RotatingCookiePolicy {
+        let policies = keys
+            .iter()
+            .map(|key| {
+                CookieIdentityPolicy::new(key)
+                    .name("auth-cookie")
+                    .secure(self.secure)
+                    .http_only(self.http_only)
+                    .same_site(self.same_site.into())
+            })
+            .collect();
+        RotatingCookiePolicy { policies }
+    }
+}
+
+fn read_or_create_key_file(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(key) => Ok(key.trim().to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(GENERATED_KEY_LENGTH)
+                .map(char::from)
+                .collect();
+            // Readable by the server only, and never clobber a key another worker just wrote.
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)?;
+            file.write_all(key.as_bytes())?;
+            log::info!("generated a new cookie key in {}", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Marks requests whose cookie was signed with an old key, so the response re-signs it.
+struct StaleCookieKey;
+
+pub struct RotatingCookiePolicy {
+    // The current key's policy first.
+    policies: Vec<CookieIdentityPolicy>,
+}
+
+impl IdentityPolicy for RotatingCookiePolicy {
+    type Future = Ready<Result<Option<String>, Error>>;
+    type ResponseFuture = Ready<Result<(), Error>>;
+
+    fn from_request(&self, req: &mut ServiceRequest) -> Self::Future {
+        // Cookie policies answer right away, the future is only the trait's doing.
+        for (i, policy) in self.policies.iter().enumerate() {
+            match policy.from_request(req).into_inner() {
+                Ok(None) => continue,
+                result => {
+                    if i > 0 {
+                        req.extensions_mut().insert(StaleCookieKey);
+                    }
+                    return ready(result);
+                }
+            }
+        }
+        ready(Ok(None))
+    }
+
+    fn to_response<B>(
+        &self,
+        identity: Option<String>,
+        changed: bool,
+        response: &mut ServiceResponse<B>,
+    ) -> Self::ResponseFuture {
+        let stale = response.request().extensions().contains::<StaleCookieKey>();
+        ready(
+            self.policies[0]
+                .to_response(identity, changed || stale, response)
+                .into_inner(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_identity::{Identity, IdentityService};
+    use actix_web::cookie::Cookie;
+    use actix_web::{get, http::StatusCode, test, web, App, HttpResponse, Responder};
+
+    use super::*;
+    use crate::auth::{new_session, AuthenticateMiddlewareFactory, Authenticated};
+    use crate::db::new_db_pool;
+    use crate::settings::Settings;
+    use crate::test_utils;
+
+    const KEY: &str = "the current key, at least 32 bytes long";
+    const OLD_KEY: &str = "the rotated out key, at least 32 bytes";
+    const WRONG_KEY: &str = "some other server's key, 32 bytes long";
+
+    fn config() -> CookieConfig {
+        CookieConfig {
+            secure: false,
+            ..CookieConfig::default()
+        }
+    }
+
+    fn keys(keys: &[&str]) -> Vec<Vec<u8>> {
+        keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+    }
+
+    #[get("/remember/{token}/")]
+    async fn remember(id: Identity, path: web::Path<(String,)>) -> impl Responder {
+        id.remember(path.into_inner().0);
+        HttpResponse::Ok()
+    }
+
+    #[get("/me/")]
+    async fn me(auth: Authenticated) -> impl Responder {
+        HttpResponse::Ok().body(auth.user_id.to_string())
+    }
+
+    async fn signed_cookie(key: &str, token: &str) -> Cookie<'static> {
+        // What a server signing with `key` would have handed out.
+        let app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    config().identity_policy(&keys(&[key])),
+                ))
+                .service(remember),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/remember/{}/", token))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let cookie = res.response().cookies().next().unwrap();
+        cookie.into_owned()
+    }
+
+    #[actix_web::test]
+    async fn rejects_cookie_signed_with_unknown_key() {
+        // Never gets as far as the database, which doesn't have to exist.
+        let settings = Settings {
+            database_url: "postgres://localhost/nowhere".to_string(),
+            ..Settings::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(new_db_pool(&settings)))
+                .app_data(web::Data::new(settings))
+                .wrap(AuthenticateMiddlewareFactory::default())
+                .wrap(IdentityService::new(
+                    config().identity_policy(&keys(&[KEY, OLD_KEY])),
+                ))
+                .service(me),
+        )
+        .await;
+
+        let cookie = signed_cookie(WRONG_KEY, "forged-session-token").await;
+        let req = test::TestRequest::get()
+            .uri("/me/")
+            .cookie(cookie)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn accepts_and_resigns_cookie_signed_with_old_key() {
+        let settings = test_utils::settings();
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let user = test_utils::new_user(&conn);
+        let token = new_session(&conn, &user, "", settings.session_hours);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings))
+                .wrap(AuthenticateMiddlewareFactory::default())
+                .wrap(IdentityService::new(
+                    config().identity_policy(&keys(&[KEY, OLD_KEY])),
+                ))
+                .service(me),
+        )
+        .await;
+
+        let old_cookie = signed_cookie(OLD_KEY, &token).await;
+        let req = test::TestRequest::get()
+            .uri("/me/")
+            .cookie(old_cookie.clone())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let new_cookie = res.response().cookies().next().unwrap().into_owned();
+        assert_ne!(new_cookie.value(), old_cookie.value());
+        assert_eq!(test::read_body(res).await, user.id.to_string());
+
+        // Good with the current key only, so the old one can go.
+        let app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    config().identity_policy(&keys(&[KEY])),
+                ))
+                .route(
+                    "/",
+                    web::get().to(|id: Identity| async move { id.identity().unwrap() }),
+                ),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/")
+            .cookie(new_cookie)
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, token);
+    }
+}
@@ -0,0 +1,207 @@
+use std::rc::Rc;
+
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error,
+    http::Method,
+    web, Error,
+};
+use common::{CSRF_COOKIE, CSRF_HEADER};
+use futures::future::{ready, Ready};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::auth::bearer_token;
+use crate::settings::Settings;
+
+const CSRF_TOKEN_LENGTH: usize = 32;
+
+// Requests that change something need the `CSRF_COOKIE` cookie echoed in the `CSRF_HEADER`
+// header, which other sites can't read to do so. Bearer tokens aren't sent by browsers on their
+// own, so those requests don't need it.
+#[derive(Default)]
+pub struct CsrfMiddlewareFactory;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        async move {
+            let cookie_token = req.cookie(CSRF_COOKIE).map(|c| c.value().to_string());
+            if !is_safe(req.method()) && bearer_token(&req).is_none() {
+                let header_token = req
+                    .headers()
+                    .get(CSRF_HEADER)
+                    .and_then(|value| value.to_str().ok());
+                let valid = match (&cookie_token, header_token) {
+                    (Some(cookie_token), Some(header_token)) => {
+                        constant_time_eq(cookie_token.as_bytes(), header_token.as_bytes())
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    log::warn!("CSRF check failed on {} {}", req.method(), req.path());
+                    return Err(error::ErrorForbidden("Jeton CSRF invalide"));
+                }
+            }
+
+            let secure = req
+                .app_data::<web::Data<Settings>>()
+                .unwrap()
+                .cookies
+                .secure;
+            let mut res = srv.call(req).await?;
+            // Handed out on the first visit, e.g. the login page, and kept from then on.
+            if cookie_token.is_none() {
+                let token: String = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(CSRF_TOKEN_LENGTH)
+                    .map(char::from)
+                    .collect();
+                let cookie = Cookie::build(CSRF_COOKIE, token)
+                    .path("/")
+                    .secure(secure)
+                    // The frontend has to read it.
+                    .http_only(false)
+                    .same_site(SameSite::Strict)
+                    .finish();
+                res.response_mut().add_cookie(&cookie).unwrap();
+            }
+            Ok(res)
+        }
+        .boxed_local()
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{get, post, App, HttpResponse, Responder};
+
+    use super::*;
+
+    #[get("/")]
+    async fn page() -> impl Responder {
+        HttpResponse::Ok()
+    }
+
+    #[post("/api/decks/")]
+    async fn create() -> impl Responder {
+        HttpResponse::Ok()
+    }
+
+    #[actix_web::test]
+    async fn checks_the_header_against_the_cookie() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Settings::default()))
+                .wrap(CsrfMiddlewareFactory)
+                .service(create),
+        )
+        .await;
+        let post = || {
+            TestRequest::post()
+                .uri("/api/decks/")
+                .cookie(Cookie::new(CSRF_COOKIE, "the token"))
+        };
+
+        let res = app.call(post().to_request()).await;
+        let res = res.unwrap_err().error_response();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = post().insert_header((CSRF_HEADER, "another token"));
+        let res = app.call(req.to_request()).await;
+        let res = res.unwrap_err().error_response();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = post().insert_header((CSRF_HEADER, "the token"));
+        let res = call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn lets_bearer_requests_through() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Settings::default()))
+                .wrap(CsrfMiddlewareFactory)
+                .service(create),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/api/decks/")
+            .insert_header((header::AUTHORIZATION, "Bearer some token"))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn hands_out_the_cookie_once() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Settings::default()))
+                .wrap(CsrfMiddlewareFactory)
+                .service(page),
+        )
+        .await;
+
+        let res = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == CSRF_COOKIE)
+            .unwrap();
+        assert_eq!(cookie.value().len(), CSRF_TOKEN_LENGTH);
+        // Readable by the frontend.
+        assert_ne!(cookie.http_only(), Some(true));
+
+        let req = TestRequest::get()
+            .uri("/")
+            .cookie(Cookie::new(CSRF_COOKIE, cookie.value().to_string()))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.response().cookies().count(), 0);
+    }
+}
@@ -1,5 +1,3 @@
-use std::env;
-
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::*;
@@ -8,17 +6,14 @@ use diesel::r2d2::{Builder, ConnectionManager, Pool};
 use diesel::sql_types::BigInt;
 use serde::Serialize;
 
+use crate::settings::Settings;
+
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
-pub fn new_db_pool() -> DbPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL not set.");
-    let max_db_connections = env::var("MAX_DB_CONNECTIONS")
-        .expect("MAX_DB_CONNECTIONS not set.")
-        .parse()
-        .unwrap();
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+pub fn new_db_pool(settings: &Settings) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(&settings.database_url);
     Builder::new()
-        .max_size(max_db_connections)
+        .max_size(settings.max_db_connections)
         .build_unchecked(manager)
 }
 
@@ -80,7 +75,7 @@ impl<T> Paginated<T> {
         let per_page = self.per_page;
         let page_number = self.page_number;
         let results = self.load::<(U, i64)>(conn)?;
-        let total = results.get(0).map(|x| x.1).unwrap_or(0);
+        let total = results.first().map(|x| x.1).unwrap_or(0);
         let records = results.into_iter().map(|x| x.0).collect();
         let page_count = (total as f64 / per_page as f64).ceil() as i64;
         Ok(Page::new(records, page_count, page_number + 1 < page_count))
@@ -0,0 +1,158 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use common::markup::plain_text;
+
+// Leading articles that don't make a card different, e.g. "le chat" vs "chat".
+const ARTICLES: [&str; 12] = [
+    "le", "la", "les", "l", "un", "une", "des", "du", "de", "the", "a", "an",
+];
+
+fn strip_accent(c: char) -> &'static str {
+    match c {
+        'à' | 'â' | 'ä' | 'á' | 'ã' => "a",
+        'é' | 'è' | 'ê' | 'ë' => "e",
+        'î' | 'ï' | 'í' | 'ì' => "i",
+        'ô' | 'ö' | 'ó' | 'ò' | 'õ' => "o",
+        'ù' | 'û' | 'ü' | 'ú' => "u",
+        'ç' => "c",
+        'ñ' => "n",
+        'ÿ' => "y",
+        'œ' => "oe",
+        'æ' => "ae",
+        _ => "",
+    }
+}
+
+pub fn normalize_front(front: &str) -> String {
+    // Key under which two fronts count as the same card: markup, case, accents, punctuation,
+    // whitespace and leading articles are all ignored.
+    let front = plain_text(front);
+    let mut plain = String::with_capacity(front.len());
+    for c in front.to_lowercase().chars() {
+        match strip_accent(c) {
+            "" if c.is_alphanumeric() => plain.push(c),
+            "" => plain.push(' '),
+            stripped => plain.push_str(stripped),
+        }
+    }
+    let words: Vec<&str> = plain.split_whitespace().collect();
+    let start = words
+        .iter()
+        .position(|word| !ARTICLES.contains(word))
+        .unwrap_or(words.len());
+    // Don't strip a card down to nothing, "la" on its own is still a card.
+    let words = if start == words.len() {
+        &words[..]
+    } else {
+        &words[start..]
+    };
+    words.join(" ")
+}
+
+pub fn find_duplicate<'a, T>(
+    front: &str,
+    candidates: &'a [T],
+    candidate_front: impl Fn(&T) -> &str,
+) -> Option<&'a T> {
+    let key = normalize_front(front);
+    candidates
+        .iter()
+        .find(|candidate| normalize_front(candidate_front(candidate)) == key)
+}
+
+pub fn cluster(pairs: &[(i32, i32)]) -> Vec<Vec<i32>> {
+    // Group ids linked by any chain of pairs (union-find), each cluster sorted, clusters
+    // ordered by their smallest id.
+    fn root(parents: &mut HashMap<i32, i32>, id: i32) -> i32 {
+        let parent = *parents.entry(id).or_insert(id);
+        if parent == id {
+            return id;
+        }
+        let found = root(parents, parent);
+        parents.insert(id, found);
+        found
+    }
+
+    let mut parents: HashMap<i32, i32> = HashMap::new();
+    for (a, b) in pairs {
+        let (root_a, root_b) = (root(&mut parents, *a), root(&mut parents, *b));
+        if root_a != root_b {
+            parents.insert(std::cmp::max(root_a, root_b), std::cmp::min(root_a, root_b));
+        }
+    }
+
+    let ids: Vec<i32> = parents.keys().copied().collect();
+    let mut clusters: HashMap<i32, Vec<i32>> = HashMap::new();
+    for id in ids {
+        let id_root = root(&mut parents, id);
+        clusters.entry(id_root).or_default().push(id);
+    }
+    let mut clusters: Vec<Vec<i32>> = clusters
+        .into_values()
+        .map(|mut ids| {
+            ids.sort_unstable();
+            ids
+        })
+        .collect();
+    clusters.sort_unstable_by_key(|ids| ids[0]);
+    clusters
+}
+
+pub fn normalized_pairs<'a>(cards: impl Iterator<Item = (i32, &'a str)>) -> Vec<(i32, i32)> {
+    // Pairs each card with the first card sharing its normalized front.
+    let mut first_by_key: HashMap<String, i32> = HashMap::new();
+    let mut pairs = Vec::new();
+    for (id, front) in cards {
+        match first_by_key.entry(normalize_front(front)) {
+            Entry::Occupied(first) => pairs.push((*first.get(), id)),
+            Entry::Vacant(entry) => {
+                entry.insert(id);
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_accents_and_whitespace() {
+        assert_eq!(normalize_front("  Été\t\tà   la Plage  "), "ete a la plage");
+        assert_eq!(normalize_front("L'Œuf"), "oeuf");
+        assert_eq!(normalize_front("Le  CHAT !"), normalize_front("chat"));
+        assert_eq!(normalize_front("un garçon"), normalize_front("GARCON"));
+        assert_eq!(normalize_front("**le** _chat_"), "chat");
+        // Articles only go at the start, and never all of the card.
+        assert_eq!(normalize_front("chat de la voisine"), "chat de la voisine");
+        assert_eq!(normalize_front("La"), "la");
+    }
+
+    #[test]
+    fn clusters_transitively() {
+        assert_eq!(cluster(&[]), Vec::<Vec<i32>>::new());
+        assert_eq!(
+            cluster(&[(3, 2), (2, 1), (10, 11), (5, 5)]),
+            vec![vec![1, 2, 3], vec![5], vec![10, 11]]
+        );
+        // Two groups that only meet through a later pair.
+        assert_eq!(cluster(&[(1, 2), (7, 8), (8, 2)]), vec![vec![1, 2, 7, 8]]);
+    }
+
+    #[test]
+    fn pairs_cards_with_the_same_normalized_front() {
+        let cards = [
+            (1, "le chat"),
+            (2, "Chien"),
+            (3, "CHAT"),
+            (4, "  chien "),
+            (5, "chât"),
+            (6, "oiseau"),
+        ];
+        let pairs = normalized_pairs(cards.iter().copied());
+        assert_eq!(pairs, vec![(1, 3), (2, 4), (1, 5)]);
+        assert_eq!(cluster(&pairs), vec![vec![1, 3, 5], vec![2, 4]]);
+    }
+}
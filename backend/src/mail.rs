@@ -0,0 +1,174 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{FileTransport, Message, SmtpTransport, Transport};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    // `.eml` files in `dir`, for development or to pick up by something else.
+    Dir,
+    Smtp,
+}
+
+impl FromStr for MailTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dir" => Ok(MailTransport::Dir),
+            "smtp" => Ok(MailTransport::Smtp),
+            _ => Err("expected `dir` or `smtp`".to_string()),
+        }
+    }
+}
+
+// The `[mail]` part of `Settings`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    pub from: String,
+    pub dir: PathBuf,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    // STARTTLS, turn off for a local test server.
+    pub smtp_tls: bool,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            transport: MailTransport::Dir,
+            from: "Anqui <anqui@localhost>".to_string(),
+            dir: PathBuf::from("mail"),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_tls: true,
+        }
+    }
+}
+
+impl MailConfig {
+    pub fn mailer(&self) -> Result<Arc<dyn Mailer>, MailError> {
+        let from: Mailbox = self
+            .from
+            .parse()
+            .map_err(|e: lettre::address::AddressError| MailError::Address(e.to_string()))?;
+        match self.transport {
+            MailTransport::Dir => {
+                fs::create_dir_all(&self.dir).map_err(MailError::Io)?;
+                Ok(Arc::new(DirMailer {
+                    from,
+                    transport: FileTransport::new(&self.dir),
+                }))
+            }
+            MailTransport::Smtp => {
+                let builder = if self.smtp_tls {
+                    SmtpTransport::starttls_relay(&self.smtp_host).map_err(MailError::Smtp)?
+                } else {
+                    SmtpTransport::builder_dangerous(&self.smtp_host)
+                };
+                let mut builder = builder.port(self.smtp_port);
+                if let (Some(username), Some(password)) = (&self.smtp_username, &self.smtp_password)
+                {
+                    builder =
+                        builder.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+                Ok(Arc::new(SmtpMailer {
+                    from,
+                    transport: builder.build(),
+                }))
+            }
+        }
+    }
+}
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    Address(String),
+    Io(io::Error),
+    Message(lettre::error::Error),
+    File(lettre::transport::file::Error),
+    Smtp(lettre::transport::smtp::Error),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MailError::Address(e) => write!(f, "bad address: {}", e),
+            MailError::Io(e) => write!(f, "{}", e),
+            MailError::Message(e) => write!(f, "{}", e),
+            MailError::File(e) => write!(f, "{}", e),
+            MailError::Smtp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+// Anything that gets emails out, `MailConfig::mailer` picks one of the ones below.
+pub trait Mailer: Send + Sync {
+    // Blocks, so from handlers it goes through `web::block`.
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+pub fn is_valid_address(address: &str) -> bool {
+    address.parse::<lettre::Address>().is_ok()
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, MailError> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e: lettre::address::AddressError| MailError::Address(e.to_string()))?;
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(&email.subject)
+        .body(email.body.clone())
+        .map_err(MailError::Message)
+}
+
+pub struct DirMailer {
+    from: Mailbox,
+    transport: FileTransport,
+}
+
+impl Mailer for DirMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = build_message(&self.from, email)?;
+        self.transport.send(&message).map_err(MailError::File)?;
+        Ok(())
+    }
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: SmtpTransport,
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = build_message(&self.from, email)?;
+        self.transport.send(&message).map_err(MailError::Smtp)?;
+        Ok(())
+    }
+}
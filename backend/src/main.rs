@@ -5,21 +5,38 @@ extern crate dotenv;
 extern crate bcrypt;
 extern crate log;
 
-use std::env;
-
 use actix_files::{Files, NamedFile};
-use actix_identity::{CookieIdentityPolicy, IdentityService};
+use actix_identity::IdentityService;
 use actix_web::{http, middleware::ErrorHandlers, web, App, HttpServer, Responder};
 use dotenv::dotenv;
 
 use crate::api::*;
 use crate::auth::*;
+use crate::csrf::CsrfMiddlewareFactory;
 use crate::db::new_db_pool;
+use crate::media::run_garbage_collection;
+use crate::rate_limit::{LoginLimiter, RateLimitMiddlewareFactory};
+use crate::settings::Settings;
 
 mod api;
 mod auth;
+mod bulk;
+mod cookies;
+mod csrf;
 mod db;
+mod duplicates;
+mod mail;
+mod media;
+mod oidc;
+mod rate_limit;
 mod revision;
+mod search;
+mod settings;
+mod stats;
+#[cfg(test)]
+mod test_utils;
+mod totp;
+mod transfer;
 
 async fn index(_auth: Authenticated, _data: web::Path<()>) -> impl Responder {
     // Need to "default" serve `index.html` from every random URL to play nice with Yew routes.
@@ -29,23 +46,47 @@ async fn index(_auth: Authenticated, _data: web::Path<()>) -> impl Responder {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let host = env::var("HOST").expect("HOST not set.");
-    let port = env::var("PORT").expect("PORT not set.");
-
     env_logger::init();
 
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("Invalid settings: {}", e);
+        std::process::exit(1);
+    });
+    // Once for all workers, so they all agree on cookies.
+    let cookie_keys = settings.cookies.load_keys().unwrap_or_else(|e| {
+        eprintln!("Can't load the cookie key: {}", e);
+        std::process::exit(1);
+    });
+
+    let mailer = settings.mail.mailer().unwrap_or_else(|e| {
+        eprintln!("Can't set up mail: {}", e);
+        std::process::exit(1);
+    });
+
+    // Shared by all workers, or each would allow its own share of attempts.
+    let login_limiter = web::Data::new(LoginLimiter::new(&settings.rate_limit));
+
+    let pool = new_db_pool(&settings);
+    actix_web::rt::spawn(run_garbage_collection(pool.clone(), settings.media.clone()));
+
+    let bind = (settings.host.clone(), settings.port);
     HttpServer::new(move || {
-        let policy = CookieIdentityPolicy::new(&[0; 32])
-            .name("auth-cookie")
-            .secure(false);
+        let policy = settings.cookies.identity_policy(&cookie_keys);
 
         App::new()
-            .app_data(web::Data::new(new_db_pool()))
+            .app_data(web::Data::new(settings.clone()))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(login_limiter.clone())
+            // Uploads are checked against `max_bytes` proper in `upload_media`.
+            .app_data(web::PayloadConfig::new(settings.media.max_bytes + 1))
             .wrap(
                 ErrorHandlers::new().handler(http::StatusCode::UNAUTHORIZED, redirect_on_autherror),
             )
             .wrap(AuthenticateMiddlewareFactory::default())
             .wrap(IdentityService::new(policy))
+            .wrap(CsrfMiddlewareFactory)
+            .wrap(RateLimitMiddlewareFactory)
             .service(
                 web::scope("/api")
                     .service(
@@ -55,22 +96,58 @@ async fn main() -> std::io::Result<()> {
                             .service(new_deck)
                             .service(update_deck)
                             .service(delete_deck)
+                            .service(merge_deck)
+                            .service(split_deck)
                             .service(read_cards)
+                            // Ahead of `update_card`, whose path would swallow `bulk/`.
+                            .service(bulk_update_cards)
+                            .service(transfer_deck_cards)
                             .service(read_card)
+                            .service(upload_media)
+                            .service(read_card_media)
+                            .service(read_deck_media)
                             .service(new_card)
                             .service(update_card)
                             .service(delete_card)
-                            .service(get_revision_cards),
+                            .service(get_revision_cards)
+                            .service(read_deck_stats),
                     )
-                    .service(post_feedback),
+                    .service(search_cards)
+                    .service(read_duplicates)
+                    .service(post_feedback)
+                    .service(get_combined_revision_cards)
+                    .service(read_stats)
+                    .service(read_activity)
+                    .service(read_sessions)
+                    .service(delete_other_sessions)
+                    .service(delete_session)
+                    .service(read_me)
+                    .service(change_password)
+                    .service(update_email)
+                    .service(read_totp_status)
+                    .service(enroll_totp)
+                    .service(confirm_totp)
+                    .service(disable_totp)
+                    .service(read_api_tokens)
+                    .service(new_api_token)
+                    .service(delete_api_token)
+                    .service(delete_media),
             )
+            .service(serve_media)
             .service(login_get)
             .service(login)
+            .service(login_totp)
+            .service(read_login_options)
+            .service(oidc_login)
+            .service(oidc_callback)
+            .service(reset_password_get)
+            .service(request_password_reset)
+            .service(reset_password)
             .service(logout)
             .service(Files::new("/static/", "frontend/dist/").index_file("index.html"))
             .default_service(web::get().to(index))
     })
-    .bind((host, port.parse::<u16>().unwrap()))?
+    .bind(bind)?
     .run()
     .await
 }
@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use actix_web::web;
+use diesel::dsl::{exists, select, sql_query};
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::db::DbPool;
+
+// How often orphaned files get cleaned up.
+const GC_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// Files younger than this are left alone, their `media` row may not be committed yet.
+const GC_GRACE: Duration = Duration::from_secs(60 * 60);
+
+// The `[media]` part of `Settings`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub dir: PathBuf,
+    pub max_bytes: usize,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig {
+            dir: PathBuf::from("media"),
+            max_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+pub fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    // Go by the file's magic bytes, not whatever the client claims it is.
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WAVE" {
+        Some("audio/wav")
+    } else if bytes.starts_with(b"OggS") {
+        Some("audio/ogg")
+    } else if bytes.starts_with(b"\x1a\x45\xdf\xa3") {
+        // What `MediaRecorder` records in Chrome and Firefox.
+        Some("audio/webm")
+    } else if bytes.len() >= 8 && &bytes[4..8] == b"ftyp" {
+        // ... and in Safari.
+        Some("audio/mp4")
+    } else if bytes.starts_with(b"ID3")
+        || bytes.starts_with(b"\xff\xfb")
+        || bytes.starts_with(b"\xff\xf3")
+    {
+        Some("audio/mpeg")
+    } else {
+        None
+    }
+}
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+pub fn is_valid_hash(hash: &str) -> bool {
+    // Also what keeps `/media/{hash}` from reaching outside the media directory.
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+pub fn store(config: &MediaConfig, bytes: &[u8]) -> io::Result<String> {
+    // Write the file under its hash unless already there, returns the hash.
+    let hash = content_hash(bytes);
+    let path = config.dir.join(&hash);
+    if !path.exists() {
+        fs::create_dir_all(&config.dir)?;
+        // Through a temporary file so a half-written upload is never served.
+        let partial = config.dir.join(format!("{}.partial", hash));
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, &path)?;
+    }
+    Ok(hash)
+}
+
+pub fn lock_blob(conn: &PgConnection, hash: &str) -> QueryResult<()> {
+    // Held until the end of the transaction, uploads take it around writing the file and its row.
+    sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind::<Text, _>(hash)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn remove_if_orphaned(conn: &PgConnection, hash: &str, path: &Path) -> io::Result<bool> {
+    // Check again under the lock, the file may have been uploaded again since `referenced` was loaded.
+    use common::schema::media;
+
+    let mut removed = Ok(false);
+    conn.transaction(|| {
+        lock_blob(conn, hash)?;
+        let referenced =
+            select(exists(media::table.filter(media::hash.eq(hash)))).get_result::<bool>(conn)?;
+        if !referenced {
+            removed = match fs::remove_file(path) {
+                Ok(()) => Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e),
+            };
+        }
+        Ok::<_, diesel::result::Error>(())
+    })
+    .map_err(io::Error::other)?;
+    removed
+}
+
+pub fn collect_garbage(conn: &PgConnection, config: &MediaConfig) -> io::Result<usize> {
+    // Delete files that no `media` row points at anymore, e.g. after their card got deleted.
+    use common::schema::media;
+
+    let entries = match fs::read_dir(&config.dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let referenced: HashSet<String> = media::table
+        .select(media::hash)
+        .distinct()
+        .load::<String>(conn)
+        .map_err(io::Error::other)?
+        .into_iter()
+        .collect();
+
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let hash = name.trim_end_matches(".partial");
+        if !is_valid_hash(hash) || referenced.contains(&name) {
+            continue;
+        }
+        let age = now
+            .duration_since(entry.metadata()?.modified()?)
+            .unwrap_or_default();
+        if age <= GC_GRACE {
+            continue;
+        }
+        if name.ends_with(".partial") {
+            // Left over from an upload that never finished.
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        } else if remove_if_orphaned(conn, hash, &entry.path())? {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+pub async fn run_garbage_collection(pool: DbPool, config: MediaConfig) {
+    let mut interval = actix_web::rt::time::interval(GC_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let config = config.clone();
+        let result = web::block(move || {
+            let conn = pool.get().unwrap();
+            collect_garbage(&conn, &config)
+        })
+        .await;
+        match result {
+            Ok(Ok(removed)) => log::info!("media gc: removed {} orphaned file(s)", removed),
+            Ok(Err(e)) => log::error!("media gc: {}", e),
+            Err(e) => log::error!("media gc: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::CardSide;
+
+    use super::*;
+    use crate::test_utils;
+
+    fn make_old(path: &Path) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - GC_GRACE * 2).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn collects_only_old_orphaned_files() {
+        use common::schema::media;
+
+        let config = MediaConfig {
+            dir: std::env::temp_dir().join(test_utils::unique_name("media")),
+            ..MediaConfig::default()
+        };
+        let pool = test_utils::pool(&test_utils::settings());
+        let conn = pool.get().unwrap();
+        let user = test_utils::new_user(&conn);
+        let card = test_utils::new_card(&conn, &test_utils::new_deck(&conn, &user));
+
+        let store_old = |name: &str| {
+            let hash = store(
+                &config,
+                [b"GIF89a".as_slice(), name.as_bytes()].concat().as_slice(),
+            )
+            .unwrap();
+            make_old(&config.dir.join(&hash));
+            hash
+        };
+        let referenced = store_old(&test_utils::unique_name("referenced"));
+        let orphaned = store_old(&test_utils::unique_name("orphaned"));
+        let young = store(&config, test_utils::unique_name("young").as_bytes()).unwrap();
+        let partial = config
+            .dir
+            .join(format!("{}.partial", content_hash(b"partial")));
+        fs::write(&partial, b"partial").unwrap();
+        make_old(&partial);
+        diesel::insert_into(media::table)
+            .values((
+                media::card_id.eq(card.id),
+                media::side.eq(CardSide::Front),
+                media::hash.eq(&referenced),
+                media::content_type.eq("image/gif"),
+                media::size.eq(0),
+            ))
+            .execute(&conn)
+            .unwrap();
+
+        assert_eq!(collect_garbage(&conn, &config).unwrap(), 2);
+        assert!(config.dir.join(&referenced).exists());
+        assert!(config.dir.join(&young).exists());
+        assert!(!config.dir.join(&orphaned).exists());
+        assert!(!partial.exists());
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn rechecks_references_before_removing() {
+        use common::schema::media;
+
+        let config = MediaConfig {
+            dir: std::env::temp_dir().join(test_utils::unique_name("media")),
+            ..MediaConfig::default()
+        };
+        let pool = test_utils::pool(&test_utils::settings());
+        let conn = pool.get().unwrap();
+        let user = test_utils::new_user(&conn);
+        let card = test_utils::new_card(&conn, &test_utils::new_deck(&conn, &user));
+        let hash = store(&config, test_utils::unique_name("uploaded").as_bytes()).unwrap();
+        let path = config.dir.join(&hash);
+
+        // As if the row had been inserted after `collect_garbage` loaded the referenced hashes.
+        diesel::insert_into(media::table)
+            .values((
+                media::card_id.eq(card.id),
+                media::side.eq(CardSide::Back),
+                media::hash.eq(&hash),
+                media::content_type.eq("image/gif"),
+                media::size.eq(0),
+            ))
+            .execute(&conn)
+            .unwrap();
+        assert!(!remove_if_orphaned(&conn, &hash, &path).unwrap());
+        assert!(path.exists());
+
+        diesel::delete(media::table.filter(media::hash.eq(&hash)))
+            .execute(&conn)
+            .unwrap();
+        assert!(remove_if_orphaned(&conn, &hash, &path).unwrap());
+        assert!(!path.exists());
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
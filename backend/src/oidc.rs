@@ -0,0 +1,247 @@
+// Logging in through an OpenID Connect provider: authorization code flow with PKCE, the ID
+// token checked against the provider's keys and the nonce handed out with the request.
+
+use std::fmt;
+
+use openidconnect::core::{CoreClient, CoreProviderMetadata, CoreResponseType};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::{
+    AuthenticationFlow, AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use serde::Deserialize;
+
+// Where the provider sends users back to, under `public_url`.
+pub const CALLBACK_PATH: &str = "/login/oidc/callback/";
+
+// The `[oidc]` part of `Settings`, only there when logging in through a provider is on.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OidcConfig {
+    // Where `.well-known/openid-configuration` lives, plain HTTP is fine for a local provider.
+    pub issuer_url: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    // On the login page's button.
+    #[serde(default = "default_label")]
+    pub label: String,
+    // Besides `openid`, e.g. for `preferred_username`.
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_label() -> String {
+    "Se connecter avec SSO".to_string()
+}
+
+fn default_scopes() -> Vec<String> {
+    vec!["profile".to_string(), "email".to_string()]
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        OidcConfig {
+            issuer_url: String::new(),
+            client_id: String::new(),
+            client_secret: None,
+            label: default_label(),
+            scopes: default_scopes(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OidcError(String);
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+fn oidc_error(e: impl fmt::Display) -> OidcError {
+    OidcError(e.to_string())
+}
+
+// What to keep until the provider sends the user back, and where to send them.
+pub struct AuthRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+}
+
+pub struct OidcIdentity {
+    // Together they're who the user is, subjects are only unique for their issuer.
+    pub issuer: String,
+    pub subject: String,
+    // Something to name new users after, if the provider shares it.
+    pub preferred_username: Option<String>,
+    pub email: Option<String>,
+}
+
+async fn client(config: &OidcConfig, public_url: &str) -> Result<CoreClient, OidcError> {
+    // Discovered on every login, providers rotate their keys.
+    let issuer_url = IssuerUrl::new(config.issuer_url.clone()).map_err(oidc_error)?;
+    let metadata = CoreProviderMetadata::discover_async(issuer_url, async_http_client)
+        .await
+        .map_err(oidc_error)?;
+    let redirect_url = format!("{}{}", public_url.trim_end_matches('/'), CALLBACK_PATH);
+    Ok(CoreClient::from_provider_metadata(
+        metadata,
+        ClientId::new(config.client_id.clone()),
+        config.client_secret.clone().map(ClientSecret::new),
+    )
+    .set_redirect_uri(RedirectUrl::new(redirect_url).map_err(oidc_error)?))
+}
+
+pub async fn begin(config: &OidcConfig, public_url: &str) -> Result<AuthRequest, OidcError> {
+    let client = client(config, public_url).await?;
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let mut request = client
+        .authorize_url(
+            AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .set_pkce_challenge(pkce_challenge);
+    for scope in &config.scopes {
+        request = request.add_scope(Scope::new(scope.clone()));
+    }
+    let (url, state, nonce) = request.url();
+    Ok(AuthRequest {
+        url: url.to_string(),
+        state: state.secret().clone(),
+        nonce: nonce.secret().clone(),
+        pkce_verifier: pkce_verifier.secret().clone(),
+    })
+}
+
+pub async fn finish(
+    config: &OidcConfig,
+    public_url: &str,
+    code: &str,
+    nonce: &str,
+    pkce_verifier: &str,
+) -> Result<OidcIdentity, OidcError> {
+    let client = client(config, public_url).await?;
+    let response = client
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .set_pkce_verifier(PkceCodeVerifier::new(pkce_verifier.to_string()))
+        .request_async(async_http_client)
+        .await
+        .map_err(oidc_error)?;
+    let id_token = response
+        .id_token()
+        .ok_or_else(|| OidcError("no ID token in the response".to_string()))?;
+    let claims = id_token
+        .claims(&client.id_token_verifier(), &Nonce::new(nonce.to_string()))
+        .map_err(oidc_error)?;
+    Ok(OidcIdentity {
+        issuer: claims.issuer().to_string(),
+        subject: claims.subject().to_string(),
+        preferred_username: claims.preferred_username().map(|name| name.to_string()),
+        email: claims.email().map(|email| email.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::OidcProvider;
+
+    const PUBLIC_URL: &str = "http://localhost:8080";
+
+    fn code(redirect: &openidconnect::url::Url) -> String {
+        redirect
+            .query_pairs()
+            .find(|(key, _)| key == "code")
+            .unwrap()
+            .1
+            .into_owned()
+    }
+
+    #[actix_web::test]
+    async fn logs_in_with_code_pkce_and_nonce() {
+        let provider = OidcProvider::start().await;
+        let config = provider.config();
+        let request = begin(&config, PUBLIC_URL).await.unwrap();
+        let redirect = provider.authorize(&request.url, "sub-1", "alice");
+        assert_eq!(
+            redirect.as_str().split('?').next(),
+            Some("http://localhost:8080/login/oidc/callback/")
+        );
+        let state = redirect.query_pairs().find(|(key, _)| key == "state");
+        assert_eq!(state.unwrap().1, request.state);
+
+        let identity = finish(
+            &config,
+            PUBLIC_URL,
+            &code(&redirect),
+            &request.nonce,
+            &request.pkce_verifier,
+        )
+        .await
+        .unwrap();
+        assert_eq!(identity.issuer, provider.issuer());
+        assert_eq!(identity.subject, "sub-1");
+        assert_eq!(identity.preferred_username.as_deref(), Some("alice"));
+    }
+
+    #[actix_web::test]
+    async fn rejects_a_wrong_verifier_or_nonce() {
+        let provider = OidcProvider::start().await;
+        let config = provider.config();
+
+        let request = begin(&config, PUBLIC_URL).await.unwrap();
+        let other = begin(&config, PUBLIC_URL).await.unwrap();
+        let redirect = provider.authorize(&request.url, "sub-1", "alice");
+        let result = finish(
+            &config,
+            PUBLIC_URL,
+            &code(&redirect),
+            &request.nonce,
+            &other.pkce_verifier,
+        )
+        .await;
+        assert!(result.is_err());
+
+        let redirect = provider.authorize(&request.url, "sub-1", "alice");
+        let result = finish(
+            &config,
+            PUBLIC_URL,
+            &code(&redirect),
+            &other.nonce,
+            &request.pkce_verifier,
+        )
+        .await;
+        assert!(result.is_err());
+
+        // Codes only work once.
+        let redirect = provider.authorize(&request.url, "sub-1", "alice");
+        let code = code(&redirect);
+        let finish_once = || {
+            finish(
+                &config,
+                PUBLIC_URL,
+                &code,
+                &request.nonce,
+                &request.pkce_verifier,
+            )
+        };
+        assert!(finish_once().await.is_ok());
+        assert!(finish_once().await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn fails_without_a_provider() {
+        let config = OidcConfig {
+            issuer_url: "http://127.0.0.1:9".to_string(),
+            ..OidcConfig::default()
+        };
+        assert!(begin(&config, PUBLIC_URL).await.is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    dev::{forward_ready, ConnectionInfo, Service, ServiceRequest, ServiceResponse, Transform},
+    error,
+    http::{header, Method, StatusCode},
+    web, Error, HttpResponse,
+};
+use derive_more::Display;
+use futures::future::{ready, Ready};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use serde::Deserialize;
+
+// Where anonymous POSTs can try out passwords, codes or tokens.
+const LIMITED_PATHS: &[&str] = &[
+    "/login/",
+    "/login/totp/",
+    "/password-reset/",
+    "/password-reset/confirm/",
+];
+// Past that many tracked keys, full buckets and expired lockouts get dropped.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+// The `[rate_limit]` part of `Settings`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    // Attempts allowed in a row from one IP, then refilled at `ip_per_minute`.
+    pub ip_burst: u32,
+    pub ip_per_minute: u32,
+    // Wrong passwords per username, whichever IPs they come from. Only failures count, so
+    // logging in doesn't use them up, but guessing from many IPs slows down the real user too.
+    pub username_burst: u32,
+    pub username_per_minute: u32,
+    // Wrong passwords in a row from one IP before it can't try that username for `lockout_secs`,
+    // twice as long on each further one, up to `max_lockout_secs`. By IP so that nobody can lock
+    // users out from elsewhere, guessing from many IPs is what the username limit is for.
+    pub lockout_after: u32,
+    pub lockout_secs: u64,
+    pub max_lockout_secs: u64,
+    // Take the client's IP from `Forwarded`/`X-Forwarded-For`, only behind a proxy that sets it.
+    pub trust_proxy: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            ip_burst: 20,
+            ip_per_minute: 10,
+            username_burst: 5,
+            username_per_minute: 2,
+            lockout_after: 5,
+            lockout_secs: 60,
+            max_lockout_secs: 60 * 60,
+            trust_proxy: false,
+        }
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(
+    fmt = "Trop de tentatives, réessayez dans {} secondes",
+    "self.retry_secs()"
+)]
+pub struct TooManyAttempts {
+    retry_after: Duration,
+}
+
+impl TooManyAttempts {
+    fn retry_secs(&self) -> u64 {
+        // Rounded up, `Retry-After` is in whole seconds.
+        self.retry_after.as_secs_f64().ceil() as u64
+    }
+}
+
+impl error::ResponseError for TooManyAttempts {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, self.retry_secs().to_string()))
+            .body(self.to_string())
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token buckets by key, all with the same capacity and refill rate.
+struct Buckets {
+    capacity: f64,
+    per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Buckets {
+    fn new(capacity: u32, per_minute: u32) -> Self {
+        Buckets {
+            capacity: f64::from(capacity),
+            per_sec: f64::from(per_minute) / 60.,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn empty(&self, tokens: f64) -> TooManyAttempts {
+        TooManyAttempts {
+            retry_after: Duration::from_secs_f64((1. - tokens) / self.per_sec),
+        }
+    }
+
+    fn check(&self, key: &str, now: Instant) -> Result<(), TooManyAttempts> {
+        // Whether `take` would go through, without using anything up.
+        let buckets = self.buckets.lock().unwrap();
+        match buckets.get(key) {
+            Some(bucket) => {
+                let tokens = refilled(bucket, self.capacity, self.per_sec, now);
+                if tokens >= 1. {
+                    Ok(())
+                } else {
+                    Err(self.empty(tokens))
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn take(&self, key: &str, now: Instant) -> Result<(), TooManyAttempts> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_TRACKED_KEYS {
+            let (capacity, per_sec) = (self.capacity, self.per_sec);
+            buckets.retain(|_, bucket| refilled(bucket, capacity, per_sec, now) < capacity);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = refilled(bucket, self.capacity, self.per_sec, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            Ok(())
+        } else {
+            Err(self.empty(bucket.tokens))
+        }
+    }
+}
+
+fn refilled(bucket: &Bucket, capacity: f64, per_sec: f64, now: Instant) -> f64 {
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    (bucket.tokens + elapsed * per_sec).min(capacity)
+}
+
+struct Failures {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+pub struct LoginLimiter {
+    config: RateLimitConfig,
+    ips: Buckets,
+    usernames: Buckets,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl LoginLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        LoginLimiter {
+            config: config.clone(),
+            ips: Buckets::new(config.ip_burst, config.ip_per_minute),
+            usernames: Buckets::new(config.username_burst, config.username_per_minute),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn client_ip(&self, peer_addr: Option<SocketAddr>, info: &ConnectionInfo) -> String {
+        let peer_ip = peer_addr.map(|addr| addr.ip().to_string());
+        if !self.config.trust_proxy {
+            return peer_ip.unwrap_or_default();
+        }
+        // Might come with a port, which shouldn't make for a new bucket.
+        match info.realip_remote_addr() {
+            Some(addr) => addr
+                .parse::<SocketAddr>()
+                .map(|addr| addr.ip())
+                .or_else(|_| addr.parse::<IpAddr>())
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|_| addr.to_string()),
+            None => peer_ip.unwrap_or_default(),
+        }
+    }
+
+    pub fn check_ip(&self, ip: &str, now: Instant) -> Result<(), TooManyAttempts> {
+        self.ips.take(ip, now)
+    }
+
+    pub fn check_username(
+        &self,
+        ip: &str,
+        username: &str,
+        now: Instant,
+    ) -> Result<(), TooManyAttempts> {
+        // Locked out usernames don't even get their password checked.
+        let failures = self.failures.lock().unwrap();
+        let locked_until = failures
+            .get(&lockout_key(ip, username))
+            .and_then(|f| f.locked_until);
+        if let Some(locked_until) = locked_until {
+            if locked_until > now {
+                return Err(TooManyAttempts {
+                    retry_after: locked_until - now,
+                });
+            }
+        }
+        drop(failures);
+        self.usernames.check(username, now)
+    }
+
+    pub fn record_failure(&self, ip: &str, username: &str, now: Instant) -> Option<Duration> {
+        // The lockout this failure started, if any.
+        let _ = self.usernames.take(username, now);
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() > MAX_TRACKED_KEYS {
+            failures.retain(|_, f| f.locked_until.is_some_and(|until| until > now));
+        }
+        let entry = failures
+            .entry(lockout_key(ip, username))
+            .or_insert(Failures {
+                count: 0,
+                locked_until: None,
+            });
+        entry.count += 1;
+        let over = entry.count.checked_sub(self.config.lockout_after)?;
+        let lockout = Duration::from_secs(
+            self.config
+                .lockout_secs
+                .saturating_mul(1 << over.min(20))
+                .min(self.config.max_lockout_secs),
+        );
+        entry.locked_until = Some(now + lockout);
+        Some(lockout)
+    }
+
+    pub fn record_success(&self, ip: &str, username: &str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&lockout_key(ip, username));
+    }
+}
+
+fn lockout_key(ip: &str, username: &str) -> String {
+    // IPs have no spaces, usernames might.
+    format!("{} {}", ip, username)
+}
+
+// Rate limits `LIMITED_PATHS` by IP, with the `LoginLimiter` from the app data. Usernames are
+// the handlers' business, they're in the body.
+#[derive(Default)]
+pub struct RateLimitMiddlewareFactory;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        async move {
+            if req.method() == Method::POST && LIMITED_PATHS.contains(&req.path()) {
+                let limiter = req.app_data::<web::Data<LoginLimiter>>().unwrap();
+                let ip = limiter.client_ip(req.peer_addr(), &req.connection_info());
+                if let Err(e) = limiter.check_ip(&ip, Instant::now()) {
+                    log::warn!("rate limited {} on {}", ip, req.path());
+                    return Err(e.into());
+                }
+            }
+            srv.call(req).await
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{post, App, Responder};
+
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn limiter(config: RateLimitConfig) -> LoginLimiter {
+        LoginLimiter::new(&config)
+    }
+
+    #[test]
+    fn refills_ip_buckets_over_time() {
+        // One attempt every 10 seconds once the first 3 are used up.
+        let limiter = limiter(RateLimitConfig {
+            ip_burst: 3,
+            ip_per_minute: 6,
+            ..RateLimitConfig::default()
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_ip("1.2.3.4", start).is_ok());
+        }
+        let refused = limiter.check_ip("1.2.3.4", start).unwrap_err();
+        assert_eq!(refused.retry_secs(), 10);
+        assert!(limiter.check_ip("5.6.7.8", start).is_ok());
+
+        let refused = limiter.check_ip("1.2.3.4", start + secs(4)).unwrap_err();
+        assert_eq!(refused.retry_secs(), 6);
+        assert!(limiter.check_ip("1.2.3.4", start + secs(10)).is_ok());
+        assert!(limiter.check_ip("1.2.3.4", start + secs(10)).is_err());
+        // Never more than the burst, however long it's been.
+        let later = start + secs(3600);
+        for _ in 0..3 {
+            assert!(limiter.check_ip("1.2.3.4", later).is_ok());
+        }
+        assert!(limiter.check_ip("1.2.3.4", later).is_err());
+    }
+
+    #[test]
+    fn only_failures_use_up_username_buckets() {
+        let limiter = limiter(RateLimitConfig {
+            username_burst: 2,
+            username_per_minute: 2,
+            lockout_after: 100,
+            ..RateLimitConfig::default()
+        });
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert!(limiter.check_username("1.2.3.4", "alice", start).is_ok());
+            limiter.record_success("1.2.3.4", "alice");
+        }
+
+        // From anywhere.
+        limiter.record_failure("1.2.3.4", "alice", start);
+        limiter.record_failure("5.6.7.8", "alice", start);
+        let refused = limiter
+            .check_username("9.9.9.9", "alice", start)
+            .unwrap_err();
+        assert_eq!(refused.retry_secs(), 30);
+        assert!(limiter.check_username("9.9.9.9", "bob", start).is_ok());
+        assert!(limiter
+            .check_username("9.9.9.9", "alice", start + secs(30))
+            .is_ok());
+    }
+
+    #[test]
+    fn locks_out_an_ip_from_a_username_for_longer_each_time() {
+        let limiter = limiter(RateLimitConfig {
+            username_burst: 100,
+            lockout_after: 3,
+            lockout_secs: 60,
+            max_lockout_secs: 200,
+            ..RateLimitConfig::default()
+        });
+        let start = Instant::now();
+        assert_eq!(limiter.record_failure("1.2.3.4", "alice", start), None);
+        assert_eq!(limiter.record_failure("1.2.3.4", "alice", start), None);
+        assert_eq!(
+            limiter.record_failure("1.2.3.4", "alice", start),
+            Some(secs(60))
+        );
+
+        let refused = limiter
+            .check_username("1.2.3.4", "alice", start + secs(20))
+            .unwrap_err();
+        assert_eq!(refused.retry_secs(), 40);
+        // Not from elsewhere, nor for anyone else.
+        assert!(limiter.check_username("5.6.7.8", "alice", start).is_ok());
+        assert!(limiter.check_username("1.2.3.4", "bob", start).is_ok());
+
+        let later = start + secs(60);
+        assert!(limiter.check_username("1.2.3.4", "alice", later).is_ok());
+        assert_eq!(
+            limiter.record_failure("1.2.3.4", "alice", later),
+            Some(secs(120))
+        );
+        assert_eq!(
+            limiter.record_failure("1.2.3.4", "alice", later + secs(120)),
+            Some(secs(200))
+        );
+
+        // Getting it right starts over.
+        limiter.record_success("1.2.3.4", "alice");
+        assert!(limiter.check_username("1.2.3.4", "alice", later).is_ok());
+        assert_eq!(limiter.record_failure("1.2.3.4", "alice", later), None);
+    }
+
+    #[post("/login/")]
+    async fn login() -> impl Responder {
+        HttpResponse::Ok()
+    }
+
+    #[actix_web::test]
+    async fn answers_too_many_requests_past_the_ip_limit() {
+        let limiter = web::Data::new(limiter(RateLimitConfig {
+            ip_burst: 2,
+            ip_per_minute: 1,
+            ..RateLimitConfig::default()
+        }));
+        let app = init_service(
+            App::new()
+                .app_data(limiter)
+                .wrap(RateLimitMiddlewareFactory)
+                .service(login),
+        )
+        .await;
+        let from = |ip: &str| {
+            TestRequest::post()
+                .uri("/login/")
+                .peer_addr(format!("{}:1234", ip).parse().unwrap())
+                .to_request()
+        };
+
+        for _ in 0..2 {
+            let res = call_service(&app, from("1.2.3.4")).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        let res = app.call(from("1.2.3.4")).await;
+        let res = res.unwrap_err().error_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "60");
+
+        let res = call_service(&app, from("5.6.7.8")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
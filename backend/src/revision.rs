@@ -1,62 +1,623 @@
-use std::cmp::{max, min};
+use std::cmp::{max, min, Reverse};
+use std::collections::HashSet;
 
-use common::models::{Card, RevisionCard};
-use common::FlipMode;
+use chrono::{Duration, NaiveDateTime, Utc};
+use common::models::{Card, Deck, FeedbackPayload, Media, RevisionCard};
+use common::query_params::RevisionQuery;
+use common::{CardSide, FlipMode, NewCardOrder, Rating, ReviewOrder};
 use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, SmallInt, Timestamp};
+use rand::seq::SliceRandom;
 use rand::Rng;
 
-pub fn make_revision_card(card: &Card, flip_mode: FlipMode) -> RevisionCard {
-    let flip = match flip_mode {
+use crate::search::{matches, to_tsquery_input};
+
+pub fn make_revision_card(
+    card: &Card,
+    media: &[Media],
+    deck: &Deck,
+    reverse: bool,
+) -> RevisionCard {
+    // `reverse` picks the sibling to show for decks scheduling both directions.
+    let flip = match deck.flip_mode {
         FlipMode::Front => false,
         FlipMode::Back => true,
+        FlipMode::Both if deck.reverse_siblings => reverse,
         FlipMode::Both => {
             let mut rng = rand::thread_rng();
             rng.gen()
         }
     };
+    let side_media = |side: CardSide| -> Vec<Media> {
+        media.iter().filter(|m| m.side == side).cloned().collect()
+    };
     let first;
     let second;
+    let first_media;
+    let second_media;
     if flip {
         first = card.back.clone();
         second = card.front.clone();
+        first_media = side_media(CardSide::Back);
+        second_media = side_media(CardSide::Front);
     } else {
         first = card.front.clone();
         second = card.back.clone();
+        first_media = side_media(CardSide::Front);
+        second_media = side_media(CardSide::Back);
     }
     RevisionCard {
         id: card.id,
         deck_id: card.deck_id,
+        deck_name: deck.name.clone(),
         first,
         second,
+        first_side: if flip {
+            CardSide::Back
+        } else {
+            CardSide::Front
+        },
+        first_media,
+        second_media,
     }
 }
 
-pub fn add_feedback(conn: &PgConnection, card: &mut Card, feedback: &str) {
-    // Take user's difficulty rating and change card weight accordingly.
-    use common::schema::cards::dsl::*;
+// What picking the cards of a session goes by, see `order_candidates`.
+#[derive(QueryableByName)]
+pub struct Candidate {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    // The back -> front sibling, with its own scheduling.
+    #[sql_type = "Bool"]
+    pub reverse: bool,
+    #[sql_type = "Timestamp"]
+    pub created: NaiveDateTime,
+    #[sql_type = "Nullable<Timestamp>"]
+    pub due: Option<NaiveDateTime>,
+    #[sql_type = "SmallInt"]
+    pub revision_weight: i16,
+    // How many times the card was failed outside of cram sessions, in this direction if
+    // scheduled separately.
+    #[sql_type = "BigInt"]
+    pub lapses: i64,
+}
+
+impl Candidate {
+    fn is_new(&self) -> bool {
+        // Never reviewed, or scheduling reset since.
+        self.due.is_none()
+    }
+}
+
+pub fn load_candidates(conn: &PgConnection, deck: &Deck) -> QueryResult<Vec<Candidate>> {
+    // Every card of the deck that can come up, twice over when both directions are scheduled.
+    sql_query(
+        r#"
+        SELECT cards.id, FALSE AS reverse, cards.created, cards.due, cards.revision_weight,
+            COUNT(reviews.id) FILTER (
+                WHERE reviews.rating = 'fail' AND NOT reviews.cram AND NOT ($2 AND reviews.reverse)
+            ) AS lapses
+        FROM cards
+        LEFT JOIN reviews ON reviews.card_id = cards.id
+        WHERE cards.deck_id = $1 AND NOT cards.suspended
+        GROUP BY cards.id
+        UNION ALL
+        SELECT cards.id, TRUE AS reverse, cards.created, reverse_siblings.due,
+            COALESCE(reverse_siblings.revision_weight, $3) AS revision_weight,
+            COUNT(reviews.id) FILTER (
+                WHERE reviews.rating = 'fail' AND NOT reviews.cram AND reviews.reverse
+            ) AS lapses
+        FROM cards
+        LEFT JOIN reverse_siblings ON reverse_siblings.card_id = cards.id
+        LEFT JOIN reviews ON reviews.card_id = cards.id
+        WHERE cards.deck_id = $1 AND NOT cards.suspended AND $2
+        GROUP BY cards.id, reverse_siblings.card_id;
+    "#,
+    )
+    .bind::<Integer, _>(deck.id)
+    .bind::<Bool, _>(deck.schedules_reverse())
+    .bind::<SmallInt, _>(DEFAULT_WEIGHT)
+    .load(conn)
+}
 
-    let mut weight = card.revision_weight;
-    match feedback {
-        "fail" => {
-            weight *= 4;
+fn sort_reviews(
+    mut candidates: Vec<Candidate>,
+    order: ReviewOrder,
+    now: NaiveDateTime,
+    rng: &mut impl Rng,
+) -> Vec<Candidate> {
+    // Sorts are stable, so shuffled candidates come out in random order among equals.
+    match order {
+        ReviewOrder::Weighted => {
+            let mut keyed: Vec<(f64, Candidate)> = candidates
+                .into_iter()
+                .map(|c| (rng.gen::<f64>() * f64::from(c.revision_weight), c))
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap());
+            return keyed.into_iter().map(|(_, c)| c).collect();
         }
-        "hard" => {
-            weight *= 2;
+        // New cards count as due now, behind the overdue ones.
+        ReviewOrder::Due => candidates.sort_by_key(|c| c.due.unwrap_or(now)),
+        ReviewOrder::Random => {}
+        ReviewOrder::Hardest => {
+            candidates.sort_by_key(|c| Reverse((c.lapses, c.revision_weight)));
+        }
+    }
+    candidates
+}
+
+fn interleave(new: Vec<Candidate>, reviews: Vec<Candidate>) -> Vec<Candidate> {
+    // Spread the new cards evenly among the reviews, e.g. 1 new for 3 reviews goes R R R N.
+    let total = new.len() + reviews.len();
+    let new_count = new.len();
+    let mut new = new.into_iter();
+    let mut reviews = reviews.into_iter();
+    let mut taken_new = 0;
+    let mut result = Vec::with_capacity(total);
+    for i in 0..total {
+        let next = if (taken_new + 1) * total <= (i + 1) * new_count {
+            taken_new += 1;
+            new.next()
+        } else {
+            reviews.next()
+        };
+        result.extend(next);
+    }
+    result
+}
+
+fn sort_new_by_created(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    // New cards keep the places they got among the others, but oldest first.
+    let slots: Vec<bool> = candidates.iter().map(Candidate::is_new).collect();
+    let (mut new, others): (Vec<Candidate>, Vec<Candidate>) =
+        candidates.into_iter().partition(Candidate::is_new);
+    new.sort_by_key(|c| c.created);
+    let (mut new, mut others) = (new.into_iter(), others.into_iter());
+    slots
+        .into_iter()
+        .filter_map(|is_new| if is_new { new.next() } else { others.next() })
+        .collect()
+}
+
+pub fn order_candidates(
+    mut candidates: Vec<Candidate>,
+    deck: &Deck,
+    now: NaiveDateTime,
+    rng: &mut impl Rng,
+) -> Vec<(i32, bool)> {
+    // `(card id, reverse)` for a revision session of `deck`, in the order they should be shown.
+    // Only one sibling of a card makes it into any one session.
+    let limit = max(deck.revision_length, 0) as usize;
+    candidates.shuffle(rng);
+    let mut seen = HashSet::new();
+
+    let ratio = match deck.new_card_ratio {
+        Some(ratio) => ratio.clamp(0, 100) as usize,
+        None => {
+            let mut ordered = sort_reviews(candidates, deck.review_order, now, rng);
+            if deck.new_card_order == NewCardOrder::Created {
+                ordered = sort_new_by_created(ordered);
+            }
+            ordered.retain(|c| seen.insert(c.id));
+            return ordered
+                .iter()
+                .take(limit)
+                .map(|c| (c.id, c.reverse))
+                .collect();
         }
-        "good" => {
+    };
+    let (mut new, reviews): (Vec<Candidate>, Vec<Candidate>) =
+        candidates.into_iter().partition(Candidate::is_new);
+    if deck.new_card_order == NewCardOrder::Created {
+        new.sort_by_key(|c| c.created);
+    }
+    let mut reviews = sort_reviews(reviews, deck.review_order, now, rng);
+    reviews.retain(|c| seen.insert(c.id));
+    new.retain(|c| seen.insert(c.id));
+
+    // Whatever share one side can't fill goes to the other.
+    let new_count = min((limit * ratio + 50) / 100, new.len());
+    let review_count = min(limit - new_count, reviews.len());
+    let new_count = min(limit - review_count, new.len());
+    new.truncate(new_count);
+    reviews.truncate(review_count);
+    interleave(new, reviews)
+        .iter()
+        .map(|c| (c.id, c.reverse))
+        .collect()
+}
+
+pub fn cram_card_ids(
+    conn: &PgConnection,
+    deck_id: i32,
+    query: &RevisionQuery,
+    now: NaiveDateTime,
+) -> QueryResult<Vec<i32>> {
+    // Cards of a cram session: every card of the deck matching all of the filters, due or not.
+    use common::schema::{cards, reviews};
+
+    let mut ids = cards::table
+        .filter(cards::deck_id.eq(deck_id))
+        .filter(cards::suspended.eq(false))
+        .select(cards::id)
+        .into_boxed();
+    let tags = query.tag_list();
+    if !tags.is_empty() {
+        ids = ids.filter(cards::tags.overlaps_with(tags));
+    }
+    if let Some(days) = query.failed_days {
+        let failed = reviews::table
+            .filter(reviews::rating.eq(Rating::Fail))
+            .filter(reviews::reviewed.gt(now - Duration::days(days.into())))
+            .select(reviews::card_id);
+        ids = ids.filter(cards::id.eq_any(failed));
+    }
+    if let Some(days) = query.added_days {
+        ids = ids.filter(cards::created.gt(now - Duration::days(days.into())));
+    }
+    if let Some(tsquery) = to_tsquery_input(&query.search_term) {
+        ids = ids.filter(matches(&tsquery));
+    }
+    ids.load(conn)
+}
+
+pub fn start_reverse_siblings(conn: &PgConnection, deck_id: i32) -> QueryResult<usize> {
+    // Reverse siblings for the deck's cards that don't have one yet, starting out with the
+    // card's own scheduling.
+    sql_query(
+        r#"
+        INSERT INTO reverse_siblings (card_id, revision_weight, due)
+        SELECT id, revision_weight, due
+        FROM cards
+        WHERE deck_id = $1
+        ON CONFLICT (card_id) DO NOTHING;
+    "#,
+    )
+    .bind::<Integer, _>(deck_id)
+    .execute(conn)
+}
+
+pub fn mix_queues<T>(queues: Vec<Vec<T>>) -> Vec<T> {
+    // One from each queue in turn, so that every deck keeps its own order within the mix.
+    let total = queues.iter().map(Vec::len).sum();
+    let mut queues: Vec<_> = queues.into_iter().map(Vec::into_iter).collect();
+    let mut mixed = Vec::with_capacity(total);
+    while mixed.len() < total {
+        for queue in queues.iter_mut() {
+            mixed.extend(queue.next());
+        }
+    }
+    mixed
+}
+
+// Weight a fresh card starts out with, see the `cards` migrations.
+pub const DEFAULT_WEIGHT: i16 = 100;
+
+pub fn next_due(reviewed: NaiveDateTime, weight: i16) -> NaiveDateTime {
+    // Weights below the default push the card out by whole days, so an "easy" on a fresh card
+    // (100 -> 25) comes back in four days, whereas anything at or above default is due right away.
+    let days = DEFAULT_WEIGHT / max(weight, 1);
+    reviewed + Duration::days(days.into())
+}
+
+pub fn next_weight(mut weight: i16, rating: Rating) -> i16 {
+    // Take user's difficulty rating and change card weight accordingly.
+    match rating {
+        Rating::Fail => {
+            weight = weight.saturating_mul(4);
+        }
+        Rating::Hard => {
+            weight = weight.saturating_mul(2);
+        }
+        Rating::Good => {
             weight /= 2;
         }
-        "easy" => {
+        Rating::Easy => {
             weight /= 4;
         }
-        _ => {}
     };
     weight = max(weight, 1);
-    weight = min(weight, 32767); // SMALLINT upper bound.
-    card.revision_weight = weight;
-    diesel::update(cards)
-        .filter(id.eq(card.id))
-        .set(revision_weight.eq(weight))
-        .load::<Card>(conn)
-        .unwrap();
+    min(weight, 32767) // SMALLINT upper bound.
+}
+
+// Anything longer is taken for the user having walked away, and counted as this long.
+pub const MAX_ANSWER_MS: i32 = 60 * 1000;
+
+pub fn cap_ms(ms: Option<i32>) -> Option<i32> {
+    // Negative times are the client's clock going wrong, not worth keeping.
+    ms.filter(|ms| *ms >= 0).map(|ms| min(ms, MAX_ANSWER_MS))
+}
+
+pub fn effective_rating(
+    rating: Rating,
+    reveal_ms: Option<i32>,
+    slow_answer_ms: Option<i32>,
+) -> Rating {
+    // An answer that was slow to come back wasn't that easy after all.
+    match (rating, reveal_ms, slow_answer_ms) {
+        (Rating::Easy, Some(reveal_ms), Some(slow_answer_ms)) if reveal_ms > slow_answer_ms => {
+            Rating::Good
+        }
+        _ => rating,
+    }
+}
+
+pub fn add_feedback(
+    conn: &PgConnection,
+    card: &Card,
+    deck: &Deck,
+    feedback: &FeedbackPayload,
+) -> QueryResult<()> {
+    // Reschedule the card, or its reverse sibling if that's what was shown, and log the review.
+    // Cramming only logs it.
+    use common::schema::{cards, reverse_siblings, reviews};
+
+    let now = Utc::now().naive_utc();
+    let sibling = feedback.reverse && deck.schedules_reverse();
+    let reveal_ms = cap_ms(feedback.reveal_ms);
+    let rate_ms = cap_ms(feedback.rate_ms);
+    let answer_ms = match (reveal_ms, rate_ms) {
+        (Some(reveal_ms), Some(rate_ms)) => Some(reveal_ms + rate_ms),
+        _ => cap_ms(feedback.answer_ms),
+    };
+    let rating = effective_rating(feedback.rating, reveal_ms, deck.slow_answer_ms);
+    conn.transaction(|| {
+        if feedback.cram {
+            // Nothing to reschedule.
+        } else if sibling {
+            let weight = reverse_siblings::table
+                .find(card.id)
+                .select(reverse_siblings::revision_weight)
+                .first::<i16>(conn)
+                .optional()?
+                .unwrap_or(DEFAULT_WEIGHT);
+            let weight = next_weight(weight, rating);
+            let due = next_due(now, weight);
+            diesel::insert_into(reverse_siblings::table)
+                .values((
+                    reverse_siblings::card_id.eq(card.id),
+                    reverse_siblings::revision_weight.eq(weight),
+                    reverse_siblings::due.eq(due),
+                ))
+                .on_conflict(reverse_siblings::card_id)
+                .do_update()
+                .set((
+                    reverse_siblings::revision_weight.eq(weight),
+                    reverse_siblings::due.eq(due),
+                ))
+                .execute(conn)?;
+        } else {
+            let weight = next_weight(card.revision_weight, rating);
+            diesel::update(cards::table)
+                .filter(cards::id.eq(card.id))
+                .set((
+                    cards::revision_weight.eq(weight),
+                    cards::due.eq(next_due(now, weight)),
+                ))
+                .execute(conn)?;
+        }
+        diesel::insert_into(reviews::table)
+            .values((
+                reviews::card_id.eq(card.id),
+                reviews::rating.eq(rating),
+                reviews::reviewed.eq(now),
+                reviews::answer_ms.eq(answer_ms),
+                reviews::cram.eq(feedback.cram),
+                reviews::reverse.eq(feedback.reverse),
+                reviews::reveal_ms.eq(reveal_ms),
+                reviews::rate_ms.eq(rate_ms),
+            ))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::test_utils;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 5, 20)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    fn candidate(id: i32, due_in_days: Option<i64>) -> Candidate {
+        Candidate {
+            id,
+            reverse: false,
+            // Older ids were added earlier.
+            created: now() - Duration::days(100 - i64::from(id)),
+            due: due_in_days.map(|days| now() + Duration::days(days)),
+            revision_weight: DEFAULT_WEIGHT,
+            lapses: 0,
+        }
+    }
+
+    fn deck(review_order: ReviewOrder, new_card_ratio: Option<i16>) -> Deck {
+        Deck {
+            id: 1,
+            name: "deck".to_string(),
+            user_id: 1,
+            revision_length: 4,
+            flip_mode: FlipMode::Front,
+            autoplay_front: false,
+            autoplay_back: false,
+            front_lang: None,
+            back_lang: None,
+            front_voice: None,
+            back_voice: None,
+            speech_rate: 1.,
+            review_order,
+            new_card_order: NewCardOrder::Created,
+            new_card_ratio,
+            reverse_siblings: false,
+            slow_answer_ms: None,
+        }
+    }
+
+    fn ids(candidates: &[Candidate]) -> Vec<i32> {
+        candidates.iter().map(|c| c.id).collect()
+    }
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(7)
+    }
+
+    #[test]
+    fn interleaves_new_cards_evenly() {
+        let many = |range: std::ops::Range<i32>| -> Vec<Candidate> {
+            range.map(|id| candidate(id, None)).collect()
+        };
+        assert_eq!(
+            ids(&interleave(many(0..1), many(10..13))),
+            vec![10, 11, 12, 0]
+        );
+        assert_eq!(
+            ids(&interleave(many(0..2), many(10..12))),
+            vec![10, 0, 11, 1]
+        );
+        assert_eq!(ids(&interleave(many(0..2), many(10..10))), vec![0, 1]);
+        assert_eq!(ids(&interleave(many(0..0), many(10..12))), vec![10, 11]);
+        assert!(interleave(Vec::new(), Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn sorts_reviews_by_due_date_with_new_ones_due_now() {
+        let candidates = vec![
+            candidate(1, Some(1)),
+            candidate(2, None),
+            candidate(3, Some(-1)),
+            candidate(4, Some(-2)),
+        ];
+        let sorted = sort_reviews(candidates, ReviewOrder::Due, now(), &mut rng());
+        assert_eq!(ids(&sorted), vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn sorts_reviews_hardest_first() {
+        let mut candidates: Vec<Candidate> = (1..=4).map(|id| candidate(id, Some(0))).collect();
+        candidates[0].lapses = 1;
+        candidates[1].lapses = 3;
+        candidates[2].lapses = 1;
+        candidates[2].revision_weight = 400;
+        let sorted = sort_reviews(candidates, ReviewOrder::Hardest, now(), &mut rng());
+        assert_eq!(ids(&sorted), vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn sorts_reviews_by_weight() {
+        // Weighted is random, but a card weighing nothing always loses.
+        let mut candidates: Vec<Candidate> = (1..=3).map(|id| candidate(id, Some(0))).collect();
+        candidates[0].revision_weight = 0;
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let candidates = candidates.iter().map(|c| Candidate { ..*c }).collect();
+            let sorted = sort_reviews(candidates, ReviewOrder::Weighted, now(), &mut rng);
+            assert_eq!(sorted.last().unwrap().id, 1);
+        }
+        let random: Vec<Candidate> = (1..=3).map(|id| candidate(id, Some(0))).collect();
+        let sorted = sort_reviews(random, ReviewOrder::Random, now(), &mut rng());
+        assert_eq!(ids(&sorted), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn keeps_a_share_of_the_session_for_new_cards() {
+        let mut candidates: Vec<Candidate> = (1..=4).map(|id| candidate(id, None)).collect();
+        candidates.extend((11..=14).map(|id| candidate(id, Some(-i64::from(id)))));
+        let ordered = order_candidates(
+            candidates,
+            &deck(ReviewOrder::Due, Some(50)),
+            now(),
+            &mut rng(),
+        );
+        // Two of each, the oldest new cards and the most overdue reviews.
+        assert_eq!(
+            ordered,
+            vec![(14, false), (1, false), (13, false), (2, false)]
+        );
+    }
+
+    #[test]
+    fn fills_the_session_from_the_other_side_when_one_runs_out() {
+        let mut candidates = vec![candidate(1, None)];
+        candidates.extend((11..=14).map(|id| candidate(id, Some(-i64::from(id)))));
+        let ordered = order_candidates(
+            candidates,
+            &deck(ReviewOrder::Due, Some(75)),
+            now(),
+            &mut rng(),
+        );
+        let ids: Vec<i32> = ordered.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![14, 13, 12, 1]);
+    }
+
+    #[test]
+    fn orders_new_cards_by_creation_without_a_ratio() {
+        // Random review order, yet new cards come up oldest first wherever they land.
+        let mut candidates: Vec<Candidate> = (1..=3).map(|id| candidate(id, None)).collect();
+        candidates.extend((11..=13).map(|id| candidate(id, Some(-1))));
+        let mut deck = deck(ReviewOrder::Random, None);
+        deck.revision_length = 6;
+        for seed in 0..20 {
+            let candidates = candidates.iter().map(|c| Candidate { ..*c }).collect();
+            let ordered =
+                order_candidates(candidates, &deck, now(), &mut StdRng::seed_from_u64(seed));
+            let new: Vec<i32> = ordered
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| *id < 10)
+                .collect();
+            assert_eq!(new, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn takes_one_sibling_per_card() {
+        let mut candidates: Vec<Candidate> = (1..=3).map(|id| candidate(id, Some(-1))).collect();
+        candidates.extend((1..=3).map(|id| Candidate {
+            reverse: true,
+            ..candidate(id, Some(-2))
+        }));
+        let mut deck = deck(ReviewOrder::Due, None);
+        deck.revision_length = 10;
+        // The more overdue siblings, in any order among themselves.
+        let mut ordered = order_candidates(candidates, &deck, now(), &mut rng());
+        ordered.sort_unstable();
+        assert_eq!(ordered, vec![(1, true), (2, true), (3, true)]);
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn counts_lapses_outside_of_cram_sessions() {
+        use common::schema::reviews;
+
+        let pool = test_utils::pool(&test_utils::settings());
+        let conn = pool.get().unwrap();
+        let user = test_utils::new_user(&conn);
+        let deck = test_utils::new_deck(&conn, &user);
+        let card = test_utils::new_card(&conn, &deck);
+        for cram in [false, true, true] {
+            diesel::insert_into(reviews::table)
+                .values((
+                    reviews::card_id.eq(card.id),
+                    reviews::rating.eq(Rating::Fail),
+                    reviews::reviewed.eq(now()),
+                    reviews::cram.eq(cram),
+                ))
+                .execute(&conn)
+                .unwrap();
+        }
+
+        let candidates = load_candidates(&conn, &deck).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].lapses, 1);
+    }
 }
@@ -0,0 +1,149 @@
+use common::search::{parse_search, SearchField, SearchTerm, HIGHLIGHT_START, HIGHLIGHT_STOP};
+use diesel::dsl::sql;
+use diesel::expression::bound::Bound;
+use diesel::expression::{SqlLiteral, UncheckedBind};
+use diesel::sql_types::{Bool, Float, Nullable, Text};
+
+// Text search configuration from the migrations: French stemming on top of `unaccent`.
+// The stemmer only trims endings, so conjugated forms like "allons" don't find "aller".
+const SEARCH_CONFIG: &str = "french_unaccent";
+
+// SQL fragment with a `to_tsquery(...)` call in the middle of it.
+// Every fragment needs its own bind of the query, diesel won't let us name it once.
+pub type TsqueryFragment<ST> = SqlLiteral<ST, UncheckedBind<SqlLiteral<ST>, Bound<Text, String>>>;
+
+fn with_tsquery<ST>(before: &str, tsquery: &str, after: &str) -> TsqueryFragment<ST> {
+    sql::<ST>(&format!("{}to_tsquery('{}', ", before, SEARCH_CONFIG))
+        .bind::<Text, _>(tsquery.to_string())
+        .sql(&format!("){}", after))
+}
+
+pub fn matches(tsquery: &str) -> TsqueryFragment<Bool> {
+    with_tsquery("cards.search @@ ", tsquery, "")
+}
+
+pub fn rank_desc(tsquery: &str) -> TsqueryFragment<Float> {
+    with_tsquery("ts_rank(cards.search, ", tsquery, ") DESC")
+}
+
+pub fn headline(column: &str, tsquery: &str) -> TsqueryFragment<Nullable<Text>> {
+    with_tsquery(
+        &format!("ts_headline('{}', {}, ", SEARCH_CONFIG, column),
+        tsquery,
+        &format!(
+            ", 'HighlightAll=true, StartSel=\"{}\", StopSel=\"{}\"')",
+            HIGHLIGHT_START, HIGHLIGHT_STOP
+        ),
+    )
+}
+
+fn term_to_tsquery(term: &SearchTerm) -> String {
+    // `cards.search` weighs the front as A and the back as B, so a field restriction is a label.
+    let label = match term.field {
+        Some(SearchField::Front) => "A",
+        Some(SearchField::Back) => "B",
+        None => "",
+    };
+    let last = term.words.len() - 1;
+    let lexemes: Vec<String> = term
+        .words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            // Prefix match the final word so results keep up while typing.
+            let prefix = if i == last && !term.negated { "*" } else { "" };
+            if prefix.is_empty() && label.is_empty() {
+                word.clone()
+            } else {
+                format!("{}:{}{}", word, prefix, label)
+            }
+        })
+        .collect();
+    let phrase = format!("({})", lexemes.join(" <-> "));
+    if term.negated {
+        format!("!{}", phrase)
+    } else {
+        phrase
+    }
+}
+
+pub fn to_tsquery_input(search_term: &str) -> Option<String> {
+    // Input for `to_tsquery`, built only from alphanumeric words so it can't be malformed.
+    let terms = parse_search(search_term);
+    if terms.is_empty() {
+        None
+    } else {
+        Some(
+            terms
+                .iter()
+                .map(term_to_tsquery)
+                .collect::<Vec<String>>()
+                .join(" & "),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::dsl::select;
+    use diesel::prelude::*;
+
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn turns_terms_into_tsqueries() {
+        assert_eq!(to_tsquery_input("maison").unwrap(), "(maison:*)");
+        assert_eq!(
+            to_tsquery_input(r#"front:maison back:"il fait""#).unwrap(),
+            "(maison:*A) & (il:B <-> fait:*B)"
+        );
+        assert_eq!(
+            to_tsquery_input(r#"-chat -front:"bon jour""#).unwrap(),
+            "!(chat) & !(bon:A <-> jour:A)"
+        );
+        assert_eq!(to_tsquery_input("  - \"\" &"), None);
+    }
+
+    #[test]
+    fn leaves_tsquery_operators_out() {
+        assert_eq!(
+            to_tsquery_input("a & b | !c:*").unwrap(),
+            "(a:*) & (b:*) & (c:*)"
+        );
+        assert_eq!(
+            to_tsquery_input("(x) <-> 'y' front:z\\").unwrap(),
+            "(x:*) & (y:*) & (z:*A)"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn matches_in_postgres() {
+        let pool = test_utils::pool(&test_utils::settings());
+        let conn = pool.get().unwrap();
+        let matches_text = |text: &str, search_term: &str| {
+            let tsquery = to_tsquery_input(search_term).unwrap();
+            select(
+                sql::<Bool>(&format!("to_tsvector('{}', ", SEARCH_CONFIG))
+                    .bind::<Text, _>(text)
+                    .sql(&format!(") @@ to_tsquery('{}', ", SEARCH_CONFIG))
+                    .bind::<Text, _>(tsquery)
+                    .sql(")"),
+            )
+            .get_result::<bool>(&conn)
+            .unwrap()
+        };
+
+        // Would be syntax errors if passed through as is.
+        assert!(matches_text("a b c", "a & b | !c:*"));
+        assert!(matches_text("l'été", "(ete) <-> '"));
+        assert!(matches_text("un été chaud", "ete"));
+        assert!(matches_text("les maisons", "maison"));
+        assert!(matches_text("un chat noir", r#""chat noir""#));
+        assert!(!matches_text("un noir chat", r#""chat noir""#));
+        assert!(!matches_text("un chat noir", "noir -chat"));
+        // Not a conjugation, see `SEARCH_CONFIG`.
+        assert!(!matches_text("nous allons", "aller"));
+    }
+}
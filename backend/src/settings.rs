@@ -0,0 +1,342 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::cookies::{CookieConfig, MIN_COOKIE_KEY_BYTES};
+use crate::mail::MailConfig;
+use crate::media::MediaConfig;
+use crate::oidc::OidcConfig;
+use crate::rate_limit::RateLimitConfig;
+
+// Where settings are read from unless `SETTINGS_FILE` says otherwise. Optional, everything can
+// come from the environment instead.
+const DEFAULT_SETTINGS_FILE: &str = "settings.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    // Only users already in the database, e.g. added by hand or linked to an OIDC subject.
+    Closed,
+    // Whoever logs in through the OIDC provider gets an account on their first login.
+    Open,
+}
+
+impl FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "closed" => Ok(RegistrationMode::Closed),
+            "open" => Ok(RegistrationMode::Open),
+            _ => Err("expected `closed` or `open`".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub host: String,
+    pub port: u16,
+    pub database_url: String,
+    pub max_db_connections: u32,
+    // Where users reach the app, for links in emails.
+    pub public_url: String,
+    // How long a login lasts.
+    pub session_hours: i64,
+    // Page size of card listings when the client doesn't ask for one.
+    pub per_page: i64,
+    pub cookies: CookieConfig,
+    pub media: MediaConfig,
+    pub mail: MailConfig,
+    pub rate_limit: RateLimitConfig,
+    pub oidc: Option<OidcConfig>,
+    pub registration: RegistrationMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            database_url: String::new(),
+            max_db_connections: 10,
+            public_url: "http://localhost:8080".to_string(),
+            session_hours: 36,
+            per_page: 36,
+            cookies: CookieConfig::default(),
+            media: MediaConfig::default(),
+            mail: MailConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            oidc: None,
+            registration: RegistrationMode::Closed,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env(&'static str, String),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Read(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+            SettingsError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            SettingsError::Env(var, e) => write!(f, "{}: {}", var, e),
+            SettingsError::Invalid(key, e) => write!(f, "`{}` {}", key, e),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    pub fn load() -> Result<Self, SettingsError> {
+        // The settings file if any, then the environment on top, checked before anything runs.
+        let (path, required) = match env::var("SETTINGS_FILE") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_SETTINGS_FILE), false),
+        };
+        let mut settings = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| SettingsError::Parse(path, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Settings::default(),
+            Err(e) => return Err(SettingsError::Read(path, e)),
+        };
+        settings.apply_env()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn apply_env(&mut self) -> Result<(), SettingsError> {
+        fn var<T: FromStr>(name: &'static str, target: &mut T) -> Result<(), SettingsError>
+        where
+            T::Err: fmt::Display,
+        {
+            if let Ok(value) = env::var(name) {
+                *target = value
+                    .parse()
+                    .map_err(|e: T::Err| SettingsError::Env(name, e.to_string()))?;
+            }
+            Ok(())
+        }
+
+        var("HOST", &mut self.host)?;
+        var("PORT", &mut self.port)?;
+        var("DATABASE_URL", &mut self.database_url)?;
+        var("MAX_DB_CONNECTIONS", &mut self.max_db_connections)?;
+        var("PUBLIC_URL", &mut self.public_url)?;
+        var("SESSION_HOURS", &mut self.session_hours)?;
+        var("PER_PAGE", &mut self.per_page)?;
+        if let Ok(key) = env::var("COOKIE_KEY") {
+            self.cookies.key = Some(key);
+        }
+        var("COOKIE_KEY_FILE", &mut self.cookies.key_file)?;
+        if let Ok(old_keys) = env::var("COOKIE_OLD_KEYS") {
+            self.cookies.old_keys = old_keys.split(',').map(str::to_string).collect();
+        }
+        var("COOKIE_SECURE", &mut self.cookies.secure)?;
+        var("COOKIE_HTTP_ONLY", &mut self.cookies.http_only)?;
+        var("COOKIE_SAME_SITE", &mut self.cookies.same_site)?;
+        var("MEDIA_DIR", &mut self.media.dir)?;
+        var("MAX_MEDIA_BYTES", &mut self.media.max_bytes)?;
+        var("MAIL_TRANSPORT", &mut self.mail.transport)?;
+        var("MAIL_FROM", &mut self.mail.from)?;
+        var("MAIL_DIR", &mut self.mail.dir)?;
+        var("SMTP_HOST", &mut self.mail.smtp_host)?;
+        var("SMTP_PORT", &mut self.mail.smtp_port)?;
+        if let Ok(username) = env::var("SMTP_USERNAME") {
+            self.mail.smtp_username = Some(username);
+        }
+        if let Ok(password) = env::var("SMTP_PASSWORD") {
+            self.mail.smtp_password = Some(password);
+        }
+        var("SMTP_TLS", &mut self.mail.smtp_tls)?;
+        var("TRUST_PROXY", &mut self.rate_limit.trust_proxy)?;
+        // Either turns it on, the rest can come from the settings file.
+        if let Ok(issuer_url) = env::var("OIDC_ISSUER_URL") {
+            self.oidc.get_or_insert_with(OidcConfig::default).issuer_url = issuer_url;
+        }
+        if let Ok(client_id) = env::var("OIDC_CLIENT_ID") {
+            self.oidc.get_or_insert_with(OidcConfig::default).client_id = client_id;
+        }
+        if let (Some(oidc), Ok(secret)) = (&mut self.oidc, env::var("OIDC_CLIENT_SECRET")) {
+            oidc.client_secret = Some(secret);
+        }
+        var("REGISTRATION", &mut self.registration)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), SettingsError> {
+        use SettingsError::Invalid;
+
+        if self.host.is_empty() {
+            return Err(Invalid("host", "is empty".to_string()));
+        }
+        if self.database_url.is_empty() {
+            return Err(Invalid("database_url", "is not set".to_string()));
+        }
+        if self.max_db_connections == 0 {
+            return Err(Invalid(
+                "max_db_connections",
+                "must be at least 1".to_string(),
+            ));
+        }
+        if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+            return Err(Invalid(
+                "public_url",
+                "must start with http:// or https://".to_string(),
+            ));
+        }
+        if self.session_hours <= 0 {
+            return Err(Invalid("session_hours", "must be positive".to_string()));
+        }
+        if self.per_page <= 0 {
+            return Err(Invalid("per_page", "must be positive".to_string()));
+        }
+        let mut cookie_keys = self.cookies.key.iter().chain(&self.cookies.old_keys);
+        if cookie_keys.any(|key| key.len() < MIN_COOKIE_KEY_BYTES) {
+            return Err(Invalid(
+                "cookies",
+                format!("keys must be at least {} bytes long", MIN_COOKIE_KEY_BYTES),
+            ));
+        }
+        if self.media.dir.as_os_str().is_empty() {
+            return Err(Invalid("media.dir", "is empty".to_string()));
+        }
+        if self.media.max_bytes == 0 {
+            return Err(Invalid("media.max_bytes", "must be positive".to_string()));
+        }
+        let rate_limit = &self.rate_limit;
+        let rates = [
+            rate_limit.ip_burst,
+            rate_limit.ip_per_minute,
+            rate_limit.username_burst,
+            rate_limit.username_per_minute,
+            rate_limit.lockout_after,
+        ];
+        if rates.contains(&0) || rate_limit.lockout_secs == 0 {
+            return Err(Invalid("rate_limit", "limits must be positive".to_string()));
+        }
+        if let Some(oidc) = &self.oidc {
+            if oidc.issuer_url.is_empty() || oidc.client_id.is_empty() {
+                return Err(Invalid(
+                    "oidc",
+                    "needs issuer_url and client_id".to_string(),
+                ));
+            }
+        }
+        if self.mail.smtp_username.is_some() != self.mail.smtp_password.is_some() {
+            return Err(Invalid(
+                "mail",
+                "smtp_username and smtp_password go together".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    fn valid() -> Settings {
+        Settings {
+            database_url: "postgres://localhost/anqui".to_string(),
+            ..Settings::default()
+        }
+    }
+
+    fn invalid_key(settings: &Settings) -> Option<&'static str> {
+        match settings.validate() {
+            Err(SettingsError::Invalid(key, _)) => Some(key),
+            Err(e) => panic!("{}", e),
+            Ok(()) => None,
+        }
+    }
+
+    #[test]
+    fn needs_oidc_issuer_and_client_when_on() {
+        assert_eq!(invalid_key(&valid()), None);
+        let with_oidc = |issuer_url: &str, client_id: &str| Settings {
+            oidc: Some(OidcConfig {
+                issuer_url: issuer_url.to_string(),
+                client_id: client_id.to_string(),
+                ..OidcConfig::default()
+            }),
+            ..valid()
+        };
+        assert_eq!(invalid_key(&with_oidc("", "anqui")), Some("oidc"));
+        assert_eq!(
+            invalid_key(&with_oidc("https://id.example.com", "")),
+            Some("oidc")
+        );
+        assert_eq!(
+            invalid_key(&with_oidc("https://id.example.com", "anqui")),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_registration_modes() {
+        assert_eq!("open".parse(), Ok(RegistrationMode::Open));
+        assert!("invite".parse::<RegistrationMode>().is_err());
+        assert!(toml::from_str::<Settings>("registration = \"invite\"").is_err());
+        let settings: Settings = toml::from_str("registration = \"open\"").unwrap();
+        assert_eq!(settings.registration, RegistrationMode::Open);
+    }
+
+    // The only test touching the environment, so nothing else sees these variables.
+    #[test]
+    fn lets_the_environment_override_the_file() {
+        let path = env::temp_dir().join(test_utils::unique_name("settings"));
+        fs::write(
+            &path,
+            "port = 9000\n\
+             per_page = 20\n\
+             database_url = \"postgres://localhost/anqui\"\n\
+             registration = \"open\"\n",
+        )
+        .unwrap();
+        env::set_var("SETTINGS_FILE", &path);
+
+        env::set_var("PORT", "9100");
+        let settings = Settings::load().unwrap();
+        assert_eq!(settings.port, 9100);
+        assert_eq!(settings.per_page, 20);
+        assert_eq!(settings.registration, RegistrationMode::Open);
+
+        env::set_var("REGISTRATION", "invite");
+        assert!(matches!(
+            Settings::load(),
+            Err(SettingsError::Env("REGISTRATION", _))
+        ));
+        env::set_var("REGISTRATION", "closed");
+        assert_eq!(
+            Settings::load().unwrap().registration,
+            RegistrationMode::Closed
+        );
+
+        // Switched on from the environment, but only halfway.
+        env::set_var("OIDC_CLIENT_ID", "anqui");
+        assert!(matches!(
+            Settings::load(),
+            Err(SettingsError::Invalid("oidc", _))
+        ));
+
+        for var in ["SETTINGS_FILE", "PORT", "REGISTRATION", "OIDC_CLIENT_ID"] {
+            env::remove_var(var);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,541 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone};
+use common::models::Review;
+use common::stats::{Activity, AnswerTimes, DayCount, RatingCounts, RetentionBucket, Stats};
+use common::Rating;
+
+// How many days the per-day series look back (or ahead, for the forecast).
+pub const STATS_WINDOW_DAYS: i64 = 30;
+
+// How far back the activity heatmap goes, a full year of weeks.
+pub const ACTIVITY_WINDOW_DAYS: i64 = 53 * 7;
+
+// How many of the slowest cards the stats list.
+pub const SLOWEST_CARDS: usize = 10;
+
+// Upper bounds (inclusive, in days) of the retention interval buckets.
+const RETENTION_BUCKETS: [(i64, &str); 5] = [
+    (0, "<1j"),
+    (1, "1j"),
+    (7, "2-7j"),
+    (30, "8-30j"),
+    (i64::MAX, ">30j"),
+];
+
+pub fn compute_stats<Tz: TimeZone>(
+    reviews: &[Review],
+    cards: &[(NaiveDateTime, Option<NaiveDateTime>)],
+    tz: &Tz,
+    now: NaiveDateTime,
+) -> Stats {
+    // Days are the user's local ones, like for `compute_activity`.
+    let today = local_date(&now, tz);
+    let first_day = today - Duration::days(STATS_WINDOW_DAYS - 1);
+    let past_days: Vec<NaiveDate> = (0..STATS_WINDOW_DAYS)
+        .map(|offset| first_day + Duration::days(offset))
+        .collect();
+    let next_days: Vec<NaiveDate> = (0..STATS_WINDOW_DAYS)
+        .map(|offset| today + Duration::days(offset))
+        .collect();
+
+    let reviews_per_day = count_per_day(
+        &past_days,
+        reviews.iter().map(|r| local_date(&r.reviewed, tz)),
+    );
+    let cards_added_per_day =
+        count_per_day(&past_days, cards.iter().map(|(c, _)| local_date(c, tz)));
+    // Overdue cards are folded into today, unseen cards have no due date at all.
+    let due_forecast = count_per_day(
+        &next_days,
+        cards
+            .iter()
+            .filter_map(|(_, due)| due.map(|due| std::cmp::max(local_date(&due, tz), today))),
+    );
+
+    // Cram sessions don't count towards how well cards are known, only towards activity.
+    let scheduled: Vec<&Review> = reviews.iter().filter(|r| !r.cram).collect();
+    let mut ratings = RatingCounts {
+        fail: 0,
+        hard: 0,
+        good: 0,
+        easy: 0,
+    };
+    for review in &scheduled {
+        match review.rating {
+            Rating::Fail => ratings.fail += 1,
+            Rating::Hard => ratings.hard += 1,
+            Rating::Good => ratings.good += 1,
+            Rating::Easy => ratings.easy += 1,
+        }
+    }
+
+    Stats {
+        reviews_per_day,
+        ratings,
+        retention: retention_by_interval(&scheduled),
+        average_answer_ms: average(scheduled.iter().filter_map(|r| r.answer_ms)),
+        average_reveal_ms: average(scheduled.iter().filter_map(|r| r.reveal_ms)),
+        // Filled in by `answer_times`, which needs to know about decks and cards.
+        deck_times: Vec::new(),
+        card_times: Vec::new(),
+        cards_added_per_day,
+        due_forecast,
+    }
+}
+
+fn average(values: impl Iterator<Item = i32>) -> Option<i64> {
+    let values: Vec<i64> = values.map(i64::from).collect();
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<i64>() / values.len() as i64)
+    }
+}
+
+pub fn answer_times<'a>(
+    reviews: &[Review],
+    group: impl Fn(&Review) -> Option<(i32, &'a str)>,
+) -> Vec<AnswerTimes> {
+    // Average times for each `(id, label)` that `group` puts reviews under, slowest first.
+    // Cram sessions go through cards already seen, so they're left out like for ratings.
+    let mut grouped: BTreeMap<i32, (&str, Vec<&Review>)> = BTreeMap::new();
+    for review in reviews.iter().filter(|r| !r.cram) {
+        if let Some((id, label)) = group(review) {
+            grouped
+                .entry(id)
+                .or_insert_with(|| (label, Vec::new()))
+                .1
+                .push(review);
+        }
+    }
+    let mut times: Vec<AnswerTimes> = grouped
+        .into_values()
+        .map(|(label, reviews)| AnswerTimes {
+            label: label.to_string(),
+            reviews: reviews.len() as i64,
+            average_reveal_ms: average(reviews.iter().filter_map(|r| r.reveal_ms)),
+            average_answer_ms: average(reviews.iter().filter_map(|r| r.answer_ms)),
+        })
+        .collect();
+    times.sort_by_key(|t| Reverse(t.average_answer_ms));
+    times
+}
+
+fn count_per_day(days: &[NaiveDate], dates: impl Iterator<Item = NaiveDate>) -> Vec<DayCount> {
+    let mut counts: HashMap<NaiveDate, i64> = HashMap::new();
+    for date in dates {
+        *counts.entry(date).or_insert(0) += 1;
+    }
+    days.iter()
+        .map(|date| DayCount {
+            date: *date,
+            count: counts.get(date).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+fn retention_by_interval(reviews: &[&Review]) -> Vec<RetentionBucket> {
+    // Retention for a review is whether the card was recalled (anything but "fail"),
+    // bucketed by how long it had been since the previous review of the same card
+    // in the same direction, a reverse review doesn't refresh the forward side.
+    let mut sorted = reviews.to_vec();
+    sorted.sort_by_key(|r| (r.card_id, r.reverse, r.reviewed));
+
+    let mut buckets: Vec<RetentionBucket> = RETENTION_BUCKETS
+        .iter()
+        .map(|(_, label)| RetentionBucket {
+            label: label.to_string(),
+            reviews: 0,
+            passed: 0,
+        })
+        .collect();
+    for pair in sorted.windows(2) {
+        let (previous, review) = (pair[0], pair[1]);
+        if previous.card_id != review.card_id || previous.reverse != review.reverse {
+            continue;
+        }
+        let interval = (review.reviewed - previous.reviewed).num_days();
+        let index = RETENTION_BUCKETS
+            .iter()
+            .position(|(upper, _)| interval <= *upper)
+            .unwrap();
+        buckets[index].reviews += 1;
+        if review.rating != Rating::Fail {
+            buckets[index].passed += 1;
+        }
+    }
+    buckets
+}
+
+pub fn local_date<Tz: TimeZone>(utc: &NaiveDateTime, tz: &Tz) -> NaiveDate {
+    // Timestamps are stored as naive UTC, the day they count towards is the user's local one.
+    tz.from_utc_datetime(utc).naive_local().date()
+}
+
+pub fn compute_activity<Tz: TimeZone>(
+    reviewed: &[NaiveDateTime],
+    tz: &Tz,
+    now: NaiveDateTime,
+) -> Activity {
+    let today = local_date(&now, tz);
+    let dates: Vec<NaiveDate> = reviewed.iter().map(|r| local_date(r, tz)).collect();
+    let first_day = today - Duration::days(ACTIVITY_WINDOW_DAYS - 1);
+    let window: Vec<NaiveDate> = (0..ACTIVITY_WINDOW_DAYS)
+        .map(|offset| first_day + Duration::days(offset))
+        .collect();
+
+    let active: BTreeSet<NaiveDate> = dates.iter().copied().collect();
+    let (current_streak, longest_streak) = streaks(&active, today);
+    Activity {
+        days: count_per_day(&window, dates.into_iter()),
+        current_streak,
+        longest_streak,
+    }
+}
+
+pub fn streaks(active: &BTreeSet<NaiveDate>, today: NaiveDate) -> (i64, i64) {
+    // Returns `(current, longest)` runs of consecutive active days.
+    // Not having revised yet today doesn't break the current streak, only missing yesterday does.
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for date in active.iter().filter(|date| **date <= today) {
+        run = match previous {
+            Some(previous) if *date - previous == Duration::days(1) => run + 1,
+            _ => 1,
+        };
+        longest = std::cmp::max(longest, run);
+        previous = Some(*date);
+    }
+
+    let current = match previous {
+        Some(last) if last >= today - Duration::days(1) => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use chrono_tz::{Europe::Paris, UTC};
+
+    use super::*;
+
+    fn at(date: (i32, u32, u32), time: (u32, u32)) -> NaiveDateTime {
+        // A UTC timestamp, as stored.
+        NaiveDate::from_ymd_opt(date.0, date.1, date.2)
+            .unwrap()
+            .and_hms_opt(time.0, time.1, 0)
+            .unwrap()
+    }
+
+    fn review(card_id: i32, rating: Rating, reviewed: NaiveDateTime) -> Review {
+        Review {
+            id: 0,
+            card_id,
+            rating,
+            reviewed,
+            answer_ms: None,
+            cram: false,
+            reverse: false,
+            reveal_ms: None,
+            rate_ms: None,
+        }
+    }
+
+    fn count_on(days: &[DayCount], date: (i32, u32, u32)) -> i64 {
+        let date = NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap();
+        days.iter().find(|day| day.date == date).unwrap().count
+    }
+
+    #[test]
+    fn buckets_days_in_the_users_timezone() {
+        // 23:30 UTC is already the next day in Paris, where it's 00:30 (UTC+1 in winter).
+        let late = at((2026, 1, 14), (23, 30));
+        let now = at((2026, 1, 15), (12, 0));
+        let reviews = [review(1, Rating::Good, late)];
+        let cards = [(late, Some(at((2026, 1, 20), (23, 30))))];
+
+        let stats = compute_stats(&reviews, &cards, &Paris, now);
+        assert_eq!(count_on(&stats.reviews_per_day, (2026, 1, 15)), 1);
+        assert_eq!(count_on(&stats.reviews_per_day, (2026, 1, 14)), 0);
+        assert_eq!(count_on(&stats.cards_added_per_day, (2026, 1, 15)), 1);
+        assert_eq!(count_on(&stats.due_forecast, (2026, 1, 21)), 1);
+
+        let stats = compute_stats(&reviews, &cards, &UTC, now);
+        assert_eq!(count_on(&stats.reviews_per_day, (2026, 1, 14)), 1);
+        assert_eq!(count_on(&stats.due_forecast, (2026, 1, 20)), 1);
+    }
+
+    #[test]
+    fn folds_overdue_cards_into_the_users_today() {
+        // 23:30 in Paris, still the 15th there.
+        let now = at((2026, 1, 15), (22, 30));
+        let cards = [
+            (at((2026, 1, 1), (0, 0)), Some(at((2026, 1, 10), (8, 0)))),
+            (at((2026, 1, 1), (0, 0)), None),
+        ];
+        let stats = compute_stats(&[], &cards, &Paris, now);
+        assert_eq!(
+            stats.due_forecast[0].date,
+            NaiveDate::from_ymd_opt(2026, 1, 15).unwrap()
+        );
+        assert_eq!(stats.due_forecast[0].count, 1);
+        assert_eq!(
+            stats.due_forecast.iter().map(|day| day.count).sum::<i64>(),
+            1
+        );
+    }
+
+    fn local(date: (i32, u32, u32), time: (u32, u32)) -> NaiveDateTime {
+        // A Paris wall clock time, as the UTC timestamp stored for it.
+        Paris
+            .from_local_datetime(&at(date, time))
+            .earliest()
+            .unwrap()
+            .naive_utc()
+    }
+
+    fn day(date: (i32, u32, u32)) -> NaiveDate {
+        NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap()
+    }
+
+    #[test]
+    fn activity_days_end_at_local_midnight() {
+        // (reviews in Paris time, now in Paris time, day expected to hold each review)
+        let cases = [
+            // Either side of midnight, in winter (UTC+1) and summer (UTC+2).
+            (
+                vec![((2026, 1, 14), (23, 59))],
+                ((2026, 1, 15), (12, 0)),
+                vec![(2026, 1, 14)],
+            ),
+            (
+                vec![((2026, 1, 15), (0, 1))],
+                ((2026, 1, 15), (12, 0)),
+                vec![(2026, 1, 15)],
+            ),
+            (
+                vec![((2026, 7, 14), (23, 59))],
+                ((2026, 7, 15), (12, 0)),
+                vec![(2026, 7, 14)],
+            ),
+            (
+                vec![((2026, 7, 15), (0, 1))],
+                ((2026, 7, 15), (12, 0)),
+                vec![(2026, 7, 15)],
+            ),
+            // Spring forward, 2026-03-29 has no 02:00-03:00 and only 23 hours.
+            (
+                vec![
+                    ((2026, 3, 29), (0, 30)),
+                    ((2026, 3, 29), (3, 30)),
+                    ((2026, 3, 29), (23, 59)),
+                ],
+                ((2026, 3, 30), (0, 1)),
+                vec![(2026, 3, 29), (2026, 3, 29), (2026, 3, 29)],
+            ),
+            // Fall back, 2026-10-25 goes through 02:00-03:00 twice and has 25 hours.
+            (
+                vec![
+                    ((2026, 10, 25), (0, 30)),
+                    ((2026, 10, 25), (2, 30)),
+                    ((2026, 10, 25), (23, 59)),
+                ],
+                ((2026, 10, 26), (0, 1)),
+                vec![(2026, 10, 25), (2026, 10, 25), (2026, 10, 25)],
+            ),
+        ];
+        for (reviews, now, expected) in cases {
+            let reviewed: Vec<NaiveDateTime> = reviews
+                .iter()
+                .map(|&(date, time)| local(date, time))
+                .collect();
+            let activity = compute_activity(&reviewed, &Paris, local(now.0, now.1));
+            assert_eq!(
+                activity.days.last().unwrap().date,
+                day(now.0),
+                "{:?}",
+                reviews
+            );
+            for date in &expected {
+                let count = expected.iter().filter(|other| *other == date).count() as i64;
+                assert_eq!(count_on(&activity.days, *date), count, "{:?}", reviews);
+            }
+            let total: i64 = activity.days.iter().map(|day| day.count).sum();
+            assert_eq!(total, reviews.len() as i64, "{:?}", reviews);
+        }
+    }
+
+    #[test]
+    fn streaks_run_through_clock_changes() {
+        // Four days in a row, the spring-forward or fall-back day among them, each at 00:30 local.
+        for (first, now) in [
+            ((2026, 3, 27), (2026, 3, 30)),
+            ((2026, 10, 23), (2026, 10, 26)),
+        ] {
+            let reviewed: Vec<NaiveDateTime> = (0..4)
+                .map(|offset| {
+                    let date = day(first) + Duration::days(offset);
+                    Paris
+                        .from_local_datetime(&date.and_hms_opt(0, 30, 0).unwrap())
+                        .unwrap()
+                        .naive_utc()
+                })
+                .collect();
+            let activity = compute_activity(&reviewed, &Paris, local(now, (23, 0)));
+            assert_eq!(
+                (activity.current_streak, activity.longest_streak),
+                (4, 4),
+                "{:?}",
+                first
+            );
+        }
+    }
+
+    #[test]
+    fn current_streak_ends_after_a_missed_day() {
+        let today = (2026, 5, 20);
+        // (active days, expected (current, longest))
+        let cases = [
+            (vec![(2026, 5, 18), (2026, 5, 19), (2026, 5, 20)], (3, 3)),
+            // Not revised yet today, the streak still stands.
+            (vec![(2026, 5, 18), (2026, 5, 19)], (2, 2)),
+            // Missed yesterday, it's over.
+            (vec![(2026, 5, 17), (2026, 5, 18)], (0, 2)),
+            (
+                vec![(2026, 5, 10), (2026, 5, 11), (2026, 5, 12), (2026, 5, 20)],
+                (1, 3),
+            ),
+            (vec![], (0, 0)),
+            // Ahead of today, e.g. a client clock running fast, doesn't count.
+            (vec![(2026, 5, 20), (2026, 5, 21)], (1, 1)),
+        ];
+        for (active, expected) in cases {
+            let active: BTreeSet<NaiveDate> = active.into_iter().map(day).collect();
+            assert_eq!(streaks(&active, day(today)), expected, "{:?}", active);
+        }
+    }
+
+    #[test]
+    fn leaves_cram_reviews_out_of_ratings_and_retention() {
+        let now = at((2026, 1, 15), (12, 0));
+        let cram = |rating, reviewed| Review {
+            cram: true,
+            ..review(1, rating, reviewed)
+        };
+        let reviews = [
+            review(1, Rating::Good, at((2026, 1, 10), (8, 0))),
+            cram(Rating::Fail, at((2026, 1, 11), (8, 0))),
+            cram(Rating::Fail, at((2026, 1, 12), (8, 0))),
+            review(1, Rating::Good, at((2026, 1, 14), (8, 0))),
+        ];
+
+        let stats = compute_stats(&reviews, &[], &UTC, now);
+        assert_eq!(stats.ratings.good, 2);
+        assert_eq!(stats.ratings.fail, 0);
+        // Four days between the two scheduled reviews, the cram ones in between don't matter.
+        let retained: Vec<(&str, i64, i64)> = stats
+            .retention
+            .iter()
+            .filter(|bucket| bucket.reviews > 0)
+            .map(|bucket| (bucket.label.as_str(), bucket.reviews, bucket.passed))
+            .collect();
+        assert_eq!(retained, vec![("2-7j", 1, 1)]);
+        // They're still activity.
+        assert_eq!(count_on(&stats.reviews_per_day, (2026, 1, 11)), 1);
+    }
+
+    #[test]
+    fn pairs_retention_reviews_by_direction() {
+        let now = at((2026, 1, 15), (12, 0));
+        let reverse = |rating, reviewed| Review {
+            reverse: true,
+            ..review(1, rating, reviewed)
+        };
+        let reviews = [
+            review(1, Rating::Good, at((2026, 1, 1), (8, 0))),
+            reverse(Rating::Fail, at((2026, 1, 2), (8, 0))),
+            reverse(Rating::Good, at((2026, 1, 3), (8, 0))),
+            review(1, Rating::Good, at((2026, 1, 10), (8, 0))),
+        ];
+
+        let stats = compute_stats(&reviews, &[], &UTC, now);
+        // Nine days between the forward reviews and one between the reverse ones.
+        let retained: Vec<(&str, i64, i64)> = stats
+            .retention
+            .iter()
+            .filter(|bucket| bucket.reviews > 0)
+            .map(|bucket| (bucket.label.as_str(), bucket.reviews, bucket.passed))
+            .collect();
+        assert_eq!(retained, vec![("1j", 1, 1), ("8-30j", 1, 1)]);
+    }
+
+    fn timed(card_id: i32, reveal_ms: i32, answer_ms: i32, cram: bool) -> Review {
+        Review {
+            reveal_ms: Some(reveal_ms),
+            answer_ms: Some(answer_ms),
+            cram,
+            ..review(card_id, Rating::Good, at((2026, 1, 14), (8, 0)))
+        }
+    }
+
+    #[test]
+    fn averages_answer_times_outside_of_cram() {
+        let now = at((2026, 1, 15), (12, 0));
+        let reviews = [
+            timed(1, 1000, 2000, false),
+            timed(2, 3000, 5000, false),
+            timed(2, 60_000, 90_000, true),
+            review(3, Rating::Good, at((2026, 1, 14), (9, 0))),
+        ];
+        let stats = compute_stats(&reviews, &[], &UTC, now);
+        assert_eq!(stats.average_reveal_ms, Some(2000));
+        assert_eq!(stats.average_answer_ms, Some(3500));
+
+        let stats = compute_stats(&reviews[3..], &[], &UTC, now);
+        assert_eq!(stats.average_answer_ms, None);
+    }
+
+    #[test]
+    fn groups_answer_times_slowest_first() {
+        let reviews = [
+            timed(1, 1000, 2000, false),
+            timed(1, 1000, 4000, false),
+            timed(2, 2000, 9000, false),
+            timed(3, 2000, 1000, false),
+            timed(3, 90_000, 90_000, true),
+            timed(4, 90_000, 90_000, true),
+            timed(5, 1000, 1000, false),
+        ];
+        let labels = ["", "un", "deux", "trois", "quatre"];
+        let times = answer_times(&reviews, |r| {
+            labels
+                .get(r.card_id as usize)
+                .map(|label| (r.card_id, *label))
+        });
+        let summary: Vec<(&str, i64, Option<i64>, Option<i64>)> = times
+            .iter()
+            .map(|t| {
+                (
+                    t.label.as_str(),
+                    t.reviews,
+                    t.average_reveal_ms,
+                    t.average_answer_ms,
+                )
+            })
+            .collect();
+        // Card 4 was only crammed and card 5 has no label.
+        assert_eq!(
+            summary,
+            vec![
+                ("deux", 1, Some(2000), Some(9000)),
+                ("un", 2, Some(1000), Some(3000)),
+                ("trois", 1, Some(2000), Some(1000)),
+            ]
+        );
+    }
+}
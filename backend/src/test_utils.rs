@@ -0,0 +1,285 @@
+// For tests needing a database: a migrated one at `TEST_DATABASE_URL`. They're `#[ignore]`d so
+// a plain `cargo test` doesn't need one, run them with `cargo test -- --ignored`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use bcrypt::hash;
+use chrono::{Duration, Utc};
+use common::models::{Card, Deck, User};
+use common::{FlipMode, TokenScope};
+use diesel::prelude::*;
+use openidconnect::core::{
+    CoreIdToken, CoreIdTokenClaims, CoreJwsSigningAlgorithm, CoreRsaPrivateSigningKey,
+};
+use openidconnect::url::Url;
+use openidconnect::{
+    Audience, EmptyAdditionalClaims, EndUserUsername, IssuerUrl, JsonWebKeyId, Nonce,
+    PkceCodeChallenge, PkceCodeVerifier, PrivateSigningKey, StandardClaims, SubjectIdentifier,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::hash_token;
+use crate::db::{new_db_pool, DbPool};
+use crate::oidc::OidcConfig;
+use crate::settings::Settings;
+
+pub const PASSWORD: &str = "mot de passe";
+
+pub fn settings() -> Settings {
+    Settings {
+        database_url: std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set"),
+        max_db_connections: 2,
+        ..Settings::default()
+    }
+}
+
+pub fn pool(settings: &Settings) -> DbPool {
+    new_db_pool(settings)
+}
+
+pub fn unique_name(prefix: &str) -> String {
+    // Tests share the database and run side by side.
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect();
+    format!("{}-{}", prefix, suffix.to_lowercase())
+}
+
+pub fn new_user(conn: &PgConnection) -> User {
+    use common::schema::users::dsl::*;
+
+    // Lowest bcrypt cost, tests log in a lot.
+    diesel::insert_into(users)
+        .values((
+            username.eq(unique_name("test")),
+            password.eq(hash(PASSWORD, 4).unwrap()),
+        ))
+        .get_result(conn)
+        .unwrap()
+}
+
+pub fn new_deck(conn: &PgConnection, user: &User) -> Deck {
+    use common::schema::decks;
+
+    diesel::insert_into(decks::table)
+        .values((
+            decks::name.eq(unique_name("deck")),
+            decks::user_id.eq(user.id),
+            decks::flip_mode.eq(FlipMode::Front),
+        ))
+        .get_result(conn)
+        .unwrap()
+}
+
+pub fn new_card(conn: &PgConnection, deck: &Deck) -> Card {
+    use common::schema::cards;
+
+    diesel::insert_into(cards::table)
+        .values((
+            cards::front.eq("front"),
+            cards::back.eq("back"),
+            cards::deck_id.eq(deck.id),
+        ))
+        .get_result(conn)
+        .unwrap()
+}
+
+pub fn new_api_token(conn: &PgConnection, user: &User) -> String {
+    // Read-write on every deck, for going past `AuthenticateMiddleware` without a cookie.
+    use common::schema::api_tokens;
+
+    let token = unique_name("anq");
+    diesel::insert_into(api_tokens::table)
+        .values((
+            api_tokens::user_id.eq(user.id),
+            api_tokens::name.eq("test"),
+            api_tokens::token_hash.eq(hash_token(&token)),
+            api_tokens::scope.eq(TokenScope::ReadWrite),
+            api_tokens::created.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+        .unwrap();
+    token
+}
+
+// Signs the mock provider's ID tokens, made for these tests only.
+const OIDC_KEY: &str = include_str!("../test_data/oidc_key.pem");
+pub const OIDC_CLIENT_ID: &str = "anqui";
+
+// What a user agreed to at the provider, until the code gets exchanged.
+struct Grant {
+    client_id: String,
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: String,
+    subject: String,
+    preferred_username: String,
+}
+
+struct ProviderState {
+    issuer: String,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+// An OpenID Connect provider on a local port, with discovery, keys and a token endpoint. Its
+// authorization step is `authorize`, as if the user went there and agreed.
+pub struct OidcProvider {
+    state: web::Data<ProviderState>,
+}
+
+impl OidcProvider {
+    pub async fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let state = web::Data::new(ProviderState {
+            issuer,
+            grants: Mutex::new(HashMap::new()),
+        });
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery),
+                )
+                .route("/jwks", web::get().to(jwks))
+                .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        OidcProvider { state }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.state.issuer
+    }
+
+    pub fn config(&self) -> OidcConfig {
+        OidcConfig {
+            issuer_url: self.state.issuer.clone(),
+            client_id: OIDC_CLIENT_ID.to_string(),
+            ..OidcConfig::default()
+        }
+    }
+
+    pub fn authorize(&self, url: &str, subject: &str, preferred_username: &str) -> Url {
+        // Where the provider would send the user back to, with a code for them.
+        let url = Url::parse(url).unwrap();
+        assert!(url
+            .as_str()
+            .starts_with(&format!("{}/authorize", self.state.issuer)));
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_else(|| panic!("no `{}` in the authorization request", name))
+        };
+        assert_eq!(param("response_type"), "code");
+        assert_eq!(param("code_challenge_method"), "S256");
+        let code = unique_name("code");
+        let mut redirect = Url::parse(&param("redirect_uri")).unwrap();
+        redirect
+            .query_pairs_mut()
+            .append_pair("state", &param("state"))
+            .append_pair("code", &code);
+        self.state.grants.lock().unwrap().insert(
+            code,
+            Grant {
+                client_id: param("client_id"),
+                redirect_uri: param("redirect_uri"),
+                code_challenge: param("code_challenge"),
+                nonce: param("nonce"),
+                subject: subject.to_string(),
+                preferred_username: preferred_username.to_string(),
+            },
+        );
+        redirect
+    }
+}
+
+async fn discovery(state: web::Data<ProviderState>) -> impl Responder {
+    let issuer = &state.issuer;
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "jwks_uri": format!("{}/jwks", issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+    }))
+}
+
+fn signing_key() -> CoreRsaPrivateSigningKey {
+    CoreRsaPrivateSigningKey::from_pem(OIDC_KEY, Some(JsonWebKeyId::new("test".to_string())))
+        .unwrap()
+}
+
+async fn jwks() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "keys": [signing_key().as_verification_key()] }))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    code_verifier: String,
+    client_id: Option<String>,
+}
+
+async fn token(state: web::Data<ProviderState>, form: web::Form<TokenRequest>) -> impl Responder {
+    // Codes are single use, and only good with the verifier for their challenge.
+    let grant = state.grants.lock().unwrap().remove(&form.code);
+    let grant = match grant {
+        Some(grant) if form.grant_type == "authorization_code" => grant,
+        _ => return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" })),
+    };
+    let verifier = PkceCodeVerifier::new(form.code_verifier.clone());
+    let challenge = PkceCodeChallenge::from_code_verifier_sha256(&verifier);
+    if challenge.as_str() != grant.code_challenge
+        || form.redirect_uri != grant.redirect_uri
+        || form
+            .client_id
+            .as_ref()
+            .is_some_and(|id| *id != grant.client_id)
+    {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+
+    let now = Utc::now();
+    let claims = CoreIdTokenClaims::new(
+        IssuerUrl::new(state.issuer.clone()).unwrap(),
+        vec![Audience::new(grant.client_id)],
+        now + Duration::minutes(5),
+        now,
+        StandardClaims::new(SubjectIdentifier::new(grant.subject))
+            .set_preferred_username(Some(EndUserUsername::new(grant.preferred_username))),
+        EmptyAdditionalClaims {},
+    )
+    .set_nonce(Some(Nonce::new(grant.nonce)));
+    let id_token = CoreIdToken::new(
+        claims,
+        &signing_key(),
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+        None,
+        None,
+    )
+    .unwrap();
+    HttpResponse::Ok().json(json!({
+        "access_token": unique_name("access"),
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+}
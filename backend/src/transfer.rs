@@ -0,0 +1,188 @@
+use chrono::{NaiveDateTime, Utc};
+use common::models::{Card, TransferMode};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Integer;
+
+use crate::revision::DEFAULT_WEIGHT;
+
+pub fn transfer_cards(
+    conn: &PgConnection,
+    card_ids: &[i32],
+    target_deck_id: i32,
+    mode: TransferMode,
+    keep_history: bool,
+) -> QueryResult<Vec<Card>> {
+    // Move or copy cards into `target_deck_id`, whose ownership the caller checked. Without
+    // `keep_history` the cards come out as new: default weight, not due, no past reviews.
+    use common::schema::{cards, reverse_siblings, reviews};
+
+    conn.transaction(|| match mode {
+        TransferMode::Move => {
+            let target = cards::table.filter(cards::id.eq_any(card_ids));
+            if keep_history {
+                diesel::update(target)
+                    .set(cards::deck_id.eq(target_deck_id))
+                    .get_results::<Card>(conn)
+            } else {
+                diesel::delete(reviews::table.filter(reviews::card_id.eq_any(card_ids)))
+                    .execute(conn)?;
+                diesel::delete(
+                    reverse_siblings::table.filter(reverse_siblings::card_id.eq_any(card_ids)),
+                )
+                .execute(conn)?;
+                diesel::update(target)
+                    .set((
+                        cards::deck_id.eq(target_deck_id),
+                        cards::revision_weight.eq(DEFAULT_WEIGHT),
+                        cards::due.eq(None::<NaiveDateTime>),
+                    ))
+                    .get_results::<Card>(conn)
+            }
+        }
+        TransferMode::Copy => {
+            let originals = cards::table
+                .filter(cards::id.eq_any(card_ids))
+                .order_by(cards::id)
+                .load::<Card>(conn)?;
+            let now = Utc::now().naive_utc();
+            let mut copies = Vec::with_capacity(originals.len());
+            for original in originals {
+                let (revision_weight, due) = if keep_history {
+                    (original.revision_weight, original.due)
+                } else {
+                    (DEFAULT_WEIGHT, None)
+                };
+                let copy: Card = diesel::insert_into(cards::table)
+                    .values((
+                        cards::deck_id.eq(target_deck_id),
+                        cards::front.eq(&original.front),
+                        cards::back.eq(&original.back),
+                        cards::revision_weight.eq(revision_weight),
+                        cards::created.eq(now),
+                        cards::due.eq(due),
+                        cards::tags.eq(&original.tags),
+                        cards::suspended.eq(original.suspended),
+                    ))
+                    .get_result(conn)?;
+                // The files are shared by hash, only the rows pointing at them get copied.
+                sql_query(
+                    r#"
+                    INSERT INTO media (card_id, side, hash, content_type, size)
+                    SELECT $1, side, hash, content_type, size
+                    FROM media
+                    WHERE card_id = $2;
+                "#,
+                )
+                .bind::<Integer, _>(copy.id)
+                .bind::<Integer, _>(original.id)
+                .execute(conn)?;
+                if keep_history {
+                    sql_query(
+                        r#"
+                        INSERT INTO reviews (
+                            card_id, rating, reviewed, answer_ms, cram, reverse, reveal_ms, rate_ms
+                        )
+                        SELECT $1, rating, reviewed, answer_ms, cram, reverse, reveal_ms, rate_ms
+                        FROM reviews
+                        WHERE card_id = $2;
+                    "#,
+                    )
+                    .bind::<Integer, _>(copy.id)
+                    .bind::<Integer, _>(original.id)
+                    .execute(conn)?;
+                    sql_query(
+                        r#"
+                        INSERT INTO reverse_siblings (card_id, revision_weight, due)
+                        SELECT $1, revision_weight, due
+                        FROM reverse_siblings
+                        WHERE card_id = $2;
+                    "#,
+                    )
+                    .bind::<Integer, _>(copy.id)
+                    .bind::<Integer, _>(original.id)
+                    .execute(conn)?;
+                }
+                copies.push(copy);
+            }
+            Ok(copies)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+    use common::CardSide;
+
+    use super::*;
+    use crate::api::serve_media;
+    use crate::auth::AuthenticateMiddlewareFactory;
+    use crate::media::{self, MediaConfig};
+    use crate::settings::Settings;
+    use crate::test_utils;
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn copies_keep_their_media() {
+        use common::schema::{cards, media as media_rows};
+
+        let settings = Settings {
+            media: MediaConfig {
+                dir: std::env::temp_dir().join(test_utils::unique_name("media")),
+                ..MediaConfig::default()
+            },
+            ..test_utils::settings()
+        };
+        let pool = test_utils::pool(&settings);
+        let conn = pool.get().unwrap();
+        let user = test_utils::new_user(&conn);
+        let deck = test_utils::new_deck(&conn, &user);
+        let other_deck = test_utils::new_deck(&conn, &user);
+        let card = test_utils::new_card(&conn, &deck);
+        let bytes = [b"GIF89a".as_slice(), user.username.as_bytes()].concat();
+        let hash = media::store(&settings.media, &bytes).unwrap();
+        diesel::insert_into(media_rows::table)
+            .values((
+                media_rows::card_id.eq(card.id),
+                media_rows::side.eq(CardSide::Front),
+                media_rows::hash.eq(&hash),
+                media_rows::content_type.eq("image/gif"),
+                media_rows::size.eq(bytes.len() as i32),
+            ))
+            .execute(&conn)
+            .unwrap();
+
+        let copies =
+            transfer_cards(&conn, &[card.id], other_deck.id, TransferMode::Copy, false).unwrap();
+        // Only the copy is left pointing at the file.
+        diesel::delete(cards::table.find(card.id))
+            .execute(&conn)
+            .unwrap();
+        let copied: Vec<(CardSide, String)> = media_rows::table
+            .filter(media_rows::card_id.eq(copies[0].id))
+            .select((media_rows::side, media_rows::hash))
+            .load(&conn)
+            .unwrap();
+        assert_eq!(copied, vec![(CardSide::Front, hash.clone())]);
+
+        let token = test_utils::new_api_token(&conn, &user);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(settings.clone()))
+                .wrap(AuthenticateMiddlewareFactory::default())
+                .service(serve_media),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/media/{}", hash))
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, bytes);
+        std::fs::remove_dir_all(&settings.media.dir).unwrap();
+    }
+}
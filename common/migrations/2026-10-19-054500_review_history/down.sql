@@ -0,0 +1,7 @@
+DROP TABLE reviews;
+
+ALTER TABLE cards
+DROP COLUMN created,
+DROP COLUMN due;
+
+DROP TYPE review_rating;
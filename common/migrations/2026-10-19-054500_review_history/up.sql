@@ -0,0 +1,20 @@
+CREATE TYPE review_rating AS ENUM ('fail', 'hard', 'good', 'easy');
+
+ALTER TABLE cards
+ADD COLUMN created TIMESTAMP NOT NULL DEFAULT NOW(),
+ADD COLUMN due TIMESTAMP;
+
+CREATE TABLE reviews (
+  id SERIAL PRIMARY KEY,
+  card_id INT NOT NULL,
+  rating review_rating NOT NULL,
+  reviewed TIMESTAMP NOT NULL,
+  answer_ms INT,
+  CONSTRAINT fk_card
+    FOREIGN KEY(card_id)
+      REFERENCES cards(id)
+      ON DELETE CASCADE
+);
+
+CREATE INDEX reviews_card_id_idx ON reviews (card_id);
+CREATE INDEX reviews_reviewed_idx ON reviews (reviewed);
@@ -0,0 +1,4 @@
+DROP INDEX cards_search_idx;
+ALTER TABLE cards DROP COLUMN search;
+DROP TEXT SEARCH CONFIGURATION french_unaccent;
+DROP EXTENSION IF EXISTS unaccent;
@@ -0,0 +1,16 @@
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+CREATE TEXT SEARCH CONFIGURATION french_unaccent (COPY = french);
+ALTER TEXT SEARCH CONFIGURATION french_unaccent
+  ALTER MAPPING FOR hword, hword_part, word
+  WITH unaccent, french_stem;
+
+-- Not part of `schema.rs` on purpose: diesel has no `tsvector` type, it's only ever
+-- touched through raw SQL fragments in `read_cards`.
+ALTER TABLE cards
+ADD COLUMN search TSVECTOR GENERATED ALWAYS AS (
+  setweight(to_tsvector('french_unaccent', front), 'A') ||
+  setweight(to_tsvector('french_unaccent', back), 'B')
+) STORED;
+
+CREATE INDEX cards_search_idx ON cards USING GIN (search);
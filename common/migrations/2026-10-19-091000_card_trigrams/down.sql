@@ -0,0 +1,2 @@
+DROP INDEX cards_front_trgm_idx;
+DROP EXTENSION IF EXISTS pg_trgm;
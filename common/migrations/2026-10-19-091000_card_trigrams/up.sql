@@ -0,0 +1,3 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX cards_front_trgm_idx ON cards USING GIN (lower(front) gin_trgm_ops);
@@ -0,0 +1,5 @@
+DROP INDEX cards_tags_idx;
+
+ALTER TABLE cards
+DROP COLUMN tags,
+DROP COLUMN suspended;
@@ -0,0 +1,5 @@
+ALTER TABLE cards
+ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+ADD COLUMN suspended BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX cards_tags_idx ON cards USING GIN (tags);
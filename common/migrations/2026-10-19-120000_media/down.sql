@@ -0,0 +1,3 @@
+DROP TABLE media;
+
+DROP TYPE card_side;
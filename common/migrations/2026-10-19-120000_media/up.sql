@@ -0,0 +1,20 @@
+CREATE TYPE card_side AS ENUM ('front', 'back');
+
+-- Files live on disk under the media directory, named by `hash` (SHA-256 of the contents), so
+-- the same picture attached to several cards is stored once.
+CREATE TABLE media (
+  id SERIAL PRIMARY KEY,
+  card_id INT NOT NULL,
+  side card_side NOT NULL,
+  hash TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  size INT NOT NULL,
+  created TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_card
+    FOREIGN KEY(card_id)
+      REFERENCES cards(id)
+      ON DELETE CASCADE
+);
+
+CREATE INDEX media_card_id_idx ON media (card_id);
+CREATE INDEX media_hash_idx ON media (hash);
@@ -0,0 +1,3 @@
+ALTER TABLE decks
+DROP COLUMN autoplay_front,
+DROP COLUMN autoplay_back;
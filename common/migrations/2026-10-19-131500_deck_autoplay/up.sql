@@ -0,0 +1,3 @@
+ALTER TABLE decks
+ADD COLUMN autoplay_front BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN autoplay_back BOOLEAN NOT NULL DEFAULT FALSE;
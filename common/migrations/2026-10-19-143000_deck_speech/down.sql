@@ -0,0 +1,6 @@
+ALTER TABLE decks
+DROP COLUMN front_lang,
+DROP COLUMN back_lang,
+DROP COLUMN front_voice,
+DROP COLUMN back_voice,
+DROP COLUMN speech_rate;
@@ -0,0 +1,7 @@
+-- Language tags (e.g. `fr-FR`) and voice names per side for speech synthesis in the browser.
+ALTER TABLE decks
+ADD COLUMN front_lang TEXT,
+ADD COLUMN back_lang TEXT,
+ADD COLUMN front_voice TEXT,
+ADD COLUMN back_voice TEXT,
+ADD COLUMN speech_rate REAL NOT NULL DEFAULT 1.0;
@@ -0,0 +1,6 @@
+ALTER TABLE decks
+DROP COLUMN review_order,
+DROP COLUMN new_card_order,
+DROP COLUMN new_card_ratio;
+DROP TYPE review_order;
+DROP TYPE new_card_order;
@@ -0,0 +1,8 @@
+-- How a deck's revision sessions pick and order their cards, see `revision::order_candidates`.
+CREATE TYPE review_order AS ENUM ('weighted', 'due', 'random', 'hardest');
+CREATE TYPE new_card_order AS ENUM ('created', 'random');
+ALTER TABLE decks
+ADD COLUMN review_order review_order NOT NULL DEFAULT 'weighted',
+ADD COLUMN new_card_order new_card_order NOT NULL DEFAULT 'created',
+-- Percentage of a session kept for new cards, NULL to mix them in with the rest.
+ADD COLUMN new_card_ratio SMALLINT CHECK (new_card_ratio BETWEEN 0 AND 100);
@@ -0,0 +1,2 @@
+-- Reviews from cram sessions, which leave the card's scheduling alone.
+ALTER TABLE reviews ADD COLUMN cram BOOLEAN NOT NULL DEFAULT FALSE;
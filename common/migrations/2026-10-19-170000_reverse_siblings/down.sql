@@ -0,0 +1,3 @@
+ALTER TABLE reviews DROP COLUMN reverse;
+ALTER TABLE decks DROP COLUMN reverse_siblings;
+DROP TABLE reverse_siblings;
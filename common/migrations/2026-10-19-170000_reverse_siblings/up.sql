@@ -0,0 +1,23 @@
+-- Scheduling of a card's back -> front direction, for decks revising both directions separately.
+-- The front -> back direction keeps using `cards.revision_weight` and `cards.due`.
+CREATE TABLE reverse_siblings (
+  card_id INT PRIMARY KEY,
+  revision_weight SMALLINT NOT NULL DEFAULT 100,
+  due TIMESTAMP,
+  CONSTRAINT fk_card
+    FOREIGN KEY(card_id)
+      REFERENCES cards(id)
+      ON DELETE CASCADE
+);
+
+ALTER TABLE decks ADD COLUMN reverse_siblings BOOLEAN NOT NULL DEFAULT FALSE;
+-- Which direction was shown, back first or not.
+ALTER TABLE reviews ADD COLUMN reverse BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Decks that used to flip a coin now schedule each direction, both starting where the card was.
+UPDATE decks SET reverse_siblings = TRUE WHERE flip_mode = 'both';
+INSERT INTO reverse_siblings (card_id, revision_weight, due)
+SELECT cards.id, cards.revision_weight, cards.due
+FROM cards
+JOIN decks ON decks.id = cards.deck_id
+WHERE decks.flip_mode = 'both';
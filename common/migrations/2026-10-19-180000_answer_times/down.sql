@@ -0,0 +1,4 @@
+ALTER TABLE decks DROP COLUMN slow_answer_ms;
+ALTER TABLE reviews
+DROP COLUMN reveal_ms,
+DROP COLUMN rate_ms;
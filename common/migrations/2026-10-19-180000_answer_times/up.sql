@@ -0,0 +1,8 @@
+-- How long the card took to flip over, then to rate once flipped, capped against walk-aways.
+-- `answer_ms` stays the whole time from the card being shown to it being rated.
+ALTER TABLE reviews
+ADD COLUMN reveal_ms INT,
+ADD COLUMN rate_ms INT;
+
+-- Past this time to reveal, an "easy" counts as "good", NULL to never downgrade.
+ALTER TABLE decks ADD COLUMN slow_answer_ms INT CHECK (slow_answer_ms > 0);
@@ -0,0 +1,9 @@
+DROP INDEX sessions_user_id;
+DROP INDEX sessions_token_hash;
+
+-- Hashed tokens can't be turned back into cookies, everyone has to log in again.
+DELETE FROM sessions;
+ALTER TABLE sessions RENAME COLUMN token_hash TO token;
+
+ALTER TABLE sessions DROP COLUMN last_seen;
+ALTER TABLE sessions DROP COLUMN user_agent;
@@ -0,0 +1,11 @@
+ALTER TABLE sessions ADD COLUMN user_agent TEXT NOT NULL DEFAULT '';
+ALTER TABLE sessions ADD COLUMN last_seen TIMESTAMP;
+UPDATE sessions SET last_seen = created;
+ALTER TABLE sessions ALTER COLUMN last_seen SET NOT NULL;
+
+-- Tokens are only kept as their SHA-256, the same way `auth::hash_token` does it.
+UPDATE sessions SET token = encode(sha256(convert_to(token, 'UTF8')), 'hex');
+ALTER TABLE sessions RENAME COLUMN token TO token_hash;
+
+CREATE UNIQUE INDEX sessions_token_hash ON sessions (token_hash);
+CREATE INDEX sessions_user_id ON sessions (user_id);
@@ -0,0 +1,17 @@
+-- What an API token may do, on top of its optional deck restriction.
+CREATE TYPE token_scope AS ENUM ('read', 'read_write');
+
+CREATE TABLE api_tokens (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  -- SHA-256 of the token, which is only shown once, on creation.
+  token_hash TEXT NOT NULL UNIQUE,
+  scope token_scope NOT NULL,
+  -- Only this deck's URLs when set.
+  deck_id INT REFERENCES decks(id) ON DELETE CASCADE,
+  created TIMESTAMP NOT NULL,
+  last_used TIMESTAMP
+);
+
+CREATE INDEX api_tokens_user_id ON api_tokens (user_id);
@@ -0,0 +1,22 @@
+-- Base32 TOTP secret, only used for logging in once `totp_enabled` after a first good code.
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false;
+-- Time step of the last code accepted, so a code can't be used twice.
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
+
+-- Single use, deleted once used.
+CREATE TABLE recovery_codes (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  code_hash TEXT NOT NULL
+);
+CREATE INDEX recovery_codes_user_id ON recovery_codes (user_id);
+
+-- Logins with the right password waiting for their second factor.
+CREATE TABLE login_challenges (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  created TIMESTAMP NOT NULL,
+  failed_attempts SMALLINT NOT NULL DEFAULT 0
+);
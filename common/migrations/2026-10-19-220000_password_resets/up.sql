@@ -0,0 +1,11 @@
+-- Where password reset links go, accounts without one can't reset.
+ALTER TABLE users ADD COLUMN email TEXT UNIQUE;
+
+-- Single use, deleted once used or when the password changes.
+CREATE TABLE password_resets (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  created TIMESTAMP NOT NULL
+);
+CREATE INDEX password_resets_user_id ON password_resets (user_id);
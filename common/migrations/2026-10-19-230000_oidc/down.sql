@@ -0,0 +1,4 @@
+DROP TABLE oidc_logins;
+ALTER TABLE users DROP CONSTRAINT users_username_key;
+ALTER TABLE users DROP COLUMN oidc_issuer;
+ALTER TABLE users DROP COLUMN oidc_subject;
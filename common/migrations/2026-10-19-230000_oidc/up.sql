@@ -0,0 +1,19 @@
+-- The `iss` and `sub` claims of users logging in through OpenID Connect. Subjects are only
+-- unique for their issuer, so it's both or neither.
+ALTER TABLE users ADD COLUMN oidc_subject TEXT;
+ALTER TABLE users ADD COLUMN oidc_issuer TEXT;
+ALTER TABLE users ADD CONSTRAINT users_oidc_identity UNIQUE (oidc_issuer, oidc_subject);
+ALTER TABLE users ADD CONSTRAINT users_oidc_identity_complete
+  CHECK ((oidc_issuer IS NULL) = (oidc_subject IS NULL));
+
+-- Logins look users up by name, and provisioning counts on the database to keep it unique.
+ALTER TABLE users ADD CONSTRAINT users_username_key UNIQUE (username);
+
+-- Logins sent off to the provider, until it sends them back. Single use, like `login_challenges`.
+CREATE TABLE oidc_logins (
+  id SERIAL PRIMARY KEY,
+  state_hash TEXT NOT NULL UNIQUE,
+  nonce TEXT NOT NULL,
+  pkce_verifier TEXT NOT NULL,
+  created TIMESTAMP NOT NULL
+);
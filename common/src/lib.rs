@@ -6,9 +6,18 @@ use std::fmt;
 use diesel_derive_enum::DbEnum;
 use serde::{Deserialize, Serialize};
 
+pub mod markup;
 pub mod models;
 pub mod query_params;
 pub mod schema;
+pub mod search;
+pub mod speech;
+pub mod stats;
+
+// Double-submit CSRF protection: the backend sets this cookie, the frontend sends it back in the
+// header with every request that changes something.
+pub const CSRF_COOKIE: &str = "csrf-token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
 
 #[derive(DbEnum, PartialEq, Copy, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -28,3 +37,62 @@ impl fmt::Debug for FlipMode {
         }
     }
 }
+
+#[derive(DbEnum, Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[DieselType = "Review_rating"]
+pub enum Rating {
+    Fail,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rating::Fail => write!(f, "fail"),
+            Rating::Hard => write!(f, "hard"),
+            Rating::Good => write!(f, "good"),
+            Rating::Easy => write!(f, "easy"),
+        }
+    }
+}
+
+#[derive(DbEnum, Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[DieselType = "Card_side"]
+pub enum CardSide {
+    Front,
+    Back,
+}
+
+// Order of the cards in a revision session: weighted random by `revision_weight`, by due date,
+// plain random, or most failed first.
+#[derive(DbEnum, Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[DieselType = "Review_order"]
+pub enum ReviewOrder {
+    Weighted,
+    Due,
+    Random,
+    Hardest,
+}
+
+// Order of the cards not reviewed yet among themselves.
+#[derive(DbEnum, Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[DieselType = "New_card_order"]
+pub enum NewCardOrder {
+    Created,
+    Random,
+}
+
+// What an API token may do. Sessions can do everything.
+#[derive(DbEnum, Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[DieselType = "Token_scope"]
+pub enum TokenScope {
+    Read,
+    ReadWrite,
+}
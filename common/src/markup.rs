@@ -0,0 +1,338 @@
+// Small Markdown-like markup for card text:
+// - `**bold**`, `*italics*` or `_italics_`,
+// - `{漢字|かんじ}` for a ruby annotation over its base text,
+// - lines starting with `- ` / `* ` or `1. ` make up lists, other lines break as typed,
+// - a backslash makes the next character literal, e.g. `\*`.
+// Anything that doesn't parse as markup is kept as plain text.
+
+use std::collections::VecDeque;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Ruby { base: String, annotation: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Line(Vec<Inline>),
+    List {
+        ordered: bool,
+        items: Vec<Vec<Inline>>,
+    },
+}
+
+pub fn parse_markup(text: &str) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in text.lines() {
+        match list_item(line) {
+            Some((ordered, item)) => {
+                let item = parse_inline(item);
+                match blocks.last_mut() {
+                    Some(Block::List {
+                        ordered: last_ordered,
+                        items,
+                    }) if *last_ordered == ordered => items.push(item),
+                    _ => blocks.push(Block::List {
+                        ordered,
+                        items: vec![item],
+                    }),
+                }
+            }
+            None => blocks.push(Block::Line(parse_inline(line))),
+        }
+    }
+    blocks
+}
+
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    // `(ordered, rest of the line)` if `line` is a list item.
+    let line = line.trim_start();
+    if let Some(rest) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+        return Some((false, rest));
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        let rest = &line[digits..];
+        if let Some(rest) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            return Some((true, rest));
+        }
+    }
+    None
+}
+
+// Emphasis openers waiting for their closer at once, past that the earliest one is given up on.
+// Bounds how deep emphasis nests, and so how often parsing moves an inline around.
+const MAX_OPENERS: usize = 16;
+
+enum Node {
+    Inline(Inline),
+    // Waiting for its closer, and text if it never comes.
+    Opener(&'static str),
+}
+
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    // Left to right, each closer matched with the nearest opener of its kind, so unclosed
+    // delimiters don't send it back over the rest of the text.
+    let chars: Vec<char> = text.chars().collect();
+    let mut nodes: Vec<Node> = Vec::new();
+    // Indices in `nodes` of the openers not closed yet, innermost last.
+    let mut openers: VecDeque<usize> = VecDeque::new();
+    let mut buffer = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\\' && i + 1 < chars.len() {
+            buffer.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+
+        if let Some(delimiter) = delimiter_at(&chars, i) {
+            if closes(&chars, i, delimiter) {
+                flush(&mut buffer, &mut nodes);
+                let opener = openers
+                    .iter()
+                    .rposition(|&at| matches!(nodes[at], Node::Opener(d) if d == delimiter));
+                // Nothing in between, e.g. `****`, isn't markup.
+                if let Some(position) =
+                    opener.filter(|&position| openers[position] + 1 < nodes.len())
+                {
+                    let at = openers[position];
+                    // Openers in between never got closed, they're text.
+                    openers.truncate(position);
+                    let children = into_inlines(nodes.drain(at + 1..));
+                    nodes[at] = Node::Inline(if delimiter == "**" {
+                        Inline::Bold(children)
+                    } else {
+                        Inline::Italic(children)
+                    });
+                    i += delimiter.len();
+                    continue;
+                }
+            }
+            if opens(&chars, i, delimiter) {
+                flush(&mut buffer, &mut nodes);
+                if openers.len() == MAX_OPENERS {
+                    let at = openers.pop_front().unwrap();
+                    if let Node::Opener(earliest) = nodes[at] {
+                        nodes[at] = Node::Inline(Inline::Text(earliest.to_string()));
+                    }
+                }
+                openers.push_back(nodes.len());
+                nodes.push(Node::Opener(delimiter));
+                i += delimiter.len();
+                continue;
+            }
+        }
+
+        if c == '{' {
+            if let Some((ruby, end)) = parse_ruby(&chars, i) {
+                flush(&mut buffer, &mut nodes);
+                nodes.push(Node::Inline(ruby));
+                i = end;
+                continue;
+            }
+        }
+
+        buffer.push(c);
+        i += 1;
+    }
+    flush(&mut buffer, &mut nodes);
+    into_inlines(nodes.drain(..))
+}
+
+fn starts_with(chars: &[char], i: usize, pattern: &str) -> bool {
+    pattern
+        .chars()
+        .enumerate()
+        .all(|(offset, c)| chars.get(i + offset) == Some(&c))
+}
+
+fn delimiter_at(chars: &[char], i: usize) -> Option<&'static str> {
+    if starts_with(chars, i, "**") {
+        Some("**")
+    } else {
+        match chars[i] {
+            '*' => Some("*"),
+            '_' => Some("_"),
+            _ => None,
+        }
+    }
+}
+
+fn opens(chars: &[char], i: usize, delimiter: &str) -> bool {
+    // Opening delimiters have to come before text ...
+    let before_text = matches!(chars.get(i + delimiter.len()), Some(next) if !next.is_whitespace());
+    // ... and no italics in the middle of words, e.g. `snake_case`.
+    let in_word = delimiter == "_" && i > 0 && chars[i - 1].is_alphanumeric();
+    before_text && !in_word
+}
+
+fn closes(chars: &[char], i: usize, delimiter: &str) -> bool {
+    // Closing delimiters have to follow text, e.g. `x * y * z` has no italics.
+    i > 0 && !chars[i - 1].is_whitespace() && starts_with(chars, i, delimiter)
+}
+
+fn flush(buffer: &mut String, nodes: &mut Vec<Node>) {
+    if !buffer.is_empty() {
+        nodes.push(Node::Inline(Inline::Text(std::mem::take(buffer))));
+    }
+}
+
+fn into_inlines(nodes: impl Iterator<Item = Node>) -> Vec<Inline> {
+    // Openers left over are text, merged with the text around them.
+    let mut inlines: Vec<Inline> = Vec::new();
+    for node in nodes {
+        let inline = match node {
+            Node::Opener(delimiter) => Inline::Text(delimiter.to_string()),
+            Node::Inline(inline) => inline,
+        };
+        match (inlines.last_mut(), inline) {
+            (Some(Inline::Text(last)), Inline::Text(text)) => last.push_str(&text),
+            (_, inline) => inlines.push(inline),
+        }
+    }
+    inlines
+}
+
+fn parse_ruby(chars: &[char], start: usize) -> Option<(Inline, usize)> {
+    // `{base|annotation}` starting at `start`, with the index right after it.
+    let mut base = String::new();
+    let mut annotation = String::new();
+    let mut in_annotation = false;
+    for (offset, &c) in chars[start + 1..].iter().enumerate() {
+        match c {
+            '|' if !in_annotation => in_annotation = true,
+            '}' if in_annotation => {
+                if base.trim().is_empty() || annotation.trim().is_empty() {
+                    return None;
+                }
+                let ruby = Inline::Ruby { base, annotation };
+                return Some((ruby, start + offset + 2));
+            }
+            '{' | '}' | '|' | '\n' => return None,
+            c if in_annotation => annotation.push(c),
+            c => base.push(c),
+        }
+    }
+    None
+}
+
+pub fn plain_text(text: &str) -> String {
+    // The text with markup taken out, ruby reduced to its base, e.g. for speech synthesis.
+    fn push_inlines(inlines: &[Inline], out: &mut String) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => out.push_str(text),
+                Inline::Bold(children) | Inline::Italic(children) => push_inlines(children, out),
+                Inline::Ruby { base, .. } => out.push_str(base),
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    for block in parse_markup(text) {
+        match block {
+            Block::Line(inlines) => {
+                let mut line = String::new();
+                push_inlines(&inlines, &mut line);
+                lines.push(line);
+            }
+            Block::List { items, .. } => {
+                for item in items {
+                    let mut line = String::new();
+                    push_inlines(&item, &mut line);
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn has_markup(text: &str) -> bool {
+    // Whether `text` renders any differently from a single line of plain text.
+    match parse_markup(text).as_slice() {
+        [] => false,
+        [Block::Line(inlines)] => !inlines
+            .iter()
+            .all(|inline| matches!(inline, Inline::Text(_))),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    fn depth(inlines: &[Inline]) -> usize {
+        inlines
+            .iter()
+            .map(|inline| match inline {
+                Inline::Bold(children) | Inline::Italic(children) => 1 + depth(children),
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn parses_emphasis() {
+        assert_eq!(
+            parse_inline("a **b *c* d** e"),
+            vec![
+                text("a "),
+                Inline::Bold(vec![
+                    text("b "),
+                    Inline::Italic(vec![text("c")]),
+                    text(" d")
+                ]),
+                text(" e"),
+            ]
+        );
+        assert_eq!(parse_inline("x * y * z"), vec![text("x * y * z")]);
+        assert_eq!(
+            parse_inline("snake_case_name"),
+            vec![text("snake_case_name")]
+        );
+        assert_eq!(parse_inline("****"), vec![text("****")]);
+        assert_eq!(parse_inline(r"\*a*"), vec![text("*a*")]);
+    }
+
+    #[test]
+    fn keeps_unclosed_delimiters_as_text() {
+        assert_eq!(
+            parse_inline("*a _b* c"),
+            vec![Inline::Italic(vec![text("a _b")]), text(" c")]
+        );
+        assert_eq!(parse_inline("**a *b"), vec![text("**a *b")]);
+    }
+
+    #[test]
+    fn unclosed_delimiters_parse_quickly() {
+        // Used to backtrack through every combination of openers, 22 of them took seconds.
+        for pattern in ["*a ", "_a ", "**a ", "*a _b **c "] {
+            let text = pattern.repeat(20_000);
+            assert_eq!(plain_text(&text), text);
+            let text = format!("{}a*_**", text);
+            assert!(plain_text(&text).len() < text.len());
+        }
+    }
+
+    #[test]
+    fn caps_nesting() {
+        let text = format!("{}a{}", "*x _x ".repeat(10), " x_ x*".repeat(10));
+        let inlines = parse_inline(&text);
+        assert_eq!(depth(&inlines), MAX_OPENERS);
+        let left = plain_text(&text).matches(['*', '_']).count();
+        assert_eq!(left, 2 * (20 - MAX_OPENERS));
+    }
+}
@@ -2,7 +2,8 @@ use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::schema::*;
-use crate::FlipMode;
+use crate::speech::some_or_null;
+use crate::{CardSide, FlipMode, NewCardOrder, Rating, ReviewOrder, TokenScope};
 
 #[derive(Identifiable, Queryable)]
 #[table_name = "users"]
@@ -10,6 +11,12 @@ pub struct User {
     pub id: i32,
     pub username: String,
     pub password: String,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub email: Option<String>,
+    pub oidc_subject: Option<String>,
+    pub oidc_issuer: Option<String>,
 }
 
 #[derive(Associations, Identifiable, Queryable)]
@@ -18,8 +25,144 @@ pub struct User {
 pub struct Session {
     pub id: i32,
     pub user_id: i32,
+    // SHA-256 of the token in the cookie, never the token itself.
+    pub token_hash: String,
+    pub created: NaiveDateTime,
+    pub user_agent: String,
+    pub last_seen: NaiveDateTime,
+}
+
+// A session as listed for its user to look over.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct SessionInfo {
+    pub id: i32,
+    // Browser and OS made out of the user agent, e.g. "Firefox sur Linux".
+    pub device: String,
+    pub user_agent: String,
+    pub created: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+    // Whether it's the session asking.
+    pub current: bool,
+}
+
+#[derive(Associations, Identifiable, Queryable)]
+#[belongs_to(User)]
+#[table_name = "api_tokens"]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    // SHA-256 of the token, like sessions'.
+    pub token_hash: String,
+    pub scope: TokenScope,
+    pub deck_id: Option<i32>,
+    pub created: NaiveDateTime,
+    pub last_used: Option<NaiveDateTime>,
+}
+
+// What the login page offers besides passwords.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct LoginOptions {
+    // The button's label, when logging in through an OpenID Connect provider is on.
+    pub oidc_label: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LoginOutcome {
+    LoggedIn,
+    // The password was right, now for a code from the `challenge`'s second step.
+    TotpRequired { challenge: String },
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TotpLoginPayload {
+    pub challenge: String,
+    // From the authenticator app, or one of the recovery codes.
+    pub code: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TotpCodePayload {
+    pub code: String,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct TotpEnrollment {
+    // Base32, for typing into apps that can't scan.
+    pub secret: String,
+    pub uri: String,
+    pub qr_svg: String,
+}
+
+// Shown once when 2FA gets turned on, only hashes are kept.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct RecoveryCodes {
+    pub codes: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PasswordChangePayload {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct EmailPayload {
+    pub email: Option<String>,
+}
+
+// Asking for a reset link, by username or email.
+#[derive(Deserialize, Serialize)]
+pub struct PasswordResetRequest {
+    pub login: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PasswordResetPayload {
     pub token: String,
+    pub password: String,
+}
+
+// An API token as listed for its user, without anything secret.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct ApiTokenInfo {
+    pub id: i32,
+    pub name: String,
+    pub scope: TokenScope,
+    pub deck_id: Option<i32>,
+    pub deck_name: Option<String>,
     pub created: NaiveDateTime,
+    pub last_used: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NewApiToken {
+    pub name: String,
+    pub scope: TokenScope,
+    pub deck_id: Option<i32>,
+}
+
+// What the current request is allowed, sessions having every scope.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct AuthInfo {
+    pub username: String,
+    pub email: Option<String>,
+    pub scope: TokenScope,
+    pub deck_id: Option<i32>,
+}
+
+// The only time the token itself gets out.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct CreatedApiToken {
+    pub token: String,
+    pub info: ApiTokenInfo,
 }
 
 #[derive(Clone, PartialEq, Identifiable, Queryable, Deserialize, Serialize)]
@@ -30,6 +173,45 @@ pub struct Deck {
     pub user_id: i32,
     pub revision_length: i16,
     pub flip_mode: FlipMode,
+    // Play a side's audio as soon as it's shown in revision.
+    pub autoplay_front: bool,
+    pub autoplay_back: bool,
+    pub front_lang: Option<String>,
+    pub back_lang: Option<String>,
+    // Name of the browser voice to read each side with, see `speech`.
+    pub front_voice: Option<String>,
+    pub back_voice: Option<String>,
+    pub speech_rate: f32,
+    pub review_order: ReviewOrder,
+    pub new_card_order: NewCardOrder,
+    // Percentage of each session kept for new cards, `None` to mix them in with the rest.
+    pub new_card_ratio: Option<i16>,
+    // With `FlipMode::Both`, schedule front -> back and back -> front separately rather than
+    // picking a direction at random each time.
+    pub reverse_siblings: bool,
+    // Answers slower than this to reveal can't be rated "easy", see `revision::effective_rating`.
+    pub slow_answer_ms: Option<i32>,
+}
+
+impl Deck {
+    pub fn schedules_reverse(&self) -> bool {
+        // Whether cards come up as two siblings, see `reverse_siblings`.
+        self.flip_mode == FlipMode::Both && self.reverse_siblings
+    }
+
+    pub fn lang(&self, side: CardSide) -> Option<&str> {
+        match side {
+            CardSide::Front => self.front_lang.as_deref(),
+            CardSide::Back => self.back_lang.as_deref(),
+        }
+    }
+
+    pub fn voice(&self, side: CardSide) -> Option<&str> {
+        match side {
+            CardSide::Front => self.front_voice.as_deref(),
+            CardSide::Back => self.back_voice.as_deref(),
+        }
+    }
 }
 
 #[derive(AsChangeset, Deserialize)]
@@ -38,6 +220,24 @@ pub struct PostDeck {
     pub name: Option<String>,
     pub revision_length: Option<i16>,
     pub flip_mode: Option<FlipMode>,
+    pub autoplay_front: Option<bool>,
+    pub autoplay_back: Option<bool>,
+    #[serde(default, deserialize_with = "some_or_null")]
+    pub front_lang: Option<Option<String>>,
+    #[serde(default, deserialize_with = "some_or_null")]
+    pub back_lang: Option<Option<String>>,
+    #[serde(default, deserialize_with = "some_or_null")]
+    pub front_voice: Option<Option<String>>,
+    #[serde(default, deserialize_with = "some_or_null")]
+    pub back_voice: Option<Option<String>>,
+    pub speech_rate: Option<f32>,
+    pub review_order: Option<ReviewOrder>,
+    pub new_card_order: Option<NewCardOrder>,
+    #[serde(default, deserialize_with = "some_or_null")]
+    pub new_card_ratio: Option<Option<i16>>,
+    pub reverse_siblings: Option<bool>,
+    #[serde(default, deserialize_with = "some_or_null")]
+    pub slow_answer_ms: Option<Option<i32>>,
 }
 
 #[derive(Clone, PartialEq, Associations, Identifiable, Queryable, Deserialize, Serialize)]
@@ -48,13 +248,163 @@ pub struct Card {
     pub front: String,
     pub back: String,
     pub revision_weight: i16,
+    pub created: NaiveDateTime,
+    pub due: Option<NaiveDateTime>,
+    pub tags: Vec<String>,
+    // Suspended cards stay in the deck but are left out of revision.
+    pub suspended: bool,
+}
+
+// A card as listed in search results, with matched words marked in its text
+// (see `search::split_highlights`). Highlights are only there when searching.
+#[derive(Clone, PartialEq, Queryable, Deserialize, Serialize)]
+pub struct CardMatch {
+    pub card: Card,
+    pub front_highlight: Option<String>,
+    pub back_highlight: Option<String>,
+}
+
+// A search hit from any of the user's decks.
+#[derive(Clone, PartialEq, Queryable, Deserialize, Serialize)]
+pub struct DeckCardMatch {
+    pub deck_name: String,
+    pub card_match: CardMatch,
+}
+
+#[derive(Clone, PartialEq, Queryable, Deserialize, Serialize)]
+pub struct DeckCard {
+    pub deck_name: String,
+    pub card: Card,
+}
+
+// Cards that look like copies of one another, for cleanup.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct DuplicateCluster {
+    pub cards: Vec<DeckCard>,
+}
+
+// Something to do to a whole selection of cards at once, see `BulkPayload`.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum BulkOperation {
+    Delete,
+    Move { deck_id: i32 },
+    AddTag { tag: String },
+    RemoveTag { tag: String },
+    ResetScheduling,
+    SwapSides,
+    Suspend,
+    Unsuspend,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BulkPayload {
+    pub card_ids: Vec<i32>,
+    #[serde(flatten)]
+    pub operation: BulkOperation,
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferMode {
+    Move,
+    Copy,
+}
+
+// Moves or copies cards over to another of the user's decks.
+#[derive(Deserialize, Serialize)]
+pub struct TransferPayload {
+    pub card_ids: Vec<i32>,
+    pub target_deck_id: i32,
+    pub mode: TransferMode,
+    // Without it the cards start over as new in the target deck.
+    pub keep_history: bool,
+}
+
+// Empties `source_deck_id` into the deck posted to, then deletes it.
+#[derive(Deserialize, Serialize)]
+pub struct MergePayload {
+    pub source_deck_id: i32,
+}
+
+// Moves the cards matching `search_term` out into a new deck called `name`.
+#[derive(Deserialize, Serialize)]
+pub struct SplitPayload {
+    pub name: String,
+    pub search_term: String,
+}
+
+#[derive(Associations, Identifiable, Queryable)]
+#[belongs_to(Card)]
+pub struct Review {
+    pub id: i32,
+    pub card_id: i32,
+    pub rating: Rating,
+    pub reviewed: NaiveDateTime,
+    pub answer_ms: Option<i32>,
+    pub cram: bool,
+    pub reverse: bool,
+    pub reveal_ms: Option<i32>,
+    pub rate_ms: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FeedbackPayload {
+    pub rating: Rating,
+    // Milliseconds between the card being shown and being rated, if the client measured it.
+    pub answer_ms: Option<i32>,
+    // ... split into the time to flip the card over and the time to rate it after that.
+    #[serde(default)]
+    pub reveal_ms: Option<i32>,
+    #[serde(default)]
+    pub rate_ms: Option<i32>,
+    // Rated during a cram session: logged, but the card's scheduling stays as it was.
+    #[serde(default)]
+    pub cram: bool,
+    // The card was shown back first, see `Deck::reverse_siblings`.
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+// A file attached to one side of a card, served from `/media/{hash}`.
+#[derive(Clone, PartialEq, Associations, Identifiable, Queryable, Deserialize, Serialize)]
+#[belongs_to(Card)]
+#[table_name = "media"]
+pub struct Media {
+    pub id: i32,
+    pub card_id: i32,
+    pub side: CardSide,
+    pub hash: String,
+    pub content_type: String,
+    pub size: i32,
+    pub created: NaiveDateTime,
+}
+
+impl Media {
+    pub fn url(&self) -> String {
+        format!("/media/{}", self.hash)
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.content_type.starts_with("audio/")
+    }
 }
 
 #[derive(Clone, PartialEq, Deserialize, Serialize)]
 pub struct RevisionCard {
     pub id: i32,
     pub deck_id: i32,
+    // Where the card comes from when revising several decks at once.
+    pub deck_name: String,
     pub first: String,
     pub second: String,
+    // Which side of the card `first` is, flip mode permitting.
+    pub first_side: CardSide,
+    pub first_media: Vec<Media>,
+    pub second_media: Vec<Media>,
     // pub revision_weight: i16,
 }
@@ -1,27 +1,104 @@
 use serde::{Deserialize, Serialize};
 
-fn default_per_page() -> i64 {
-    // TODO gold plate would be to get this from config
-    36
-}
+use crate::CardSide;
 
-#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
 pub struct CardReadQuery {
     // TODO would be nice to figure out how to reuse these bits across any paginated URL params
     #[serde(default)]
     pub page: i64,
-    #[serde(default = "default_per_page")]
-    pub per_page: i64,
+    // The server's configured page size when not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<i64>,
     #[serde(default)]
     pub search_term: String,
 }
 
-impl Default for CardReadQuery {
-    fn default() -> Self {
-        Self {
-            page: 0,
-            per_page: default_per_page(),
-            search_term: String::new(),
-        }
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct TimezoneQuery {
+    // IANA timezone name, e.g. "Europe/Paris", used to decide where one day ends.
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateScope {
+    #[default]
+    Deck,
+    All,
+}
+
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct NewCardQuery {
+    // Where to look for an existing card with the same front.
+    #[serde(default)]
+    pub duplicate_scope: DuplicateScope,
+    // Skip the duplicate check, i.e. "add anyway".
+    #[serde(default)]
+    pub allow_duplicate: bool,
+}
+
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct DuplicatesQuery {
+    // Only report clusters involving this deck, otherwise all of the user's decks.
+    #[serde(default)]
+    pub deck_id: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MediaUploadQuery {
+    pub side: CardSide,
+}
+
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RevisionQuery {
+    // Drill the cards matching the filters below regardless of due dates, see `FeedbackPayload`.
+    #[serde(default)]
+    pub cram: bool,
+    // Comma separated, cards with any of them.
+    #[serde(default)]
+    pub tags: String,
+    // Cards failed within that many days.
+    #[serde(default)]
+    pub failed_days: Option<i32>,
+    // Cards added within that many days.
+    #[serde(default)]
+    pub added_days: Option<i32>,
+    #[serde(default)]
+    pub search_term: String,
+}
+
+impl RevisionQuery {
+    pub fn tag_list(&self) -> Vec<String> {
+        self.tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct CombinedRevisionQuery {
+    // Comma separated, every deck of the user's if empty.
+    #[serde(default)]
+    pub deck_ids: String,
+    // Where the day ends for what's due today, see `TimezoneQuery`.
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+impl CombinedRevisionQuery {
+    pub fn deck_id_list(&self) -> Vec<i32> {
+        self.deck_ids
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect()
     }
 }
@@ -1,3 +1,19 @@
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    api_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Text,
+        token_hash -> Text,
+        scope -> Token_scope,
+        deck_id -> Nullable<Int4>,
+        created -> Timestamp,
+        last_used -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::*;
@@ -8,6 +24,10 @@ table! {
         front -> Text,
         back -> Text,
         revision_weight -> Int2,
+        created -> Timestamp,
+        due -> Nullable<Timestamp>,
+        tags -> Array<Text>,
+        suspended -> Bool,
     }
 }
 
@@ -21,6 +41,110 @@ table! {
         user_id -> Int4,
         revision_length -> Int2,
         flip_mode -> Flip_mode,
+        autoplay_front -> Bool,
+        autoplay_back -> Bool,
+        front_lang -> Nullable<Text>,
+        back_lang -> Nullable<Text>,
+        front_voice -> Nullable<Text>,
+        back_voice -> Nullable<Text>,
+        speech_rate -> Float4,
+        review_order -> Review_order,
+        new_card_order -> New_card_order,
+        new_card_ratio -> Nullable<Int2>,
+        reverse_siblings -> Bool,
+        slow_answer_ms -> Nullable<Int4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    login_challenges (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        created -> Timestamp,
+        failed_attempts -> Int2,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    media (id) {
+        id -> Int4,
+        card_id -> Int4,
+        side -> Card_side,
+        hash -> Text,
+        content_type -> Text,
+        size -> Int4,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    oidc_logins (id) {
+        id -> Int4,
+        state_hash -> Text,
+        nonce -> Text,
+        pkce_verifier -> Text,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    password_resets (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    reviews (id) {
+        id -> Int4,
+        card_id -> Int4,
+        rating -> Review_rating,
+        reviewed -> Timestamp,
+        answer_ms -> Nullable<Int4>,
+        cram -> Bool,
+        reverse -> Bool,
+        reveal_ms -> Nullable<Int4>,
+        rate_ms -> Nullable<Int4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Text,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::*;
+
+    reverse_siblings (card_id) {
+        card_id -> Int4,
+        revision_weight -> Int2,
+        due -> Nullable<Timestamp>,
     }
 }
 
@@ -31,8 +155,10 @@ table! {
     sessions (id) {
         id -> Int4,
         user_id -> Int4,
-        token -> Text,
+        token_hash -> Text,
         created -> Timestamp,
+        user_agent -> Text,
+        last_seen -> Timestamp,
     }
 }
 
@@ -44,10 +170,37 @@ table! {
         id -> Int4,
         username -> Text,
         password -> Text,
+        totp_secret -> Nullable<Text>,
+        totp_enabled -> Bool,
+        totp_last_step -> Nullable<Int8>,
+        email -> Nullable<Text>,
+        oidc_subject -> Nullable<Text>,
+        oidc_issuer -> Nullable<Text>,
     }
 }
 
+joinable!(api_tokens -> decks (deck_id));
+joinable!(api_tokens -> users (user_id));
 joinable!(cards -> decks (deck_id));
 joinable!(decks -> users (user_id));
+joinable!(login_challenges -> users (user_id));
+joinable!(media -> cards (card_id));
+joinable!(password_resets -> users (user_id));
+joinable!(recovery_codes -> users (user_id));
+joinable!(reverse_siblings -> cards (card_id));
+joinable!(reviews -> cards (card_id));
 
-allow_tables_to_appear_in_same_query!(cards, decks, sessions, users,);
+allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    cards,
+    decks,
+    login_challenges,
+    media,
+    oidc_logins,
+    password_resets,
+    recovery_codes,
+    reverse_siblings,
+    reviews,
+    sessions,
+    users,
+);
@@ -0,0 +1,135 @@
+// Small query language for card search, e.g. `front:maison "il fait" -chat`:
+// - bare words match either side of the card,
+// - `front:` / `back:` restrict a word or phrase to one side,
+// - double quotes make a phrase that must match in that order,
+// - a leading `-` excludes cards matching the word or phrase.
+
+// Tooltip of the search inputs. Accents and plurals are folded, conjugations aren't: the stemmer
+// only trims endings, so "allons" and "aller" stay different words.
+pub const SEARCH_HELP: &str = "front:mot, back:mot, \"une expression\", -exclure\n\
+    Accents et pluriels ignorés, pas les conjugaisons : « allons » ne trouve pas « aller ».";
+
+// Markers wrapped around matched words in highlighted card text.
+pub const HIGHLIGHT_START: &str = "\u{2}";
+pub const HIGHLIGHT_STOP: &str = "\u{3}";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchField {
+    Front,
+    Back,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchTerm {
+    pub field: Option<SearchField>,
+    // Words of the phrase, in order, stripped of punctuation.
+    pub words: Vec<String>,
+    pub negated: bool,
+}
+
+pub fn parse_search(input: &str) -> Vec<SearchTerm> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut raw = String::new();
+        let mut in_quotes = false;
+        while let Some(c) = chars.next_if(|c| in_quotes || !c.is_whitespace()) {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            } else {
+                raw.push(c);
+            }
+        }
+
+        let (field, text) = match raw.split_once(':') {
+            Some(("front", text)) => (Some(SearchField::Front), text),
+            Some(("back", text)) => (Some(SearchField::Back), text),
+            _ => (None, raw.as_str()),
+        };
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        if !words.is_empty() {
+            terms.push(SearchTerm {
+                field,
+                words,
+                negated,
+            });
+        }
+    }
+    terms
+}
+
+pub fn split_highlights(text: &str) -> Vec<(String, bool)> {
+    // Break highlighted text into `(segment, is_match)` pieces for display.
+    let mut segments = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(HIGHLIGHT_START) {
+        if start > 0 {
+            segments.push((rest[..start].to_string(), false));
+        }
+        rest = &rest[start + HIGHLIGHT_START.len()..];
+        let stop = rest.find(HIGHLIGHT_STOP).unwrap_or(rest.len());
+        segments.push((rest[..stop].to_string(), true));
+        rest = &rest[(stop + HIGHLIGHT_STOP.len()).min(rest.len())..];
+    }
+    if !rest.is_empty() {
+        segments.push((rest.to_string(), false));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(field: Option<SearchField>, words: &[&str], negated: bool) -> SearchTerm {
+        SearchTerm {
+            field,
+            words: words.iter().map(|word| word.to_string()).collect(),
+            negated,
+        }
+    }
+
+    #[test]
+    fn parses_fields_phrases_and_negation() {
+        assert_eq!(
+            parse_search(r#"front:Maison back:"il fait beau" -chat -front:"bon jour" x:y"#),
+            vec![
+                term(Some(SearchField::Front), &["maison"], false),
+                term(Some(SearchField::Back), &["il", "fait", "beau"], false),
+                term(None, &["chat"], true),
+                term(Some(SearchField::Front), &["bon", "jour"], true),
+                term(None, &["x", "y"], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_only_words_out_of_punctuation() {
+        assert_eq!(
+            parse_search("a & b | !c:* l'été"),
+            vec![
+                term(None, &["a"], false),
+                term(None, &["b"], false),
+                term(None, &["c"], false),
+                term(None, &["l", "été"], false),
+            ]
+        );
+        // Nothing left to search for.
+        assert_eq!(parse_search(r#"  & | - "" front: !:*"#), vec![]);
+        // An unclosed quote runs to the end.
+        assert_eq!(
+            parse_search(r#""il fait"#),
+            vec![term(None, &["il", "fait"], false)]
+        );
+    }
+}
@@ -0,0 +1,30 @@
+// Settings for reading cards out loud with the browser's speech synthesis.
+
+use serde::{Deserialize, Deserializer};
+
+pub const MIN_SPEECH_RATE: f32 = 0.5;
+pub const MAX_SPEECH_RATE: f32 = 2.0;
+
+pub fn is_valid_lang(lang: &str) -> bool {
+    // Loose BCP 47 check, e.g. `fr`, `fr-FR`, `zh-Hant-TW`.
+    let mut subtags = lang.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (2..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+pub fn is_valid_rate(rate: f32) -> bool {
+    (MIN_SPEECH_RATE..=MAX_SPEECH_RATE).contains(&rate)
+}
+
+pub fn some_or_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    // With `#[serde(default)]`, tells a missing field (leave as is) from a `null` (clear it).
+    Option::<T>::deserialize(deserializer).map(Some)
+}
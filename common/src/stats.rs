@@ -0,0 +1,56 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct DayCount {
+    pub date: NaiveDate,
+    pub count: i64,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct RatingCounts {
+    pub fail: i64,
+    pub hard: i64,
+    pub good: i64,
+    pub easy: i64,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct RetentionBucket {
+    // Human readable interval range, e.g. "2-7j".
+    pub label: String,
+    pub reviews: i64,
+    pub passed: i64,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct AnswerTimes {
+    // What the times are for, a deck's name or a card's front.
+    pub label: String,
+    pub reviews: i64,
+    pub average_reveal_ms: Option<i64>,
+    pub average_answer_ms: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct Stats {
+    pub reviews_per_day: Vec<DayCount>,
+    pub ratings: RatingCounts,
+    pub retention: Vec<RetentionBucket>,
+    pub average_answer_ms: Option<i64>,
+    // Time to flip the card over, out of `average_answer_ms`.
+    pub average_reveal_ms: Option<i64>,
+    pub deck_times: Vec<AnswerTimes>,
+    // Cards slowest to answer first.
+    pub card_times: Vec<AnswerTimes>,
+    pub cards_added_per_day: Vec<DayCount>,
+    pub due_forecast: Vec<DayCount>,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct Activity {
+    // One entry per day (in the user's timezone), oldest first, ending today.
+    pub days: Vec<DayCount>,
+    pub current_streak: i64,
+    pub longest_streak: i64,
+}
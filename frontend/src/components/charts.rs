@@ -0,0 +1,128 @@
+use chrono::Datelike;
+use common::stats::DayCount;
+use yew::prelude::*;
+
+const BAR_WIDTH: f64 = 10.0;
+const BAR_GAP: f64 = 2.0;
+const CHART_HEIGHT: f64 = 60.0;
+
+#[derive(Clone, PartialEq)]
+pub struct Bar {
+    pub label: String,
+    pub value: f64,
+    pub color: &'static str,
+}
+
+impl Bar {
+    pub fn new(label: impl Into<String>, value: f64) -> Self {
+        Self {
+            label: label.into(),
+            value,
+            color: "fill-white",
+        }
+    }
+
+    pub fn color(mut self, color: &'static str) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+#[derive(PartialEq, Properties)]
+pub struct BarChartProps {
+    pub title: String,
+    pub bars: Vec<Bar>,
+}
+
+// Plain SVG bar chart, hovering a bar shows its label and value.
+#[function_component(BarChart)]
+pub fn bar_chart(BarChartProps { title, bars }: &BarChartProps) -> Html {
+    let max_value = bars.iter().map(|bar| bar.value).fold(0.0, f64::max);
+    let width = (bars.len() as f64 * (BAR_WIDTH + BAR_GAP)).max(BAR_WIDTH);
+    let view_box = format!("0 0 {} {}", width, CHART_HEIGHT);
+
+    html! {
+        <div class={ classes!("w-full", "py-4") }>
+            <div class={ classes!("pb-2") }>{ title }</div>
+            <svg
+                viewBox={ view_box }
+                preserveAspectRatio="none"
+                class={ classes!("w-full", "h-32", "portrait:h-64", "border-b", "border-gray-600") }
+            >
+                {
+                    bars.iter().enumerate().map(|(i, bar)| {
+                        let height = if max_value > 0.0 {
+                            bar.value / max_value * CHART_HEIGHT
+                        } else {
+                            0.0
+                        };
+                        html! {
+                            <rect
+                                x={ (i as f64 * (BAR_WIDTH + BAR_GAP)).to_string() }
+                                y={ (CHART_HEIGHT - height).to_string() }
+                                width={ BAR_WIDTH.to_string() }
+                                height={ height.to_string() }
+                                class={ classes!(bar.color) }
+                            >
+                                <title>{ format!("{} : {}", bar.label, bar.value) }</title>
+                            </rect>
+                        }
+                    }).collect::<Html>()
+                }
+            </svg>
+        </div>
+    }
+}
+
+const CELL_SIZE: f64 = 10.0;
+const CELL_GAP: f64 = 2.0;
+
+#[derive(PartialEq, Properties)]
+pub struct HeatmapProps {
+    pub days: Vec<DayCount>,
+}
+
+// GitHub style calendar, one column per week starting on Monday, one cell per day.
+#[function_component(Heatmap)]
+pub fn heatmap(HeatmapProps { days }: &HeatmapProps) -> Html {
+    let offset = days
+        .first()
+        .map(|day| day.date.weekday().num_days_from_monday() as usize)
+        .unwrap_or(0);
+    let max_count = days.iter().map(|day| day.count).max().unwrap_or(0);
+    let weeks = (days.len() + offset).div_ceil(7);
+    let view_box = format!(
+        "0 0 {} {}",
+        weeks as f64 * (CELL_SIZE + CELL_GAP),
+        7.0 * (CELL_SIZE + CELL_GAP),
+    );
+
+    html! {
+        <svg viewBox={ view_box } class={ classes!("w-full") }>
+            {
+                days.iter().enumerate().map(|(i, day)| {
+                    let slot = i + offset;
+                    let color = match (day.count, max_count) {
+                        (0, _) => "fill-gray-800",
+                        (count, max) if count * 4 <= max => "fill-green-900",
+                        (count, max) if count * 2 <= max => "fill-green-700",
+                        (count, max) if count * 4 <= max * 3 => "fill-green-500",
+                        _ => "fill-green-300",
+                    };
+                    html! {
+                        <rect
+                            x={ ((slot / 7) as f64 * (CELL_SIZE + CELL_GAP)).to_string() }
+                            y={ ((slot % 7) as f64 * (CELL_SIZE + CELL_GAP)).to_string() }
+                            width={ CELL_SIZE.to_string() }
+                            height={ CELL_SIZE.to_string() }
+                            rx="2"
+                            class={ classes!(color) }
+                        >
+                            <title>{ format!("{} : {}", day.date.format("%d/%m/%Y"), day.count) }</title>
+                        </rect>
+                    }
+                }).collect::<Html>()
+            }
+        </svg>
+    }
+}
@@ -0,0 +1,41 @@
+use common::models::Card;
+use yew::prelude::*;
+
+#[derive(PartialEq, Properties)]
+pub struct DuplicateNoticeProps {
+    pub existing: Card,
+    pub on_open: Callback<Card>,
+    pub on_add_anyway: Callback<()>,
+}
+
+// Shown when the backend refuses a new card because it looks like one we already have.
+#[function_component(DuplicateNotice)]
+pub fn duplicate_notice(
+    DuplicateNoticeProps {
+        existing,
+        on_open,
+        on_add_anyway,
+    }: &DuplicateNoticeProps,
+) -> Html {
+    let on_open_click = {
+        let existing = existing.clone();
+        let on_open = on_open.clone();
+        Callback::from(move |_| on_open.emit(existing.clone()))
+    };
+    let on_add_anyway_click = {
+        let on_add_anyway = on_add_anyway.clone();
+        Callback::from(move |_| on_add_anyway.emit(()))
+    };
+
+    html! {
+        <div class={ classes!("py-4", "text-2xl", "portrait:text-4xl") }>
+            <div class={ classes!("pb-2") }>
+                { format!("Existe déjà : {} / {}", existing.front, existing.back) }
+            </div>
+            <div class={ classes!("flex", "justify-around") }>
+                <button type="button" onclick={ on_open_click }>{ "Ouvrir" }</button>
+                <button type="button" onclick={ on_add_anyway_click }>{ "Ajouter quand même" }</button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,64 @@
+use common::markup::{has_markup, parse_markup, Block, Inline};
+use yew::prelude::*;
+
+fn render_inlines(inlines: &[Inline]) -> Html {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => html! { { text } },
+            Inline::Bold(children) => html! { <strong>{ render_inlines(children) }</strong> },
+            Inline::Italic(children) => html! { <em>{ render_inlines(children) }</em> },
+            Inline::Ruby { base, annotation } => html! {
+                <ruby>{ base }<rp>{ "(" }</rp><rt>{ annotation }</rt><rp>{ ")" }</rp></ruby>
+            },
+        })
+        .collect::<Html>()
+}
+
+pub fn render_markup(text: &str) -> Html {
+    // Card text as markup, see `common::markup`. Only ever builds text nodes and a few fixed
+    // tags, so whatever is in the card can't inject HTML.
+    parse_markup(text)
+        .iter()
+        .map(|block| match block {
+            Block::Line(inlines) if inlines.is_empty() => html! { <br /> },
+            Block::Line(inlines) => html! { <div>{ render_inlines(inlines) }</div> },
+            Block::List { ordered, items } => {
+                let items = items
+                    .iter()
+                    .map(|item| html! { <li>{ render_inlines(item) }</li> })
+                    .collect::<Html>();
+                if *ordered {
+                    html! { <ol class={ classes!("list-decimal", "text-left", "pl-8") }>{ items }</ol> }
+                } else {
+                    html! { <ul class={ classes!("list-disc", "text-left", "pl-8") }>{ items }</ul> }
+                }
+            }
+        })
+        .collect::<Html>()
+}
+
+#[derive(PartialEq, Properties)]
+pub struct MarkupPreviewProps {
+    pub text: String,
+}
+
+// How card text being edited will come out, only when there is any markup in it.
+#[function_component(MarkupPreview)]
+pub fn markup_preview(MarkupPreviewProps { text }: &MarkupPreviewProps) -> Html {
+    if !has_markup(text) {
+        return html! {};
+    }
+    html! {
+        <div
+            class={
+                classes!(
+                    "p-2", "mb-2", "text-center", "rounded-lg", "border-2", "border-gray-600", "border-dashed"
+                )
+            }
+            title={ "Aperçu" }
+        >
+            { render_markup(text) }
+        </div>
+    }
+}
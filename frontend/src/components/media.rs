@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::models::Media;
+use common::query_params::MediaUploadQuery;
+use common::CardSide;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, BlobEvent, BlobPropertyBag, HtmlAudioElement, HtmlInputElement, MediaRecorder,
+    MediaStream, MediaStreamConstraints,
+};
+use yew::prelude::*;
+
+use crate::api;
+use crate::emojis;
+
+#[derive(PartialEq, Properties)]
+pub struct MediaViewProps {
+    pub media: Vec<Media>,
+    #[prop_or_default]
+    pub class: Classes,
+}
+
+// Pictures attached to a card side, sounds are left to `play_audio`.
+#[function_component(MediaView)]
+pub fn media_view(MediaViewProps { media, class }: &MediaViewProps) -> Html {
+    media
+        .iter()
+        .filter(|media| media.is_image())
+        .map(|media| {
+            html! {
+                <img
+                    key={ media.id }
+                    src={ media.url() }
+                    class={ classes!("object-contain", class.clone()) }
+                />
+            }
+        })
+        .collect::<Html>()
+}
+
+pub fn play_audio(media: &[Media]) {
+    // Play the first clip among `media`, if any. Browsers may refuse until the user has
+    // interacted with the page, nothing to be done about that.
+    if let Some(clip) = media.iter().find(|media| media.is_audio()) {
+        if let Ok(audio) = HtmlAudioElement::new_with_src(&clip.url()) {
+            audio.play().ok();
+        }
+    }
+}
+
+// A recording in progress, with what it has produced so far.
+struct Recording {
+    recorder: MediaRecorder,
+    side: CardSide,
+    chunks: Rc<RefCell<Vec<Blob>>>,
+    // Kept alive for as long as the recorder may call it.
+    _on_data: Closure<dyn FnMut(BlobEvent)>,
+}
+
+async fn start_recording(side: CardSide) -> Result<Recording, JsValue> {
+    let mut constraints = MediaStreamConstraints::new();
+    constraints.audio(&JsValue::TRUE);
+    let devices = web_sys::window().unwrap().navigator().media_devices()?;
+    let stream: MediaStream =
+        JsFuture::from(devices.get_user_media_with_constraints(&constraints)?)
+            .await?
+            .unchecked_into();
+    let recorder = MediaRecorder::new_with_media_stream(&stream)?;
+
+    let chunks = Rc::new(RefCell::new(Vec::new()));
+    let on_data = {
+        let chunks = chunks.clone();
+        Closure::wrap(Box::new(move |e: BlobEvent| {
+            if let Some(data) = e.data() {
+                chunks.borrow_mut().push(data);
+            }
+        }) as Box<dyn FnMut(BlobEvent)>)
+    };
+    recorder.set_ondataavailable(Some(on_data.as_ref().unchecked_ref()));
+    recorder.start()?;
+    Ok(Recording {
+        recorder,
+        side,
+        chunks,
+        _on_data: on_data,
+    })
+}
+
+#[derive(PartialEq, Properties)]
+pub struct MediaEditorProps {
+    pub deck_id: i32,
+    pub card_id: i32,
+}
+
+// Attach or remove files on both sides of an existing card.
+#[function_component(MediaEditor)]
+pub fn media_editor(MediaEditorProps { deck_id, card_id }: &MediaEditorProps) -> Html {
+    let url = format!("/api/decks/{}/cards/{}/media/", deck_id, card_id);
+    let media = use_state_eq(Vec::<Media>::new);
+    {
+        let media = media.clone();
+        let url = url.clone();
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<Vec<Media>, _>(fetched_media) = api::get(&url).await {
+                        media.set(fetched_media);
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    let upload = {
+        let media = media.clone();
+        let url = url.clone();
+        Callback::from(move |(side, blob): (CardSide, Blob)| {
+            let media = media.clone();
+            let url = format!(
+                "{}?{}",
+                url,
+                serde_qs::to_string(&MediaUploadQuery { side }).unwrap()
+            );
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok::<Media, _>(uploaded) = api::post_file(&url, &blob).await {
+                    let mut media_vec = (*media).clone();
+                    media_vec.push(uploaded);
+                    media.set(media_vec);
+                } // TODO else ...
+            });
+        })
+    };
+
+    let on_upload = |side: CardSide| {
+        let upload = upload.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Some(file) = input.files().and_then(|files| files.get(0)) {
+                upload.emit((side, file.into()));
+            }
+            input.set_value("");
+        })
+    };
+
+    let recording = use_mut_ref(|| None::<Recording>);
+    // Side being recorded, mirrors `recording` for rendering.
+    let recording_side = use_state_eq(|| None);
+    let on_record_click = |side: CardSide| {
+        let recording = recording.clone();
+        let recording_side = recording_side.clone();
+        let upload = upload.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            let current = recording.borrow_mut().take();
+            if let Some(current) = current {
+                // Let the recorder flush its last chunk before putting the clip together.
+                let on_stop = {
+                    let upload = upload.clone();
+                    let chunks = current.chunks.clone();
+                    let mime_type = current.recorder.mime_type();
+                    let side = current.side;
+                    Closure::once(Box::new(move || {
+                        let parts = js_sys::Array::new();
+                        for chunk in chunks.borrow().iter() {
+                            parts.push(chunk);
+                        }
+                        let mut options = BlobPropertyBag::new();
+                        options.type_(&mime_type);
+                        if let Ok(blob) = Blob::new_with_blob_sequence_and_options(&parts, &options)
+                        {
+                            upload.emit((side, blob));
+                        }
+                    }) as Box<dyn FnOnce()>)
+                };
+                current
+                    .recorder
+                    .set_onstop(Some(on_stop.as_ref().unchecked_ref()));
+                on_stop.forget();
+                current.recorder.stop().ok();
+                current
+                    .recorder
+                    .stream()
+                    .get_tracks()
+                    .for_each(&mut |track, _, _| {
+                        track.unchecked_into::<web_sys::MediaStreamTrack>().stop();
+                    });
+                recording_side.set(None);
+                if current.side == side {
+                    return;
+                }
+            }
+            let recording = recording.clone();
+            let recording_side = recording_side.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match start_recording(side).await {
+                    Ok(started) => {
+                        *recording.borrow_mut() = Some(started);
+                        recording_side.set(Some(side));
+                    }
+                    Err(e) => log::error!("could not record: {:?}", e),
+                }
+            });
+        })
+    };
+
+    let render_side = |side: CardSide, label: &str| {
+        let thumbnails = media
+            .iter()
+            .filter(|m| m.side == side)
+            .map(|m| {
+                let onclick = {
+                    let media = media.clone();
+                    let media_id = m.id;
+                    Callback::from(move |e: MouseEvent| {
+                        e.prevent_default();
+                        let media = media.clone();
+                        wasm_bindgen_futures::spawn_local(async move {
+                            let url = format!("/api/media/{}/", media_id);
+                            if api::delete(&url).await.is_ok() {
+                                media.set(
+                                    (*media)
+                                        .iter()
+                                        .filter(|m| m.id != media_id)
+                                        .cloned()
+                                        .collect(),
+                                );
+                            }
+                        });
+                    })
+                };
+                let preview = if m.is_audio() {
+                    html! { <audio controls=true src={ m.url() } class={ classes!("h-8", "w-48") } /> }
+                } else {
+                    html! { <MediaView media={ vec![m.clone()] } class={ classes!("h-16") } /> }
+                };
+                html! {
+                    <span key={ m.id } class={ classes!("relative", "mr-2") }>
+                        { preview }
+                        <button { onclick } class={ classes!("absolute", "top-0", "right-0", "text-base") }>
+                            { emojis::AXE }
+                        </button>
+                    </span>
+                }
+            })
+            .collect::<Html>();
+        html! {
+            <div class={ classes!("flex", "items-center", "mt-2", "text-2xl") }>
+                <label class={ classes!("cursor-pointer", "mr-4") }>
+                    { label }
+                    <input
+                        type="file"
+                        accept="image/*,audio/*"
+                        class={ classes!("hidden") }
+                        onchange={ on_upload(side) }
+                    />
+                </label>
+                <button
+                    onclick={ on_record_click(side) }
+                    class={ classes!("mr-4") }
+                    title={ "Enregistrer un son" }
+                >
+                    { if *recording_side == Some(side) { emojis::STOP } else { emojis::MICROPHONE } }
+                </button>
+                { thumbnails }
+            </div>
+        }
+    };
+
+    html! {
+        <div class={ classes!("flex", "flex-col") }>
+            { render_side(CardSide::Front, "+ fichier de face") }
+            { render_side(CardSide::Back, "+ fichier arrière") }
+        </div>
+    }
+}
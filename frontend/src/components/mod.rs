@@ -1 +1,6 @@
+pub mod charts;
+pub mod duplicate;
+pub mod markup;
+pub mod media;
 pub mod modals;
+pub mod search;
@@ -1,11 +1,16 @@
-use common::models::Card;
+use common::models::{Card, Deck, TransferMode, TransferPayload};
+use common::query_params::{DuplicateScope, NewCardQuery};
+use common::CardSide;
 use serde_json::json;
-use web_sys::HtmlTextAreaElement;
+use web_sys::{HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use super::Modal;
 use crate::api;
+use crate::components::duplicate::DuplicateNotice;
+use crate::components::markup::MarkupPreview;
+use crate::components::media::MediaEditor;
 use crate::emojis;
 use crate::AppContext;
 use crate::AppRoute;
@@ -51,9 +56,32 @@ pub fn card_form_modal(CardFormModalProps { deck_id, card_id }: &CardFormModalPr
         );
     }
 
+    // For the deck's languages, see `lang` on the text areas.
+    let deck = use_state_eq(|| None::<Deck>);
+    {
+        let deck = deck.clone();
+        let deck_id = *deck_id;
+        use_effect_with_deps(
+            move |_| {
+                api::get_deck(
+                    deck_id,
+                    Box::new(move |fetched_deck| deck.set(Some(fetched_deck))),
+                );
+                || ()
+            },
+            (),
+        );
+    }
+    let lang = |side: CardSide| {
+        (*deck)
+            .as_ref()
+            .and_then(|deck| deck.lang(side))
+            .map(str::to_string)
+    };
+
     let on_front_change = {
         let front = front.clone();
-        Callback::from(move |e: Event| {
+        Callback::from(move |e: InputEvent| {
             let textarea: HtmlTextAreaElement = e.target_unchecked_into();
             front.set(textarea.value());
         })
@@ -61,24 +89,36 @@ pub fn card_form_modal(CardFormModalProps { deck_id, card_id }: &CardFormModalPr
 
     let on_back_change = {
         let back = back.clone();
-        Callback::from(move |e: Event| {
+        Callback::from(move |e: InputEvent| {
             let textarea: HtmlTextAreaElement = e.target_unchecked_into();
             back.set(textarea.value());
         })
     };
 
-    let onsubmit = {
+    // Set when the backend turned down a new card as a duplicate of this one.
+    let duplicate = use_state(|| None);
+
+    let save = {
         let api_url = api_url.clone();
         let front = front.clone();
         let back = back.clone();
-        let deck_id = *deck_id;
+        let duplicate = duplicate.clone();
+        let is_new = card_id.is_none();
         let ctx = ctx.clone();
         let history = history.clone();
-        Callback::from(move |e: FocusEvent| {
-            e.prevent_default();
+        Callback::from(move |allow_duplicate: bool| {
             let ctx = ctx.clone();
             let history = history.clone();
-            let api_url = api_url.clone();
+            let duplicate = duplicate.clone();
+            let api_url = if is_new {
+                let query = NewCardQuery {
+                    duplicate_scope: DuplicateScope::All,
+                    allow_duplicate,
+                };
+                format!("{}?{}", api_url, serde_qs::to_string(&query).unwrap())
+            } else {
+                api_url.clone()
+            };
             if front.is_empty() || back.is_empty() {
                 return;
             }
@@ -87,17 +127,44 @@ pub fn card_form_modal(CardFormModalProps { deck_id, card_id }: &CardFormModalPr
                 "back": *back,
             });
             wasm_bindgen_futures::spawn_local(async move {
-                let api_url = api_url.clone();
-                if api::post_vanilla(&api_url, payload).await.is_ok() {
-                    ctx.set_modal.emit(None);
-                    // TODO doesn't actually trigger refetch.
-                    history.go(0);
-                    //history.replace(AppRoute::DeckDetail { deck_id });
-                } // TODO else ...
+                match api::post_vanilla(&api_url, payload).await {
+                    Ok(_) => {
+                        ctx.set_modal.emit(None);
+                        // TODO doesn't actually trigger refetch.
+                        history.go(0);
+                        //history.replace(AppRoute::DeckDetail { deck_id });
+                    }
+                    Err(e) if e.status == Some(409) => {
+                        duplicate.set(serde_json::from_str::<Card>(&e.to_string()).ok());
+                    }
+                    Err(_) => {} // TODO else ...
+                }
             });
         })
     };
 
+    let onsubmit = {
+        let save = save.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            save.emit(false);
+        })
+    };
+
+    let on_open_duplicate = {
+        let ctx = ctx.clone();
+        Callback::from(move |existing: Card| {
+            let deck_id = existing.deck_id;
+            let card_id = existing.id;
+            // Keyed so the form gets mounted afresh and fetches the existing card.
+            ctx.set_modal.emit(Some(html! {
+                <CardFormModal key={ card_id } { deck_id } { card_id } />
+            }));
+        })
+    };
+
+    let on_add_anyway = Callback::from(move |_| save.emit(true));
+
     let on_delete = {
         let deck_id = *deck_id;
 
@@ -121,16 +188,47 @@ pub fn card_form_modal(CardFormModalProps { deck_id, card_id }: &CardFormModalPr
             <form { onsubmit } class={ classes!("flex", "flex-col", "text-3xl", "portrait:text-6xl") }>
                 <textarea
                     value={ (*front).clone() }
-                    onchange={ on_front_change }
+                    oninput={ on_front_change }
+                    lang={ lang(CardSide::Front) }
+                    spellcheck="true"
                     placeholder={ "de face" }
                     class={ classes!("h-64") }
                 />
+                <MarkupPreview text={ (*front).clone() } />
                 <textarea
                     value={ (*back).clone() }
-                    onchange={ on_back_change }
+                    oninput={ on_back_change }
+                    lang={ lang(CardSide::Back) }
+                    spellcheck="true"
                     placeholder={ "arrière" }
                     class={ classes!("h-64") }
                 />
+                <MarkupPreview text={ (*back).clone() } />
+                {
+                    if let Some(existing) = (*duplicate).clone() {
+                        html! {
+                            <DuplicateNotice
+                                { existing }
+                                on_open={ on_open_duplicate }
+                                { on_add_anyway }
+                            />
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if let Some(card_id) = *card_id {
+                        html! {
+                            <>
+                                <MediaEditor deck_id={ *deck_id } { card_id } />
+                                <CardTransfer deck_id={ *deck_id } { card_id } />
+                            </>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
                 <div
                     class={
                         classes!(
@@ -157,3 +255,125 @@ pub fn card_form_modal(CardFormModalProps { deck_id, card_id }: &CardFormModalPr
         </Modal>
     }
 }
+
+#[derive(PartialEq, Properties)]
+struct CardTransferProps {
+    deck_id: i32,
+    card_id: i32,
+}
+
+// Sends the card over to another deck, as is or as a copy.
+#[function_component(CardTransfer)]
+fn card_transfer(CardTransferProps { deck_id, card_id }: &CardTransferProps) -> Html {
+    let ctx = use_context::<AppContext>().unwrap();
+    let history = use_history().unwrap();
+    let deck_id = *deck_id;
+    let card_id = *card_id;
+
+    let decks = use_state_eq(Vec::<Deck>::new);
+    let target_deck_id = use_state_eq(|| None);
+    {
+        let decks = decks.clone();
+        let target_deck_id = target_deck_id.clone();
+        use_effect_with_deps(
+            move |_| {
+                api::get_other_decks(
+                    deck_id,
+                    Box::new(move |fetched_decks| {
+                        target_deck_id.set(fetched_decks.first().map(|deck| deck.id));
+                        decks.set(fetched_decks);
+                    }),
+                );
+                || ()
+            },
+            (),
+        );
+    }
+
+    let on_target_change = {
+        let target_deck_id = target_deck_id.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            target_deck_id.set(select.value().parse::<i32>().ok());
+        })
+    };
+
+    let keep_history = use_state_eq(|| true);
+    let on_keep_history_change = {
+        let keep_history = keep_history.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            keep_history.set(input.checked());
+        })
+    };
+
+    let transfer = {
+        let target_deck_id = target_deck_id.clone();
+        let keep_history = keep_history.clone();
+        Callback::from(move |mode: TransferMode| {
+            let target_deck_id = match *target_deck_id {
+                Some(target_deck_id) => target_deck_id,
+                None => return,
+            };
+            let ctx = ctx.clone();
+            let history = history.clone();
+            let url = format!("/api/decks/{}/cards/transfer/", deck_id);
+            let payload = serde_json::to_value(TransferPayload {
+                card_ids: vec![card_id],
+                target_deck_id,
+                mode,
+                keep_history: *keep_history,
+            })
+            .unwrap();
+            wasm_bindgen_futures::spawn_local(async move {
+                if api::post_vanilla(&url, payload).await.is_ok() {
+                    ctx.set_modal.emit(None);
+                    // TODO doesn't actually trigger refetch.
+                    history.go(0);
+                } // TODO else ...
+            });
+        })
+    };
+    let on_move_click = {
+        let transfer = transfer.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            transfer.emit(TransferMode::Move);
+        })
+    };
+    let on_copy_click = Callback::from(move |e: MouseEvent| {
+        e.prevent_default();
+        transfer.emit(TransferMode::Copy);
+    });
+
+    if decks.is_empty() {
+        return html! {};
+    }
+    html! {
+        <div class={ classes!("flex", "items-center", "justify-between", "mt-5", "text-2xl") }>
+            <select onchange={ on_target_change } class={ classes!("bg-blk") }>
+                {
+                    decks.iter().map(|deck| html! {
+                        <option
+                            value={ deck.id.to_string() }
+                            selected={ *target_deck_id == Some(deck.id) }
+                        >
+                            { &deck.name }
+                        </option>
+                    }).collect::<Html>()
+                }
+            </select>
+            <span>
+                <input
+                    id="keep-history"
+                    type="checkbox"
+                    checked={ *keep_history }
+                    onchange={ on_keep_history_change }
+                />
+                <label for="keep-history">{ "garder l'historique" }</label>
+            </span>
+            <button onclick={ on_move_click }>{ "Déplacer" }</button>
+            <button onclick={ on_copy_click }>{ "Copier" }</button>
+        </div>
+    }
+}
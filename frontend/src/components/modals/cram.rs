@@ -0,0 +1,110 @@
+use common::models::Deck;
+use common::query_params::RevisionQuery;
+use common::search::SEARCH_HELP;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use super::Modal;
+use crate::AppContext;
+use crate::AppRoute;
+
+#[derive(PartialEq, Properties)]
+pub struct CramModalProps {
+    pub deck: Deck,
+}
+
+// Launches a cram session: the cards matching every filter filled in, due or not, without
+// their scheduling being touched.
+#[function_component(CramModal)]
+pub fn cram_modal(CramModalProps { deck }: &CramModalProps) -> Html {
+    let ctx = use_context::<AppContext>().unwrap();
+    let history = use_history().unwrap();
+
+    let tags = use_state_eq(String::new);
+    let failed_days = use_state_eq(|| None::<i32>);
+    let added_days = use_state_eq(|| None::<i32>);
+    let search_term = use_state_eq(String::new);
+    let on_text_input = |value: UseStateHandle<String>| {
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            value.set(input.value());
+        })
+    };
+    let on_days_input = |value: UseStateHandle<Option<i32>>| {
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            // Left empty for no filter.
+            value.set(input.value().parse::<i32>().ok().filter(|days| *days > 0));
+        })
+    };
+
+    let onsubmit = {
+        let deck_id = deck.id;
+        let tags = tags.clone();
+        let failed_days = failed_days.clone();
+        let added_days = added_days.clone();
+        let search_term = search_term.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            let query = RevisionQuery {
+                cram: true,
+                tags: (*tags).clone(),
+                failed_days: *failed_days,
+                added_days: *added_days,
+                search_term: (*search_term).clone(),
+            };
+            ctx.set_modal.emit(None);
+            history
+                .push_with_query(AppRoute::Revision { deck_id }, query)
+                .unwrap();
+        })
+    };
+
+    let days_value = |days: &Option<i32>| days.map(|days| days.to_string()).unwrap_or_default();
+
+    html! {
+        <Modal title={ Some("Bachoter") }>
+            <form { onsubmit } class={ classes!("flex", "flex-col", "text-2xl") }>
+                <input
+                    type="text"
+                    class={ classes!("mb-4") }
+                    placeholder={ "Étiquettes (séparées par des virgules)" }
+                    value={ (*tags).clone() }
+                    oninput={ on_text_input(tags.clone()) }
+                />
+                <div class={ classes!("flex", "justify-between", "items-center", "mb-4") }>
+                    <span>{ "ratées ces derniers jours :" }</span>
+                    <input
+                        type="number"
+                        min="1"
+                        class={ classes!("w-24") }
+                        value={ days_value(&failed_days) }
+                        oninput={ on_days_input(failed_days.clone()) }
+                    />
+                </div>
+                <div class={ classes!("flex", "justify-between", "items-center", "mb-4") }>
+                    <span>{ "ajoutées ces derniers jours :" }</span>
+                    <input
+                        type="number"
+                        min="1"
+                        class={ classes!("w-24") }
+                        value={ days_value(&added_days) }
+                        oninput={ on_days_input(added_days.clone()) }
+                    />
+                </div>
+                <input
+                    type="text"
+                    class={ classes!("mb-4") }
+                    placeholder={ "Recherche" }
+                    title={ SEARCH_HELP }
+                    value={ (*search_term).clone() }
+                    oninput={ on_text_input(search_term.clone()) }
+                />
+                <button type={ "submit" } class={ classes!("text-right") }>
+                    { "Bachoter" }
+                </button>
+            </form>
+        </Modal>
+    }
+}
@@ -1,12 +1,14 @@
 use common::models::Deck;
-use common::FlipMode;
+use common::speech::{MAX_SPEECH_RATE, MIN_SPEECH_RATE};
+use common::{CardSide, FlipMode, NewCardOrder, ReviewOrder};
 use serde_json::json;
-use web_sys::HtmlInputElement;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 use yew::prelude::*;
 
 use super::Modal;
 use crate::api;
 use crate::emojis;
+use crate::speech;
 use crate::AppContext;
 
 #[derive(PartialEq, Properties)]
@@ -51,8 +53,139 @@ pub fn deck_form_modal(DeckFormModalProps { deck, update_deck }: &DeckFormModalP
         })
     };
 
+    let reverse_siblings = use_state_eq(|| deck.reverse_siblings);
+    let on_reverse_siblings_change = {
+        let reverse_siblings = reverse_siblings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            reverse_siblings.set(input.checked());
+        })
+    };
+
+    let review_order = use_state_eq(|| deck.review_order);
+    let on_review_order_change = {
+        let review_order = review_order.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            match &*select.value() {
+                "weighted" => review_order.set(ReviewOrder::Weighted),
+                "due" => review_order.set(ReviewOrder::Due),
+                "random" => review_order.set(ReviewOrder::Random),
+                "hardest" => review_order.set(ReviewOrder::Hardest),
+                _ => (),
+            }
+        })
+    };
+
+    // New cards get their own share of each session only with a ratio.
+    let new_card_ratio = use_state_eq(|| deck.new_card_ratio);
+    let on_new_card_split_change = {
+        let new_card_ratio = new_card_ratio.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            new_card_ratio.set(if input.checked() { Some(20) } else { None });
+        })
+    };
+    let on_new_card_ratio_input = {
+        let new_card_ratio = new_card_ratio.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            new_card_ratio.set(Some(input.value().parse::<i16>().unwrap()));
+        })
+    };
+    let new_card_order = use_state_eq(|| deck.new_card_order);
+    let on_new_card_order_change = {
+        let new_card_order = new_card_order.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            match &*select.value() {
+                "created" => new_card_order.set(NewCardOrder::Created),
+                "random" => new_card_order.set(NewCardOrder::Random),
+                _ => (),
+            }
+        })
+    };
+
+    // Easy answers slower than this count as good, when set.
+    let slow_answer_ms = use_state_eq(|| deck.slow_answer_ms);
+    let on_slow_answer_change = {
+        let slow_answer_ms = slow_answer_ms.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            slow_answer_ms.set(if input.checked() {
+                Some(10 * 1000)
+            } else {
+                None
+            });
+        })
+    };
+    let on_slow_answer_input = {
+        let slow_answer_ms = slow_answer_ms.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Ok(seconds) = input.value().parse::<i32>() {
+                if seconds > 0 {
+                    slow_answer_ms.set(Some(seconds * 1000));
+                }
+            }
+        })
+    };
+
+    let autoplay_front = use_state_eq(|| deck.autoplay_front);
+    let autoplay_back = use_state_eq(|| deck.autoplay_back);
+    let on_autoplay_change = |autoplay: UseStateHandle<bool>| {
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            autoplay.set(input.checked());
+        })
+    };
+
+    // Speech settings, empty strings for the browser's defaults.
+    let front_lang = use_state_eq(|| deck.front_lang.clone().unwrap_or_default());
+    let back_lang = use_state_eq(|| deck.back_lang.clone().unwrap_or_default());
+    let front_voice = use_state_eq(|| deck.front_voice.clone().unwrap_or_default());
+    let back_voice = use_state_eq(|| deck.back_voice.clone().unwrap_or_default());
+    let speech_rate = use_state_eq(|| deck.speech_rate);
+    let on_speech_rate_input = {
+        let speech_rate = speech_rate.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            speech_rate.set(input.value().parse::<f32>().unwrap());
+        })
+    };
+
+    // Re-render once the browser is done listing its voices.
+    let voices_changed = use_state(|| 0);
+    {
+        let voices_changed = voices_changed.clone();
+        use_effect_with_deps(
+            move |_| {
+                let closure = speech::watch_voices(Box::new(move || {
+                    voices_changed.set(*voices_changed + 1);
+                }));
+                move || {
+                    speech::unwatch_voices();
+                    drop(closure);
+                }
+            },
+            (),
+        );
+    }
+
     let onsubmit = {
         let deck_id = deck.id;
+        let front_lang = front_lang.clone();
+        let back_lang = back_lang.clone();
+        let front_voice = front_voice.clone();
+        let back_voice = back_voice.clone();
+        let speech_rate = speech_rate.clone();
+        let reverse_siblings = reverse_siblings.clone();
+        let review_order = review_order.clone();
+        let new_card_order = new_card_order.clone();
+        let new_card_ratio = new_card_ratio.clone();
+        let slow_answer_ms = slow_answer_ms.clone();
+        let autoplay_front = autoplay_front.clone();
+        let autoplay_back = autoplay_back.clone();
         let name = name.clone();
         let revision_length = revision_length.clone();
         let flip_mode = flip_mode.clone();
@@ -69,6 +202,18 @@ pub fn deck_form_modal(DeckFormModalProps { deck, update_deck }: &DeckFormModalP
                 "name": *name,
                 "revision_length": *revision_length,
                 "flip_mode": *flip_mode,
+                "reverse_siblings": *reverse_siblings,
+                "review_order": *review_order,
+                "new_card_order": *new_card_order,
+                "new_card_ratio": *new_card_ratio,
+                "slow_answer_ms": *slow_answer_ms,
+                "autoplay_front": *autoplay_front,
+                "autoplay_back": *autoplay_back,
+                "front_lang": non_empty(&front_lang),
+                "back_lang": non_empty(&back_lang),
+                "front_voice": non_empty(&front_voice),
+                "back_voice": non_empty(&back_voice),
+                "speech_rate": *speech_rate,
             });
             wasm_bindgen_futures::spawn_local(async move {
                 if let Ok::<Deck, _>(deck) = api::post(&url, payload).await {
@@ -79,6 +224,65 @@ pub fn deck_form_modal(DeckFormModalProps { deck, update_deck }: &DeckFormModalP
         })
     };
 
+    let render_speech_side = |side: CardSide,
+                              label: &str,
+                              lang: UseStateHandle<String>,
+                              voice: UseStateHandle<String>| {
+        let on_lang_input = {
+            let lang = lang.clone();
+            let voice = voice.clone();
+            Callback::from(move |e: InputEvent| {
+                let input: HtmlInputElement = e.target_unchecked_into();
+                lang.set(input.value().trim().to_string());
+                // A voice for the old language is no good for the new one.
+                voice.set(String::new());
+            })
+        };
+        let on_voice_change = {
+            let voice = voice.clone();
+            Callback::from(move |e: Event| {
+                let select: HtmlSelectElement = e.target_unchecked_into();
+                voice.set(select.value());
+            })
+        };
+        let voices = if lang.is_empty() {
+            Vec::new()
+        } else {
+            speech::voices_for(&lang)
+        };
+        let list_id = format!("langs-{:?}", side);
+        html! {
+            <div class={ classes!("w-full", "flex", "justify-between", "text-2xl", "items-center", "pt-4") }>
+                <span>{ label }</span>
+                <input
+                    type="text"
+                    list={ list_id.clone() }
+                    class={ classes!("w-32") }
+                    placeholder={ "fr-FR" }
+                    value={ (*lang).clone() }
+                    oninput={ on_lang_input }
+                />
+                <datalist id={ list_id }>
+                    {
+                        COMMON_LANGS.iter().map(|lang| html! {
+                            <option value={ *lang } />
+                        }).collect::<Html>()
+                    }
+                </datalist>
+                <select onchange={ on_voice_change } class={ classes!("w-48", "bg-blk") }>
+                    <option value="" selected={ voice.is_empty() }>{ "voix par défaut" }</option>
+                    {
+                        voices.iter().map(|v| html! {
+                            <option value={ v.name() } selected={ *voice == v.name() }>
+                                { v.name() }
+                            </option>
+                        }).collect::<Html>()
+                    }
+                </select>
+            </div>
+        }
+    };
+
     html! {
         <Modal title={ Some("Modifier le paquet") }>
             <form { onsubmit }>
@@ -136,6 +340,146 @@ pub fn deck_form_modal(DeckFormModalProps { deck, update_deck }: &DeckFormModalP
                             <label for="back">{ "les deux" }</label>
                         </span>
                     </div>
+                    {
+                        if *flip_mode == FlipMode::Both {
+                            html! {
+                                <div class={ classes!("w-full", "text-2xl", "pt-4") }>
+                                    <input
+                                        id="reverse-siblings"
+                                        type="checkbox"
+                                        checked={ *reverse_siblings }
+                                        onchange={ on_reverse_siblings_change }
+                                    />
+                                    <label for="reverse-siblings">
+                                        { "chaque sens révisé à part" }
+                                    </label>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class={ classes!("w-full", "flex", "justify-between", "text-2xl", "items-center", "pt-4") }>
+                        <span>{ "ordre :" }</span>
+                        <select onchange={ on_review_order_change } class={ classes!("w-48", "bg-blk") }>
+                            {
+                                [
+                                    (ReviewOrder::Weighted, "weighted", "pondéré"),
+                                    (ReviewOrder::Due, "due", "par échéance"),
+                                    (ReviewOrder::Random, "random", "aléatoire"),
+                                    (ReviewOrder::Hardest, "hardest", "difficiles d'abord"),
+                                ].into_iter().map(|(order, value, label)| html! {
+                                    <option { value } selected={ *review_order == order }>{ label }</option>
+                                }).collect::<Html>()
+                            }
+                        </select>
+                    </div>
+                    <div class={ classes!("w-full", "flex", "justify-between", "text-2xl", "items-center", "pt-4") }>
+                        <span>
+                            <input
+                                id="new-card-split"
+                                type="checkbox"
+                                checked={ new_card_ratio.is_some() }
+                                onchange={ on_new_card_split_change }
+                            />
+                            <label for="new-card-split">{ "nouvelles à part" }</label>
+                        </span>
+                        {
+                            if let Some(ratio) = *new_card_ratio {
+                                html! {
+                                    <>
+                                        <input
+                                            oninput={ on_new_card_ratio_input }
+                                            type="range"
+                                            min="0"
+                                            max="100"
+                                            step="5"
+                                            value={ ratio.to_string() }
+                                            class={ classes!("w-32") }
+                                        />
+                                        <span>{ format!("{} %", ratio) }</span>
+                                    </>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <select onchange={ on_new_card_order_change } class={ classes!("w-32", "bg-blk") }>
+                            <option value="created" selected={ *new_card_order == NewCardOrder::Created }>
+                                { "par ajout" }
+                            </option>
+                            <option value="random" selected={ *new_card_order == NewCardOrder::Random }>
+                                { "aléatoire" }
+                            </option>
+                        </select>
+                    </div>
+                    <div class={ classes!("w-full", "flex", "justify-between", "text-2xl", "items-center", "pt-4") }>
+                        <span>
+                            <input
+                                id="slow-answer"
+                                type="checkbox"
+                                checked={ slow_answer_ms.is_some() }
+                                onchange={ on_slow_answer_change }
+                            />
+                            <label for="slow-answer">{ "facile si répondu en moins de" }</label>
+                        </span>
+                        {
+                            if let Some(ms) = *slow_answer_ms {
+                                html! {
+                                    <span>
+                                        <input
+                                            oninput={ on_slow_answer_input }
+                                            type="number"
+                                            min="1"
+                                            value={ (ms / 1000).to_string() }
+                                            class={ classes!("w-16", "bg-blk") }
+                                        />
+                                        { " s" }
+                                    </span>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </div>
+                    <div class={ classes!("w-full", "flex", "justify-between", "text-2xl", "items-center", "pt-4") }>
+                        <span>{ "son auto :" }</span>
+                        <span>
+                            <input
+                                id="autoplay-front"
+                                type="checkbox"
+                                checked={ *autoplay_front }
+                                onchange={ on_autoplay_change(autoplay_front.clone()) }
+                            />
+                            <label for="autoplay-front">{ "de face" }</label>
+                        </span>
+                        <span>
+                            <input
+                                id="autoplay-back"
+                                type="checkbox"
+                                checked={ *autoplay_back }
+                                onchange={ on_autoplay_change(autoplay_back.clone()) }
+                            />
+                            <label for="autoplay-back">{ "arrière" }</label>
+                        </span>
+                    </div>
+                    { render_speech_side(CardSide::Front, "de face", front_lang.clone(), front_voice.clone()) }
+                    { render_speech_side(CardSide::Back, "arrière", back_lang.clone(), back_voice.clone()) }
+                    <div class={ classes!("flex", "flex-row", "pt-4", "text-2xl", "items-center") }>
+                        <span class={ classes!("mr-4") }>{ "débit" }</span>
+                        <input
+                            oninput={ on_speech_rate_input }
+                            type="range"
+                            min={ MIN_SPEECH_RATE.to_string() }
+                            max={ MAX_SPEECH_RATE.to_string() }
+                            step="0.1"
+                            value={ speech_rate.to_string() }
+                            class={ classes!("w-full", "mr-4") }
+                        />
+                        <span class={ classes!("text-center") }>
+                            { format!("{:.1}", *speech_rate) }
+                        </span>
+                    </div>
                     <button
                         type={ "submit" }
                         class={ classes!("text-right") }
@@ -147,3 +491,16 @@ pub fn deck_form_modal(DeckFormModalProps { deck, update_deck }: &DeckFormModalP
         </Modal>
     }
 }
+
+// Suggestions for the language fields, any BCP 47 tag will do.
+const COMMON_LANGS: [&str; 10] = [
+    "fr-FR", "en-US", "en-GB", "es-ES", "de-DE", "it-IT", "pt-BR", "ja-JP", "zh-CN", "ru-RU",
+];
+
+fn non_empty(value: &str) -> Option<&str> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
@@ -0,0 +1,171 @@
+use common::models::{Deck, MergePayload, SplitPayload};
+use common::search::SEARCH_HELP;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use super::Modal;
+use crate::api;
+use crate::AppContext;
+
+#[derive(PartialEq, Properties)]
+pub struct DeckReorganizeModalProps {
+    pub deck: Deck,
+}
+
+// Merge another deck into this one, or split some of its cards out into a new deck.
+#[function_component(DeckReorganizeModal)]
+pub fn deck_reorganize_modal(DeckReorganizeModalProps { deck }: &DeckReorganizeModalProps) -> Html {
+    let ctx = use_context::<AppContext>().unwrap();
+    let history = use_history().unwrap();
+    let deck_id = deck.id;
+
+    let decks = use_state_eq(Vec::<Deck>::new);
+    let source_deck_id = use_state_eq(|| None);
+    {
+        let decks = decks.clone();
+        let source_deck_id = source_deck_id.clone();
+        use_effect_with_deps(
+            move |_| {
+                api::get_other_decks(
+                    deck_id,
+                    Box::new(move |fetched_decks| {
+                        source_deck_id.set(fetched_decks.first().map(|deck| deck.id));
+                        decks.set(fetched_decks);
+                    }),
+                );
+                || ()
+            },
+            (),
+        );
+    }
+
+    let on_source_change = {
+        let source_deck_id = source_deck_id.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            source_deck_id.set(select.value().parse::<i32>().ok());
+        })
+    };
+
+    let on_merge_submit = {
+        let ctx = ctx.clone();
+        let history = history.clone();
+        let source_deck_id = source_deck_id.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            let source_deck_id = match *source_deck_id {
+                Some(source_deck_id) => source_deck_id,
+                None => return,
+            };
+            let ctx = ctx.clone();
+            let history = history.clone();
+            let url = format!("/api/decks/{}/merge/", deck_id);
+            let payload = serde_json::to_value(MergePayload { source_deck_id }).unwrap();
+            wasm_bindgen_futures::spawn_local(async move {
+                if api::post_vanilla(&url, payload).await.is_ok() {
+                    ctx.set_modal.emit(None);
+                    // TODO doesn't actually trigger refetch.
+                    history.go(0);
+                } // TODO else ...
+            });
+        })
+    };
+
+    let name = use_state_eq(String::new);
+    let on_name_input = {
+        let name = name.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            name.set(input.value());
+        })
+    };
+
+    let search_term = use_state_eq(String::new);
+    let on_search_term_input = {
+        let search_term = search_term.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            search_term.set(input.value());
+        })
+    };
+
+    let on_split_submit = {
+        let name = name.clone();
+        let search_term = search_term.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            if name.is_empty() || search_term.is_empty() {
+                return;
+            }
+            let ctx = ctx.clone();
+            let history = history.clone();
+            let url = format!("/api/decks/{}/split/", deck_id);
+            let payload = serde_json::to_value(SplitPayload {
+                name: (*name).clone(),
+                search_term: (*search_term).clone(),
+            })
+            .unwrap();
+            wasm_bindgen_futures::spawn_local(async move {
+                if api::post_vanilla(&url, payload).await.is_ok() {
+                    ctx.set_modal.emit(None);
+                    // TODO doesn't actually trigger refetch.
+                    history.go(0);
+                } // TODO else ...
+            });
+        })
+    };
+
+    html! {
+        <Modal title={ Some("Réorganiser le paquet") }>
+            <div class={ classes!("flex", "flex-col", "text-2xl") }>
+                {
+                    if decks.is_empty() {
+                        html! {}
+                    } else {
+                        html! {
+                            <form
+                                onsubmit={ on_merge_submit }
+                                class={ classes!("flex", "justify-between", "items-center", "pb-8") }
+                            >
+                                <select onchange={ on_source_change } class={ classes!("bg-blk") }>
+                                    {
+                                        decks.iter().map(|deck| html! {
+                                            <option
+                                                value={ deck.id.to_string() }
+                                                selected={ *source_deck_id == Some(deck.id) }
+                                            >
+                                                { &deck.name }
+                                            </option>
+                                        }).collect::<Html>()
+                                    }
+                                </select>
+                                <button type={ "submit" }>{ "Fusionner ici" }</button>
+                            </form>
+                        }
+                    }
+                }
+                <form onsubmit={ on_split_submit } class={ classes!("flex", "flex-col") }>
+                    <input
+                        type="text"
+                        class={ classes!("mb-4") }
+                        placeholder={ "Nom du nouveau paquet" }
+                        value={ (*name).clone() }
+                        oninput={ on_name_input }
+                    />
+                    <input
+                        type="text"
+                        class={ classes!("mb-4") }
+                        placeholder={ "Cartes à déplacer (recherche)" }
+                        title={ SEARCH_HELP }
+                        value={ (*search_term).clone() }
+                        oninput={ on_search_term_input }
+                    />
+                    <button type={ "submit" } class={ classes!("text-right") }>
+                        { "Séparer" }
+                    </button>
+                </form>
+            </div>
+        </Modal>
+    }
+}
@@ -1,10 +1,14 @@
 use yew::prelude::*;
 
 pub mod card_form;
+pub mod cram;
 pub mod deck_form;
+pub mod deck_reorganize;
 
 pub(crate) use card_form::CardFormModal;
+pub(crate) use cram::CramModal;
 pub(crate) use deck_form::DeckFormModal;
+pub(crate) use deck_reorganize::DeckReorganizeModal;
 
 #[derive(PartialEq, Properties)]
 pub struct ModalProps {
@@ -0,0 +1,190 @@
+use common::models::DeckCardMatch;
+use common::query_params::CardReadQuery;
+use common::search::split_highlights;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::api;
+use crate::components::markup::render_markup;
+use crate::components::modals::CardFormModal;
+use crate::AppContext;
+
+pub fn highlighted(text: &str, highlight: &Option<String>) -> Html {
+    // Card text with the words matched by a search marked up, see `search::split_highlights`.
+    // Highlights come from the raw text, so markup is only rendered outside of a search.
+    match highlight {
+        Some(highlight) => split_highlights(highlight)
+            .into_iter()
+            .map(|(segment, is_match)| {
+                if is_match {
+                    html! { <mark class={ classes!("bg-yellow-500", "text-black") }>{ segment }</mark> }
+                } else {
+                    html! { { segment } }
+                }
+            })
+            .collect::<Html>(),
+        None => render_markup(text),
+    }
+}
+
+// Search bar for the nav, looks through the cards of every deck.
+#[function_component(GlobalSearch)]
+pub fn global_search() -> Html {
+    let ctx = use_context::<AppContext>().unwrap();
+    let query_params = use_state_eq(CardReadQuery::default);
+    let results = use_state_eq(Vec::<DeckCardMatch>::new);
+    let has_more = use_state_eq(|| false);
+    {
+        let results = results.clone();
+        let has_more = has_more.clone();
+        let this_page = query_params.page;
+        let is_empty = query_params.search_term.is_empty();
+        let url = format!(
+            "/api/cards/search/?{}",
+            serde_qs::to_string(&*query_params).unwrap(),
+        );
+        use_effect_with_deps(
+            move |_| {
+                if is_empty {
+                    results.set(Vec::new());
+                    has_more.set(false);
+                } else {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        if let Ok::<api::Page<DeckCardMatch>, _>(page) = api::get(&url).await {
+                            if this_page > 0 {
+                                let mut result_vec = (*results).clone();
+                                result_vec.extend(page.results);
+                                results.set(result_vec);
+                            } else {
+                                results.set(page.results);
+                            }
+                            has_more.set(page.has_more);
+                        }
+                    });
+                }
+                || ()
+            },
+            query_params.clone(),
+        );
+    }
+
+    let on_search_term_input = {
+        let query_params = query_params.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            query_params.set(CardReadQuery {
+                search_term: input.value(),
+                ..CardReadQuery::default()
+            });
+        })
+    };
+
+    let on_more_click = {
+        let query_params = query_params.clone();
+        Callback::from(move |_| {
+            let mut new_query_params = (*query_params).clone();
+            new_query_params.page += 1;
+            query_params.set(new_query_params);
+        })
+    };
+
+    // Results come ranked, keep decks in the order of their best hit.
+    let mut groups: Vec<(String, Vec<DeckCardMatch>)> = Vec::new();
+    for result in (*results).iter() {
+        match groups
+            .iter_mut()
+            .find(|(name, _)| *name == result.deck_name)
+        {
+            Some((_, group)) => group.push(result.clone()),
+            None => groups.push((result.deck_name.clone(), vec![result.clone()])),
+        }
+    }
+
+    let render_result = |result: &DeckCardMatch| {
+        let onclick = {
+            let ctx = ctx.clone();
+            let query_params = query_params.clone();
+            let deck_id = result.card_match.card.deck_id;
+            let card_id = result.card_match.card.id;
+            Callback::from(move |_| {
+                query_params.set(CardReadQuery::default());
+                ctx.set_modal.emit(Some(html! {
+                    <CardFormModal { deck_id } { card_id } />
+                }));
+            })
+        };
+        let card_match = &result.card_match;
+        html! {
+            <div
+                { onclick }
+                key={ card_match.card.id }
+                class={ classes!("py-1", "px-4", "cursor-pointer", "hover:bg-gray-800") }
+            >
+                { highlighted(&card_match.card.front, &card_match.front_highlight) }
+                { " / " }
+                { highlighted(&card_match.card.back, &card_match.back_highlight) }
+            </div>
+        }
+    };
+
+    html! {
+        <span class={ classes!("absolute", "left-5", "w-1/4", "text-2xl", "portrait:text-4xl") }>
+            <input
+                type="text"
+                class={ classes!("w-full") }
+                placeholder={ "Chercher partout" }
+                value={ query_params.search_term.clone() }
+                oninput={ on_search_term_input }
+            />
+            {
+                if query_params.search_term.is_empty() {
+                    html! {}
+                } else {
+                    html! {
+                        <div
+                            class={
+                                classes!(
+                                    "absolute", "w-full", "max-h-[60vh]", "overflow-y-auto", "z-20",
+                                    "bg-blk", "rounded-lg", "border-2", "border-gray-600",
+                                )
+                            }
+                        >
+                            {
+                                if groups.is_empty() {
+                                    html! {
+                                        <div class={ classes!("p-4") }>{ "Aucun résultat" }</div>
+                                    }
+                                } else {
+                                    groups.iter().map(|(deck_name, group)| {
+                                        html! {
+                                            <div class={ classes!("py-2") }>
+                                                <div class={ classes!("px-4", "text-gray-400") }>
+                                                    { deck_name }
+                                                </div>
+                                                { for group.iter().map(render_result) }
+                                            </div>
+                                        }
+                                    }).collect::<Html>()
+                                }
+                            }
+                            {
+                                if *has_more {
+                                    html! {
+                                        <button
+                                            onclick={ on_more_click }
+                                            class={ classes!("w-full", "p-2") }
+                                        >
+                                            { "Plus de résultats" }
+                                        </button>
+                                    }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                        </div>
+                    }
+                }
+            }
+        </span>
+    }
+}
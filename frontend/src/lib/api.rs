@@ -1,24 +1,34 @@
 use core::fmt;
 
 use common::models::Deck;
+use common::{CSRF_COOKIE, CSRF_HEADER};
 use reqwasm::{http::Request, http::Response, Error};
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde_json::Value;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlDocument;
 
 #[derive(Debug)]
-pub struct ApiError(String);
+pub struct ApiError {
+    message: String,
+    // Set when the server did answer, just not with a success.
+    pub status: Option<u16>,
+}
 
 impl ApiError {
     pub fn new(msg: String) -> Self {
         log::error!("{}", msg);
-        ApiError(msg)
+        ApiError {
+            message: msg,
+            status: None,
+        }
     }
 }
 
 impl fmt::Display for ApiError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.0)
+        f.write_str(&self.message)
     }
 }
 
@@ -33,7 +43,10 @@ async fn handle_request(request: Request) -> Result<Response, ApiError> {
         Ok(response) => {
             if !response.ok() {
                 // TODO not too sure about this approach.
-                return Err(ApiError(response.text().await.unwrap()));
+                return Err(ApiError {
+                    status: Some(response.status()),
+                    message: response.text().await.unwrap(),
+                });
             }
             Ok(response)
         }
@@ -41,6 +54,24 @@ async fn handle_request(request: Request) -> Result<Response, ApiError> {
     }
 }
 
+fn csrf_token() -> Option<String> {
+    // Set by the backend on the first page load, see `CsrfMiddleware`.
+    let document: HtmlDocument = web_sys::window()?.document()?.dyn_into().ok()?;
+    document.cookie().ok()?.split("; ").find_map(|pair| {
+        pair.strip_prefix(CSRF_COOKIE)?
+            .strip_prefix('=')
+            .map(str::to_string)
+    })
+}
+
+fn with_csrf_token(request: Request) -> Request {
+    // Anything but GETs gets refused without it.
+    match csrf_token() {
+        Some(token) => request.header(CSRF_HEADER, &token),
+        None => request,
+    }
+}
+
 async fn deserialize<T: DeserializeOwned>(response: Response) -> Result<T, ApiError> {
     response.json().await.map_err(ApiError::from)
 }
@@ -57,7 +88,7 @@ pub async fn post_vanilla(url: &str, payload: Value) -> Result<Response, ApiErro
         .header("Content-Type", "application/json")
         .body(payload);
 
-    handle_request(request).await
+    handle_request(with_csrf_token(request)).await
 }
 
 pub async fn post<T: DeserializeOwned>(url: &str, payload: Value) -> Result<T, ApiError> {
@@ -66,7 +97,7 @@ pub async fn post<T: DeserializeOwned>(url: &str, payload: Value) -> Result<T, A
 }
 
 pub async fn delete(url: &str) -> Result<Response, ApiError> {
-    handle_request(Request::delete(url)).await
+    handle_request(with_csrf_token(Request::delete(url))).await
 }
 
 // TODO should move to a `common` crate between here and `backend`.
@@ -85,3 +116,29 @@ pub fn get_deck(deck_id: i32, callback: Box<dyn Fn(Deck)>) {
         }
     });
 }
+
+// The user's decks besides `deck_id`, e.g. to pick where to send cards.
+pub fn get_other_decks(deck_id: i32, callback: Box<dyn Fn(Vec<Deck>)>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Ok::<Vec<Deck>, _>(fetched_decks) = get("/api/decks/").await {
+            callback(
+                fetched_decks
+                    .into_iter()
+                    .filter(|deck| deck.id != deck_id)
+                    .collect(),
+            );
+        }
+    });
+}
+
+// Sends a file (or any blob) as the raw request body.
+pub async fn post_file<T: DeserializeOwned>(
+    url: &str,
+    file: &web_sys::Blob,
+) -> Result<T, ApiError> {
+    let request = Request::post(url)
+        .header("Content-Type", &file.type_())
+        .body(file.clone());
+    let response = handle_request(with_csrf_token(request)).await?;
+    deserialize(response).await
+}
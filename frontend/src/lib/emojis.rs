@@ -1,4 +1,5 @@
 pub const BELL: &str = "\u{1F6CE}\u{FE0F}";
+pub const BOOKS: &str = "\u{1F4DA}";
 
 pub const WAVE: &str = "\u{1F44B}\u{FE0F}";
 pub const HOME: &str = "\u{1F3E1}\u{FE0F}";
@@ -7,5 +8,14 @@ pub const HEAD_BANDAGE: &str = "\u{1F915}\u{FE0F}";
 pub const GEAR: &str = "\u{2699}\u{FE0F}";
 pub const PENCIL: &str = "\u{270F}\u{FE0F}";
 pub const AXE: &str = "\u{1FA93}\u{FE0F}";
+pub const SCISSORS: &str = "\u{2702}\u{FE0F}";
 
 pub const RETURN: &str = "\u{21A9}\u{FE0F}";
+
+pub const CHART: &str = "\u{1F4CA}";
+pub const KEY: &str = "\u{1F511}";
+
+pub const SPEAKER: &str = "\u{1F50A}";
+pub const MICROPHONE: &str = "\u{1F399}\u{FE0F}";
+pub const STOP: &str = "\u{23F9}\u{FE0F}";
+pub const SPEAKING_HEAD: &str = "\u{1F5E3}\u{FE0F}";
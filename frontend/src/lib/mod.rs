@@ -1,2 +1,4 @@
 pub(crate) mod api;
 pub(crate) mod emojis;
+pub(crate) mod speech;
+pub(crate) mod time;
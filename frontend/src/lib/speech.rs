@@ -0,0 +1,103 @@
+use common::models::Deck;
+use common::CardSide;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{SpeechSynthesis, SpeechSynthesisUtterance, SpeechSynthesisVoice};
+
+fn synthesis() -> Option<SpeechSynthesis> {
+    // Not every browser has it.
+    web_sys::window()?.speech_synthesis().ok()
+}
+
+pub fn voices() -> Vec<SpeechSynthesisVoice> {
+    // Voices installed in the browser, may come up empty until the `voiceschanged` event.
+    match synthesis() {
+        Some(synthesis) => synthesis
+            .get_voices()
+            .iter()
+            .map(|voice| voice.unchecked_into::<SpeechSynthesisVoice>())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+pub fn voices_for(lang: &str) -> Vec<SpeechSynthesisVoice> {
+    // Voices speaking `lang`, a bare `fr` takes any `fr-*`.
+    let lang = lang.to_lowercase();
+    voices()
+        .into_iter()
+        .filter(|voice| {
+            let voice_lang = voice.lang().to_lowercase().replace('_', "-");
+            voice_lang == lang || voice_lang.starts_with(&format!("{}-", lang))
+        })
+        .collect()
+}
+
+// How to read out one side of a deck's cards.
+#[derive(Clone, PartialEq)]
+pub struct Voice {
+    pub lang: Option<String>,
+    pub name: Option<String>,
+    pub rate: f32,
+}
+
+impl Voice {
+    pub fn for_side(deck: &Deck, side: CardSide) -> Self {
+        Voice {
+            lang: deck.lang(side).map(str::to_string),
+            name: deck.voice(side).map(str::to_string),
+            rate: deck.speech_rate,
+        }
+    }
+
+    pub fn speak(&self, text: &str) {
+        speak(text, self.lang.as_deref(), self.name.as_deref(), self.rate);
+    }
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Voice {
+            lang: None,
+            name: None,
+            rate: 1.0,
+        }
+    }
+}
+
+pub fn speak(text: &str, lang: Option<&str>, voice: Option<&str>, rate: f32) {
+    let synthesis = match synthesis() {
+        Some(synthesis) => synthesis,
+        None => return,
+    };
+    let utterance = match SpeechSynthesisUtterance::new_with_text(text) {
+        Ok(utterance) => utterance,
+        Err(_) => return,
+    };
+    if let Some(lang) = lang {
+        utterance.set_lang(lang);
+    }
+    if let Some(voice) = voice {
+        let voice = voices().into_iter().find(|v| v.name() == voice);
+        utterance.set_voice(voice.as_ref());
+    }
+    utterance.set_rate(rate);
+    // Don't queue up behind whatever was being said for the previous card.
+    synthesis.cancel();
+    synthesis.speak(&utterance);
+}
+
+pub fn watch_voices(callback: Box<dyn Fn()>) -> Option<Closure<dyn Fn()>> {
+    // Call `callback` whenever the list of voices changes, until `unwatch_voices`. The returned
+    // closure has to be kept alive in the meantime.
+    let synthesis = synthesis()?;
+    let closure = Closure::wrap(callback);
+    synthesis.set_onvoiceschanged(Some(closure.as_ref().unchecked_ref()));
+    Some(closure)
+}
+
+pub fn unwatch_voices() {
+    if let Some(synthesis) = synthesis() {
+        synthesis.set_onvoiceschanged(None);
+    }
+}
@@ -0,0 +1,18 @@
+use chrono::NaiveDateTime;
+use js_sys::{Array, Date, Intl, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+pub fn browser_timezone() -> String {
+    // IANA name of the browser's timezone, which the backend uses to decide where days end.
+    let options = Intl::DateTimeFormat::new(&Array::new(), &Object::new()).resolved_options();
+    Reflect::get(&options, &JsValue::from_str("timeZone"))
+        .ok()
+        .and_then(|tz| tz.as_string())
+        .unwrap_or_else(|| "UTC".to_string())
+}
+
+pub fn local_datetime(utc: NaiveDateTime) -> String {
+    // A UTC timestamp from the backend, as the browser's local date and time.
+    let date = Date::new(&JsValue::from_f64(utc.and_utc().timestamp_millis() as f64));
+    String::from(date.to_locale_string("fr-FR", &JsValue::UNDEFINED))
+}
@@ -2,6 +2,7 @@ use web_sys::Element;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+use crate::components::search::GlobalSearch;
 use crate::lib::*;
 use crate::routes::*;
 
@@ -72,13 +73,24 @@ pub fn layout(LayoutProps { children }: &LayoutProps) -> Html {
                             )
                         }
                     >
+                        <GlobalSearch />
                         <span>{ (*title).clone() }</span>
                         <span class={ classes!("absolute", "right-5") }>
+                            <span class={ classes!("px-2") }>
+                                <Link<AppRoute> to={ AppRoute::Stats }>
+                                    { emojis::CHART }
+                                </Link<AppRoute>>
+                            </span>
                             <span class={ classes!("px-2") }>
                                 <Link<AppRoute> to={ AppRoute::Decks }>
                                     { emojis::HOME }
                                 </Link<AppRoute>>
                             </span>
+                            <span class={ classes!("px-2") }>
+                                <Link<AppRoute> to={ AppRoute::Account }>
+                                    { emojis::KEY }
+                                </Link<AppRoute>>
+                            </span>
                             <span class={ classes!("px-2") }>
                                 <a href={ "/logout/" }>
                                     { emojis::WAVE }
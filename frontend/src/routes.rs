@@ -11,6 +11,9 @@ pub enum MainRoute {
     RedirectToHome,
     #[at("/login/")]
     Login,
+    // Where password reset emails link to.
+    #[at("/reset-password/")]
+    ResetPassword,
     #[at("/app/*")]
     AppRoot,
     #[not_found]
@@ -22,11 +25,20 @@ pub enum MainRoute {
 pub enum AppRoute {
     #[at("/app/decks/")]
     Decks,
+    #[at("/app/stats/")]
+    Stats,
+    #[at("/app/account/")]
+    Account,
+    // What's due today across decks.
+    #[at("/app/revision/")]
+    CombinedRevision,
     // TODO would be nice to have a title slug instead of int id.
     #[at("/app/decks/:deck_id/")]
     DeckDetail { deck_id: i32 },
     #[at("/app/decks/:deck_id/revision/")]
     Revision { deck_id: i32 },
+    #[at("/app/decks/:deck_id/stats/")]
+    DeckStats { deck_id: i32 },
     #[at("/app/decks/:deck_id/cards/")]
     CardCreateForm { deck_id: i32 },
     #[at("/app/decks/:deck_id/cards/:card_id/")]
@@ -37,6 +49,7 @@ pub fn main_switch(routes: &MainRoute) -> Html {
     match routes {
         MainRoute::RedirectToHome => html! { <Redirect<AppRoute> to={AppRoute::Decks}/> },
         MainRoute::Login => html! { <views::login::Login /> },
+        MainRoute::ResetPassword => html! { <views::reset_password::ResetPassword /> },
         MainRoute::NotFound => html! {
             <>
                 <h1>{ format!("Page not found {}", emojis::HEAD_BANDAGE) }</h1>
@@ -64,7 +77,17 @@ fn app_switch(routes: &AppRoute) -> Html {
             <views::cards::form::CardForm deck_id={ *deck_id } card_id={ *card_id }/>
         },
         AppRoute::Revision { deck_id } => html! {
-            <views::revision::Revision deck_id={ *deck_id }/>
+            <views::revision::Revision deck_id={ Some(*deck_id) }/>
         },
+        AppRoute::CombinedRevision => html! {
+            <views::revision::Revision deck_id={ None }/>
+        },
+        AppRoute::Stats => html! {
+            <views::stats::StatsDashboard deck_id={ None }/>
+        },
+        AppRoute::DeckStats { deck_id } => html! {
+            <views::stats::StatsDashboard deck_id={ *deck_id }/>
+        },
+        AppRoute::Account => html! { <views::account::Account /> },
     }
 }
@@ -0,0 +1,571 @@
+use common::models::{
+    ApiTokenInfo, AuthInfo, CreatedApiToken, Deck, RecoveryCodes, SessionInfo, TotpEnrollment,
+    TotpStatus,
+};
+use common::TokenScope;
+use serde_json::json;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+use crate::api;
+use crate::emojis;
+use crate::time::local_datetime;
+use crate::AppContext;
+
+#[function_component(Account)]
+pub fn account() -> Html {
+    let ctx = use_context::<AppContext>().unwrap();
+    ctx.set_title.emit("Compte".to_string());
+
+    html! {
+        <div class={ classes!("max-w-2xl", "h-[90vh]", "overflow-y-auto", "text-4xl", "lg:text-xl") }>
+            <PasswordSettings />
+            <SessionList />
+            <TotpSettings />
+            <ApiTokenList />
+        </div>
+    }
+}
+
+#[function_component(PasswordSettings)]
+fn password_settings() -> Html {
+    let email_node_ref = use_node_ref();
+    let old_password_node_ref = use_node_ref();
+    let new_password_node_ref = use_node_ref();
+    let message = use_state_eq(String::new);
+    {
+        let email_node_ref = email_node_ref.clone();
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<AuthInfo, _>(me) = api::get("/api/me/").await {
+                        if let Some(input) = email_node_ref.cast::<HtmlInputElement>() {
+                            input.set_value(me.email.as_deref().unwrap_or_default());
+                        }
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    let on_email_save = {
+        let email_node_ref = email_node_ref.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let email = match email_node_ref.cast::<HtmlInputElement>() {
+                Some(input) => input.value(),
+                None => return,
+            };
+            let payload = json!({ "email": Some(email).filter(|email| !email.trim().is_empty()) });
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::post_vanilla("/api/email/", payload).await {
+                    Ok(_) => message.set("Adresse e-mail enregistrée".to_string()),
+                    Err(e) => message.set(e.to_string()),
+                }
+            });
+        })
+    };
+
+    let on_password_change = {
+        let old_password_node_ref = old_password_node_ref.clone();
+        let new_password_node_ref = new_password_node_ref.clone();
+        let message = message.clone();
+        Callback::from(move |_| {
+            let (old_password, new_password) = match (
+                old_password_node_ref.cast::<HtmlInputElement>(),
+                new_password_node_ref.cast::<HtmlInputElement>(),
+            ) {
+                (Some(old), Some(new)) if !new.value().is_empty() => (old, new),
+                _ => return,
+            };
+            let payload = json!({
+                "old_password": old_password.value(),
+                "new_password": new_password.value(),
+            });
+            let message = message.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::post_vanilla("/api/password/", payload).await {
+                    Ok(_) => {
+                        old_password.set_value("");
+                        new_password.set_value("");
+                        message.set(
+                            "Mot de passe changé, les autres sessions sont déconnectées"
+                                .to_string(),
+                        );
+                    }
+                    Err(e) => message.set(e.to_string()),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class={ classes!("py-4") }>
+            <div class={ classes!("text-5xl", "lg:text-2xl", "pb-2") }>{ "Identifiants" }</div>
+            <div hidden={ message.is_empty() } class={ classes!("py-2") }>{ (*message).clone() }</div>
+            <div class={ classes!("flex", "justify-between", "items-center", "py-2") }>
+                <input
+                    type="email"
+                    placeholder="Adresse e-mail, pour réinitialiser le mot de passe"
+                    ref={ email_node_ref }
+                    class={ classes!("w-2/3", "bg-blk") }
+                />
+                <button onclick={ on_email_save }>{ "Enregistrer" }</button>
+            </div>
+            <div class={ classes!("flex", "justify-between", "items-center", "py-2") }>
+                <input
+                    type="password"
+                    placeholder="Mot de passe actuel"
+                    ref={ old_password_node_ref }
+                    class={ classes!("w-1/3", "bg-blk") }
+                />
+                <input
+                    type="password"
+                    placeholder="Nouveau mot de passe"
+                    ref={ new_password_node_ref }
+                    class={ classes!("w-1/3", "bg-blk") }
+                />
+                <button onclick={ on_password_change }>{ "Changer" }</button>
+            </div>
+        </div>
+    }
+}
+
+#[function_component(SessionList)]
+fn session_list() -> Html {
+    let sessions = use_state_eq(Vec::<SessionInfo>::new);
+    let refresh = {
+        let sessions = sessions.clone();
+        Callback::from(move |_: ()| {
+            let sessions = sessions.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok::<Vec<SessionInfo>, _>(fetched) = api::get("/api/sessions/").await {
+                    sessions.set(fetched);
+                }
+            });
+        })
+    };
+    {
+        let refresh = refresh.clone();
+        use_effect_with_deps(
+            move |_| {
+                refresh.emit(());
+                || ()
+            },
+            (),
+        );
+    }
+
+    let on_revoke = |session_id: i32| {
+        let refresh = refresh.clone();
+        Callback::from(move |_| {
+            let refresh = refresh.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let url = format!("/api/sessions/{}/", session_id);
+                if api::delete(&url).await.is_ok() {
+                    refresh.emit(());
+                }
+            });
+        })
+    };
+    let on_revoke_others = {
+        let refresh = refresh.clone();
+        Callback::from(move |_| {
+            let refresh = refresh.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if api::delete("/api/sessions/").await.is_ok() {
+                    refresh.emit(());
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class={ classes!("py-4") }>
+            <div class={ classes!("text-5xl", "lg:text-2xl", "pb-2") }>{ "Sessions" }</div>
+            {
+                sessions.iter().map(|session| html! {
+                    <div key={ session.id } class={ classes!("flex", "justify-between", "items-center", "py-2") }>
+                        <span title={ session.user_agent.clone() }>
+                            <div>
+                                { &session.device }
+                                {
+                                    if session.current {
+                                        html! { <span class={ classes!("text-gray-400") }>{ " (cette session)" }</span> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </div>
+                            <div class={ classes!("text-gray-400") }>
+                                { format!("Vue le {}", local_datetime(session.last_seen)) }
+                            </div>
+                        </span>
+                        {
+                            if session.current {
+                                html! {}
+                            } else {
+                                html! {
+                                    <button onclick={ on_revoke(session.id) } class={ classes!("px-2") }>
+                                        { emojis::AXE }
+                                    </button>
+                                }
+                            }
+                        }
+                    </div>
+                }).collect::<Html>()
+            }
+            {
+                if sessions.len() > 1 {
+                    html! {
+                        <button onclick={ on_revoke_others } class={ classes!("pt-4") }>
+                            { "Déconnecter les autres sessions" }
+                        </button>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component(TotpSettings)]
+fn totp_settings() -> Html {
+    let status = use_state_eq(|| None::<TotpStatus>);
+    // Between asking for a secret and confirming it with a first code.
+    let enrollment = use_state_eq(|| None::<TotpEnrollment>);
+    // Shown once after confirming, like new API tokens.
+    let recovery_codes = use_state_eq(Vec::<String>::new);
+    let error = use_state_eq(String::new);
+    let code_node_ref = use_node_ref();
+
+    let refresh = {
+        let status = status.clone();
+        Callback::from(move |_: ()| {
+            let status = status.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok::<TotpStatus, _>(fetched) = api::get("/api/totp/").await {
+                    status.set(Some(fetched));
+                }
+            });
+        })
+    };
+    {
+        let refresh = refresh.clone();
+        use_effect_with_deps(
+            move |_| {
+                refresh.emit(());
+                || ()
+            },
+            (),
+        );
+    }
+
+    let on_enroll = {
+        let enrollment = enrollment.clone();
+        let recovery_codes = recovery_codes.clone();
+        Callback::from(move |_| {
+            let enrollment = enrollment.clone();
+            let recovery_codes = recovery_codes.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok::<TotpEnrollment, _>(started) =
+                    api::post("/api/totp/enroll/", json!({})).await
+                {
+                    recovery_codes.set(Vec::new());
+                    enrollment.set(Some(started));
+                }
+            });
+        })
+    };
+
+    // Confirming a new secret and turning 2FA off both take a code from the app.
+    let on_code = {
+        let enrollment = enrollment.clone();
+        let recovery_codes = recovery_codes.clone();
+        let error = error.clone();
+        let code_node_ref = code_node_ref.clone();
+        let refresh = refresh.clone();
+        Callback::from(move |_| {
+            let input = match code_node_ref.cast::<HtmlInputElement>() {
+                Some(input) if !input.value().trim().is_empty() => input,
+                _ => return,
+            };
+            let payload = json!({ "code": input.value() });
+            let enrollment = enrollment.clone();
+            let recovery_codes = recovery_codes.clone();
+            let error = error.clone();
+            let refresh = refresh.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = if enrollment.is_some() {
+                    api::post::<RecoveryCodes>("/api/totp/confirm/", payload)
+                        .await
+                        .map(|created| recovery_codes.set(created.codes))
+                } else {
+                    api::post_vanilla("/api/totp/disable/", payload)
+                        .await
+                        .map(|_| ())
+                };
+                match result {
+                    Ok(()) => {
+                        input.set_value("");
+                        error.set(String::new());
+                        enrollment.set(None);
+                        refresh.emit(());
+                    }
+                    Err(e) => error.set(e.to_string()),
+                }
+            });
+        })
+    };
+
+    let status = match &*status {
+        Some(status) => status,
+        None => return html! {},
+    };
+    let code_input = |label: &'static str| {
+        html! {
+            <div class={ classes!("flex", "justify-between", "items-center", "pt-4") }>
+                <input
+                    type="text"
+                    inputmode="numeric"
+                    autocomplete="one-time-code"
+                    placeholder="Code"
+                    ref={ code_node_ref.clone() }
+                    class={ classes!("w-1/3", "bg-blk") }
+                />
+                <button onclick={ on_code.clone() }>{ label }</button>
+            </div>
+        }
+    };
+
+    html! {
+        <div class={ classes!("py-4") }>
+            <div class={ classes!("text-5xl", "lg:text-2xl", "pb-2") }>
+                { "Double authentification" }
+            </div>
+            <div hidden={ error.is_empty() } class={ classes!("py-2") }>{ (*error).clone() }</div>
+            {
+                if !recovery_codes.is_empty() {
+                    html! {
+                        <div class={ classes!("py-2") }>
+                            <div class={ classes!("text-gray-400") }>
+                                { "Codes de secours, à garder de côté, ils ne seront plus affichés :" }
+                            </div>
+                            {
+                                recovery_codes.iter().map(|code| html! {
+                                    <div><code class={ classes!("select-all") }>{ code }</code></div>
+                                }).collect::<Html>()
+                            }
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if status.enabled {
+                    html! {
+                        <>
+                            <div class={ classes!("text-gray-400") }>
+                                { format!("Activée, {} codes de secours restants", status.recovery_codes_left) }
+                            </div>
+                            { code_input("Désactiver") }
+                        </>
+                    }
+                } else if let Some(enrollment) = &*enrollment {
+                    let qr_src = format!(
+                        "data:image/svg+xml;utf8,{}",
+                        String::from(js_sys::encode_uri_component(&enrollment.qr_svg)),
+                    );
+                    html! {
+                        <>
+                            <div class={ classes!("text-gray-400") }>
+                                { "À scanner avec une application d'authentification, puis saisir le code affiché :" }
+                            </div>
+                            <img src={ qr_src } alt={ enrollment.uri.clone() } class={ classes!("py-2", "bg-white") } />
+                            <code class={ classes!("select-all", "break-all") }>{ &enrollment.secret }</code>
+                            { code_input("Activer") }
+                        </>
+                    }
+                } else {
+                    html! {
+                        <button onclick={ on_enroll }>{ "Activer" }</button>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component(ApiTokenList)]
+fn api_token_list() -> Html {
+    let tokens = use_state_eq(Vec::<ApiTokenInfo>::new);
+    let decks = use_state_eq(Vec::<Deck>::new);
+    // Shown once right after creation, there's no getting it back afterwards.
+    let created_token = use_state_eq(|| None::<String>);
+    {
+        let tokens = tokens.clone();
+        let decks = decks.clone();
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<Vec<ApiTokenInfo>, _>(fetched) = api::get("/api/tokens/").await {
+                        tokens.set(fetched);
+                    }
+                    if let Ok::<Vec<Deck>, _>(fetched) = api::get("/api/decks/").await {
+                        decks.set(fetched);
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    let name_node_ref = use_node_ref();
+    let scope = use_state_eq(|| TokenScope::Read);
+    let on_scope_change = {
+        let scope = scope.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            match &*select.value() {
+                "read" => scope.set(TokenScope::Read),
+                "read_write" => scope.set(TokenScope::ReadWrite),
+                _ => (),
+            }
+        })
+    };
+    let deck_id = use_state_eq(|| None::<i32>);
+    let on_deck_change = {
+        let deck_id = deck_id.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            deck_id.set(select.value().parse::<i32>().ok());
+        })
+    };
+
+    let on_create = {
+        let tokens = tokens.clone();
+        let created_token = created_token.clone();
+        let name_node_ref = name_node_ref.clone();
+        let scope = scope.clone();
+        let deck_id = deck_id.clone();
+        Callback::from(move |_| {
+            let input = match name_node_ref.cast::<HtmlInputElement>() {
+                Some(input) if !input.value().trim().is_empty() => input,
+                _ => return,
+            };
+            let payload = json!({
+                "name": input.value(),
+                "scope": *scope,
+                "deck_id": *deck_id,
+            });
+            let tokens = tokens.clone();
+            let created_token = created_token.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok::<CreatedApiToken, _>(created) = api::post("/api/tokens/", payload).await
+                {
+                    input.set_value("");
+                    let mut updated = vec![created.info];
+                    updated.extend((*tokens).clone());
+                    tokens.set(updated);
+                    created_token.set(Some(created.token));
+                }
+            });
+        })
+    };
+
+    let on_revoke = |token_id: i32| {
+        let tokens = tokens.clone();
+        Callback::from(move |_| {
+            let tokens = tokens.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let url = format!("/api/tokens/{}/", token_id);
+                if api::delete(&url).await.is_ok() {
+                    tokens.set(
+                        tokens
+                            .iter()
+                            .filter(|token| token.id != token_id)
+                            .cloned()
+                            .collect(),
+                    );
+                }
+            });
+        })
+    };
+
+    let describe = |token: &ApiTokenInfo| {
+        let scope = match token.scope {
+            TokenScope::Read => "lecture",
+            TokenScope::ReadWrite => "lecture et écriture",
+        };
+        let deck = token.deck_name.as_deref().unwrap_or("tous les paquets");
+        let last_used = match token.last_used {
+            Some(last_used) => format!("utilisé le {}", local_datetime(last_used)),
+            None => "jamais utilisé".to_string(),
+        };
+        format!("{}, {}, {}", scope, deck, last_used)
+    };
+
+    html! {
+        <div class={ classes!("py-4") }>
+            <div class={ classes!("text-5xl", "lg:text-2xl", "pb-2") }>{ "Jetons d'API" }</div>
+            {
+                if let Some(token) = &*created_token {
+                    html! {
+                        <div class={ classes!("py-2") }>
+                            <div class={ classes!("text-gray-400") }>
+                                { "À copier maintenant, il ne sera plus affiché :" }
+                            </div>
+                            <code class={ classes!("select-all", "break-all") }>{ token }</code>
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                tokens.iter().map(|token| html! {
+                    <div key={ token.id } class={ classes!("flex", "justify-between", "items-center", "py-2") }>
+                        <span>
+                            <div>{ &token.name }</div>
+                            <div class={ classes!("text-gray-400") }>{ describe(token) }</div>
+                        </span>
+                        <button onclick={ on_revoke(token.id) } class={ classes!("px-2") }>
+                            { emojis::AXE }
+                        </button>
+                    </div>
+                }).collect::<Html>()
+            }
+            <div class={ classes!("flex", "justify-between", "items-center", "pt-4") }>
+                <input
+                    type="text"
+                    placeholder="Nom du jeton"
+                    ref={ name_node_ref }
+                    class={ classes!("w-1/3", "bg-blk") }
+                />
+                <select onchange={ on_scope_change } class={ classes!("bg-blk") }>
+                    <option value="read" selected={ *scope == TokenScope::Read }>{ "lecture" }</option>
+                    <option value="read_write" selected={ *scope == TokenScope::ReadWrite }>
+                        { "lecture et écriture" }
+                    </option>
+                </select>
+                <select onchange={ on_deck_change } class={ classes!("bg-blk") }>
+                    <option value="" selected={ deck_id.is_none() }>{ "tous les paquets" }</option>
+                    {
+                        decks.iter().map(|deck| html! {
+                            <option value={ deck.id.to_string() } selected={ *deck_id == Some(deck.id) }>
+                                { &deck.name }
+                            </option>
+                        }).collect::<Html>()
+                    }
+                </select>
+                <button onclick={ on_create }>{ "Créer" }</button>
+            </div>
+        </div>
+    }
+}
@@ -1,10 +1,14 @@
-use common::models::Card;
+use common::models::{Card, Deck};
+use common::query_params::{DuplicateScope, NewCardQuery};
+use common::CardSide;
 use serde_json::json;
 use web_sys::HtmlTextAreaElement;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use crate::api;
+use crate::components::duplicate::DuplicateNotice;
+use crate::components::markup::MarkupPreview;
 use crate::emojis;
 use crate::AppRoute;
 
@@ -44,10 +48,33 @@ pub fn card_form(CardFormProps { deck_id, card_id }: &CardFormProps) -> Html {
         );
     }
 
+    // For the deck's languages, see `lang` on the text areas.
+    let deck = use_state_eq(|| None::<Deck>);
+    {
+        let deck = deck.clone();
+        let deck_id = *deck_id;
+        use_effect_with_deps(
+            move |_| {
+                api::get_deck(
+                    deck_id,
+                    Box::new(move |fetched_deck| deck.set(Some(fetched_deck))),
+                );
+                || ()
+            },
+            (),
+        );
+    }
+    let lang = |side: CardSide| {
+        (*deck)
+            .as_ref()
+            .and_then(|deck| deck.lang(side))
+            .map(str::to_string)
+    };
+
     // TODO surely there's a DRYer way to approach this.
     let on_front_change = {
         let front = front.clone();
-        Callback::from(move |e: Event| {
+        Callback::from(move |e: InputEvent| {
             let textarea: HtmlTextAreaElement = e.target_unchecked_into();
             front.set(textarea.value());
         })
@@ -55,22 +82,35 @@ pub fn card_form(CardFormProps { deck_id, card_id }: &CardFormProps) -> Html {
 
     let on_back_change = {
         let back = back.clone();
-        Callback::from(move |e: Event| {
+        Callback::from(move |e: InputEvent| {
             let textarea: HtmlTextAreaElement = e.target_unchecked_into();
             back.set(textarea.value());
         })
     };
 
-    let onsubmit = {
+    // Set when the backend turned down a new card as a duplicate of this one.
+    let duplicate = use_state(|| None);
+
+    let save = {
         let api_url = api_url.clone();
         let front = front.clone();
         let back = back.clone();
+        let duplicate = duplicate.clone();
+        let is_new = card_id.is_none();
         let deck_id = *deck_id;
         let history = history.clone();
-        Callback::from(move |e: FocusEvent| {
-            e.prevent_default();
+        Callback::from(move |allow_duplicate: bool| {
             let history = history.clone();
-            let api_url = api_url.clone();
+            let duplicate = duplicate.clone();
+            let api_url = if is_new {
+                let query = NewCardQuery {
+                    duplicate_scope: DuplicateScope::All,
+                    allow_duplicate,
+                };
+                format!("{}?{}", api_url, serde_qs::to_string(&query).unwrap())
+            } else {
+                api_url.clone()
+            };
             if front.is_empty() || back.is_empty() {
                 return;
             }
@@ -79,14 +119,37 @@ pub fn card_form(CardFormProps { deck_id, card_id }: &CardFormProps) -> Html {
                 "back": *back,
             });
             wasm_bindgen_futures::spawn_local(async move {
-                let api_url = api_url.clone();
-                if api::post_vanilla(&api_url, payload).await.is_ok() {
-                    history.push(AppRoute::DeckDetail { deck_id });
-                } // TODO else ...
+                match api::post_vanilla(&api_url, payload).await {
+                    Ok(_) => history.push(AppRoute::DeckDetail { deck_id }),
+                    Err(e) if e.status == Some(409) => {
+                        duplicate.set(serde_json::from_str::<Card>(&e.to_string()).ok());
+                    }
+                    Err(_) => {} // TODO else ...
+                }
+            });
+        })
+    };
+
+    let onsubmit = {
+        let save = save.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            save.emit(false);
+        })
+    };
+
+    let on_open_duplicate = {
+        let history = history.clone();
+        Callback::from(move |existing: Card| {
+            history.push(AppRoute::CardUpdateForm {
+                deck_id: existing.deck_id,
+                card_id: existing.id,
             });
         })
     };
 
+    let on_add_anyway = Callback::from(move |_| save.emit(true));
+
     let on_delete = {
         let deck_id = *deck_id;
         let history = history.clone();
@@ -113,16 +176,35 @@ pub fn card_form(CardFormProps { deck_id, card_id }: &CardFormProps) -> Html {
             <form { onsubmit } class={ classes!("flex", "flex-col", "text-3xl", "portrait:text-6xl") }>
                 <textarea
                     value={ (*front).clone() }
-                    onchange={ on_front_change }
+                    oninput={ on_front_change }
+                    lang={ lang(CardSide::Front) }
+                    spellcheck="true"
                     placeholder={ "de face" }
                     class={ classes!("h-64") }
                 />
+                <MarkupPreview text={ (*front).clone() } />
                 <textarea
                     value={ (*back).clone() }
-                    onchange={ on_back_change }
+                    oninput={ on_back_change }
+                    lang={ lang(CardSide::Back) }
+                    spellcheck="true"
                     placeholder={ "arrière" }
                     class={ classes!("h-64") }
                 />
+                <MarkupPreview text={ (*back).clone() } />
+                {
+                    if let Some(existing) = (*duplicate).clone() {
+                        html! {
+                            <DuplicateNotice
+                                { existing }
+                                on_open={ on_open_duplicate }
+                                { on_add_anyway }
+                            />
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
                 <div class={ classes!("flex", "w-full", "justify-around", "text-3xl", "portrait:text-6xl") }>
                     <button type={ "submit" }>
                         { emojis::PENCIL }
@@ -1,12 +1,18 @@
-use common::models::{Card, Deck};
+use std::collections::{BTreeSet, HashMap};
+
+use common::models::{BulkOperation, BulkPayload, CardMatch, Deck, Media};
 use common::query_params::CardReadQuery;
+use common::search::SEARCH_HELP;
+use common::CardSide;
 use wasm_bindgen::JsCast;
-use web_sys::{Element, HtmlInputElement};
+use web_sys::{Element, HtmlInputElement, HtmlSelectElement};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use crate::api;
-use crate::components::modals::{CardFormModal, DeckFormModal};
+use crate::components::media::MediaView;
+use crate::components::modals::{CardFormModal, CramModal, DeckFormModal, DeckReorganizeModal};
+use crate::components::search::highlighted;
 use crate::emojis;
 use crate::routes::AppRoute;
 use crate::AppContext;
@@ -85,12 +91,14 @@ fn card_list(CardListProps { deck_id }: &CardListProps) -> Html {
     let deck_id = *deck_id;
     let query_params = use_state_eq(CardReadQuery::default);
 
+    // Bumped to fetch the cards again after a bulk operation changed them.
+    let refresh = use_state_eq(|| 0);
+
     // Fetch list of cards associated with this deck
     let cards = use_state_eq(|| None);
     let has_more = use_mut_ref(|| false);
     {
         let cards = cards.clone();
-        let query_params = query_params.clone();
         let has_more = has_more.clone();
         let this_page = query_params.page;
 
@@ -103,9 +111,9 @@ fn card_list(CardListProps { deck_id }: &CardListProps) -> Html {
             move |_| {
                 let cards = cards.clone();
                 wasm_bindgen_futures::spawn_local(async move {
-                    if let Ok::<api::Page<Card>, _>(page) = api::get(&url).await {
+                    if let Ok::<api::Page<CardMatch>, _>(page) = api::get(&url).await {
                         if (*cards).is_some() && this_page > 0 {
-                            let mut card_vec: Vec<Card> = (*cards).clone().unwrap();
+                            let mut card_vec: Vec<CardMatch> = (*cards).clone().unwrap();
                             card_vec.extend(page.results);
                             cards.set(Some(card_vec));
                         } else {
@@ -116,10 +124,84 @@ fn card_list(CardListProps { deck_id }: &CardListProps) -> Html {
                 });
                 || ()
             },
-            query_params, // TODO would be nice to cache by `query_params`.
+            // TODO would be nice to cache by `query_params`.
+            ((*query_params).clone(), *refresh),
+        );
+    }
+
+    // Attached files by card, for thumbnails.
+    let media = use_state_eq(HashMap::<i32, Vec<Media>>::new);
+    {
+        let media = media.clone();
+        let url = format!("/api/decks/{}/media/", deck_id);
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<Vec<Media>, _>(fetched_media) = api::get(&url).await {
+                        let mut by_card: HashMap<i32, Vec<Media>> = HashMap::new();
+                        for m in fetched_media {
+                            by_card.entry(m.card_id).or_default().push(m);
+                        }
+                        media.set(by_card);
+                    }
+                });
+                || ()
+            },
+            *refresh,
         );
     }
 
+    // `None` outside of multi-select mode.
+    let selected = use_state_eq(|| None::<BTreeSet<i32>>);
+
+    let on_select_mode_click = {
+        let selected = selected.clone();
+        Callback::from(move |_| {
+            if selected.is_some() {
+                selected.set(None);
+            } else {
+                selected.set(Some(BTreeSet::new()));
+            }
+        })
+    };
+
+    let on_select = {
+        let selected = selected.clone();
+        Callback::from(move |card_id: i32| {
+            if let Some(mut card_ids) = (*selected).clone() {
+                if !card_ids.remove(&card_id) {
+                    card_ids.insert(card_id);
+                }
+                selected.set(Some(card_ids));
+            }
+        })
+    };
+
+    let on_select_all_click = {
+        let selected = selected.clone();
+        let cards = cards.clone();
+        Callback::from(move |_| {
+            if let Some(cards) = &*cards {
+                selected.set(Some(
+                    cards.iter().map(|card_match| card_match.card.id).collect(),
+                ));
+            }
+        })
+    };
+
+    let on_bulk_done = {
+        let selected = selected.clone();
+        let query_params = query_params.clone();
+        let refresh = refresh.clone();
+        Callback::from(move |_| {
+            selected.set(None);
+            let mut new_query_params = (*query_params).clone();
+            new_query_params.page = 0;
+            query_params.set(new_query_params);
+            refresh.set(*refresh + 1);
+        })
+    };
+
     let on_search_term_input = {
         let query_params = query_params.clone();
         Callback::from(move |e: InputEvent| {
@@ -169,10 +251,38 @@ fn card_list(CardListProps { deck_id }: &CardListProps) -> Html {
                             class={ classes!("w-full", "text-center") }
                             type="text"
                             placeholder={ "Chercher" }
+                            title={ SEARCH_HELP }
                             value={ (*query_params).clone().search_term }
                             oninput={ on_search_term_input }
                         />
+                        <button
+                            onclick={ on_select_mode_click }
+                            class={ classes!("px-2", "whitespace-nowrap") }
+                        >
+                            { if selected.is_some() { "Annuler" } else { "Sélectionner" } }
+                        </button>
                     </div>
+                    {
+                        if let Some(card_ids) = (*selected).clone() {
+                            html! {
+                                <div class={ classes!("flex", "items-center", "portrait:text-4xl", "text-l") }>
+                                    <button
+                                        onclick={ on_select_all_click }
+                                        class={ classes!("px-2", "whitespace-nowrap") }
+                                    >
+                                        { "Tout" }
+                                    </button>
+                                    <BulkActions
+                                        { deck_id }
+                                        card_ids={ card_ids.into_iter().collect::<Vec<i32>>() }
+                                        on_done={ on_bulk_done }
+                                    />
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                     <div
                         { onscroll }
                         class={ classes!("h-[85vh]", "w-full", "overflow-y-auto", "px-24") }
@@ -186,8 +296,23 @@ fn card_list(CardListProps { deck_id }: &CardListProps) -> Html {
                             }
                         >
                             {
-                                cards.into_iter().map(|card| {
-                                    html! { <CardSummary { deck_id } card={ card.clone() } /> }
+                                cards.into_iter().map(|card_match| {
+                                    let is_selected = (*selected)
+                                        .as_ref()
+                                        .map(|card_ids| card_ids.contains(&card_match.card.id));
+                                    let card_media = media
+                                        .get(&card_match.card.id)
+                                        .cloned()
+                                        .unwrap_or_default();
+                                    html! {
+                                        <CardSummary
+                                            { deck_id }
+                                            { card_match }
+                                            media={ card_media }
+                                            selected={ is_selected }
+                                            on_select={ on_select.clone() }
+                                        />
+                                    }
                                 }).collect::<Html>()
                             }
                         </div>
@@ -200,6 +325,124 @@ fn card_list(CardListProps { deck_id }: &CardListProps) -> Html {
     }
 }
 
+#[derive(PartialEq, Properties)]
+struct BulkActionsProps {
+    deck_id: i32,
+    card_ids: Vec<i32>,
+    on_done: Callback<()>,
+}
+
+// What can be done to the cards picked out in multi-select mode.
+#[function_component(BulkActions)]
+fn bulk_actions(
+    BulkActionsProps {
+        deck_id,
+        card_ids,
+        on_done,
+    }: &BulkActionsProps,
+) -> Html {
+    let deck_id = *deck_id;
+
+    // Other decks, for moving cards over.
+    let decks = use_state_eq(Vec::<Deck>::new);
+    {
+        let decks = decks.clone();
+        use_effect_with_deps(
+            move |_| {
+                api::get_other_decks(
+                    deck_id,
+                    Box::new(move |fetched_decks| decks.set(fetched_decks)),
+                );
+                || ()
+            },
+            (),
+        );
+    }
+
+    let tag = use_state_eq(String::new);
+    let on_tag_input = {
+        let tag = tag.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            tag.set(input.value());
+        })
+    };
+
+    let apply = {
+        let card_ids = card_ids.clone();
+        let on_done = on_done.clone();
+        Callback::from(move |operation: BulkOperation| {
+            if card_ids.is_empty() {
+                return;
+            }
+            let on_done = on_done.clone();
+            let url = format!("/api/decks/{}/cards/bulk/", deck_id);
+            let payload = serde_json::to_value(BulkPayload {
+                card_ids: card_ids.clone(),
+                operation,
+            })
+            .unwrap();
+            wasm_bindgen_futures::spawn_local(async move {
+                if api::post_vanilla(&url, payload).await.is_ok() {
+                    on_done.emit(());
+                } // TODO else ...
+            });
+        })
+    };
+
+    let on_move_change = {
+        let apply = apply.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Ok(deck_id) = select.value().parse::<i32>() {
+                apply.emit(BulkOperation::Move { deck_id });
+            }
+        })
+    };
+
+    let button = |label: &str, operation: BulkOperation| {
+        let apply = apply.clone();
+        html! {
+            <button
+                onclick={ Callback::from(move |_| apply.emit(operation.clone())) }
+                class={ classes!("px-2", "whitespace-nowrap") }
+            >
+                { label }
+            </button>
+        }
+    };
+
+    html! {
+        <>
+            <span class={ classes!("px-2", "text-gray-400") }>
+                { format!("{} sélectionnée(s)", card_ids.len()) }
+            </span>
+            { button("Supprimer", BulkOperation::Delete) }
+            <select onchange={ on_move_change } class={ classes!("px-2", "bg-blk") }>
+                <option value="" selected=true>{ "Déplacer vers..." }</option>
+                {
+                    decks.iter().map(|deck| html! {
+                        <option value={ deck.id.to_string() }>{ &deck.name }</option>
+                    }).collect::<Html>()
+                }
+            </select>
+            <input
+                type="text"
+                class={ classes!("w-32", "mx-2") }
+                placeholder={ "étiquette" }
+                value={ (*tag).clone() }
+                oninput={ on_tag_input }
+            />
+            { button("+ étiquette", BulkOperation::AddTag { tag: (*tag).clone() }) }
+            { button("- étiquette", BulkOperation::RemoveTag { tag: (*tag).clone() }) }
+            { button("Réinitialiser", BulkOperation::ResetScheduling) }
+            { button("Inverser", BulkOperation::SwapSides) }
+            { button("Suspendre", BulkOperation::Suspend) }
+            { button("Réactiver", BulkOperation::Unsuspend) }
+        </>
+    }
+}
+
 #[derive(PartialEq, Properties)]
 struct DeckDetailToolbarProps {
     deck: Deck,
@@ -219,14 +462,42 @@ fn deck_detail_toolbar(
         Callback::from(move |_| history.push(AppRoute::Revision { deck_id }))
     };
 
+    let on_cram_click = {
+        let ctx = ctx.clone();
+        let deck = (*deck).clone();
+        Callback::from(move |_| {
+            let deck = deck.clone();
+            ctx.set_modal.emit(Some(html! {
+                <CramModal { deck } />
+            }));
+        })
+    };
+
     let on_create_click = {
-        let history = history;
+        let history = history.clone();
         let deck_id = deck.id;
         Callback::from(move |_| {
             history.push(AppRoute::CardCreateForm { deck_id });
         })
     };
 
+    let on_stats_click = {
+        let history = history;
+        let deck_id = deck.id;
+        Callback::from(move |_| history.push(AppRoute::DeckStats { deck_id }))
+    };
+
+    let on_scissors_click = {
+        let ctx = ctx.clone();
+        let deck = (*deck).clone();
+        Callback::from(move |_| {
+            let deck = deck.clone();
+            ctx.set_modal.emit(Some(html! {
+                <DeckReorganizeModal { deck } />
+            }));
+        })
+    };
+
     let on_gear_click = {
         // TODO feels like I should be doing it smarter pass borrowed instead of clone
         let deck = (*deck).clone();
@@ -256,12 +527,31 @@ fn deck_detail_toolbar(
             >
                 { emojis::BELL }
             </button>
+            <button
+                onclick={ on_cram_click }
+                class={ classes!("px-2") }
+                title={ "Bachoter" }
+            >
+                { emojis::BOOKS }
+            </button>
             <button
                 onclick={ on_create_click }
                 class={ classes!("px-2") }
             >
                 { emojis::PENCIL }
             </button>
+            <button
+                onclick={ on_stats_click }
+                class={ classes!("px-2") }
+            >
+                { emojis::CHART }
+            </button>
+            <button
+                onclick={ on_scissors_click }
+                class={ classes!("px-2") }
+            >
+                { emojis::SCISSORS }
+            </button>
             <button
                 onclick={ on_gear_click }
                 class={ classes!("px-2") }
@@ -275,13 +565,27 @@ fn deck_detail_toolbar(
 #[derive(PartialEq, Properties)]
 pub struct CardSummaryProps {
     deck_id: i32,
-    card: Card,
+    card_match: CardMatch,
+    media: Vec<Media>,
+    // Whether the card is picked, `None` outside of multi-select mode.
+    selected: Option<bool>,
+    on_select: Callback<i32>,
 }
 
 #[function_component(CardSummary)]
-fn card(CardSummaryProps { deck_id, card }: &CardSummaryProps) -> Html {
+fn card(
+    CardSummaryProps {
+        deck_id,
+        card_match,
+        media,
+        selected,
+        on_select,
+    }: &CardSummaryProps,
+) -> Html {
     let ctx = use_context::<AppContext>().unwrap();
-    fn card_content(content: &str) -> Html {
+    let card = &card_match.card;
+    fn card_content(content: &str, highlight: &Option<String>, media: Vec<Media>) -> Html {
+        let has_audio = media.iter().any(|m| m.is_audio());
         html! {
             <span
                 class={
@@ -290,19 +594,31 @@ fn card(CardSummaryProps { deck_id, card }: &CardSummaryProps) -> Html {
                     )
                 }
             >
+                <MediaView { media } class={ classes!("max-h-12", "portrait:max-h-20", "mr-2") } />
+                { if has_audio { html! { <span class={ classes!("mr-2") }>{ emojis::SPEAKER }</span> } } else { html! {} } }
                 // TODO figure out some dynamic way to truncate / clip?
-                { content }
+                // Extra div keeps highlighted segments and markup lines from becoming separate flex items.
+                <div>{ highlighted(content, highlight) }</div>
             </span>
         }
     }
+    let side_media = |side: CardSide| -> Vec<Media> {
+        media.iter().filter(|m| m.side == side).cloned().collect()
+    };
 
     let onclick = {
         let deck_id = *deck_id;
         let card_id = card.id;
+        let selecting = selected.is_some();
+        let on_select = on_select.clone();
         Callback::from(move |_| {
-            ctx.set_modal.emit(Some(html! {
-                <CardFormModal { deck_id } { card_id } />
-            }));
+            if selecting {
+                on_select.emit(card_id);
+            } else {
+                ctx.set_modal.emit(Some(html! {
+                    <CardFormModal { deck_id } { card_id } />
+                }));
+            }
         })
     };
 
@@ -315,14 +631,27 @@ fn card(CardSummaryProps { deck_id, card }: &CardSummaryProps) -> Html {
                     "rounded-lg", "border-2", "border-gray-600",
                     "bg-black", "cursor-pointer",
                     "transition", "hover:bg-gray-800", "duration-300",
+                    (*selected == Some(true)).then(|| classes!("ring-2", "ring-yellow-500")),
+                    card.suspended.then_some("opacity-50"),
                 )
             }
             { onclick }
             key={ card.id }
         >
-            { card_content(&card.front) }
+            { card_content(&card.front, &card_match.front_highlight, side_media(CardSide::Front)) }
             <hr class={ classes!("w-full", "border-gray-600", "border", "border-dashed") } />
-            { card_content(&card.back) }
+            { card_content(&card.back, &card_match.back_highlight, side_media(CardSide::Back)) }
+            {
+                if card.tags.is_empty() {
+                    html! {}
+                } else {
+                    html! {
+                        <span class={ classes!("text-sm", "text-gray-400") }>
+                            { card.tags.join(", ") }
+                        </span>
+                    }
+                }
+            }
         </div>
     }
 }
@@ -1,13 +1,17 @@
 use common::models::Deck;
+use common::query_params::TimezoneQuery;
+use common::stats::Activity;
 use serde_json::json;
 use web_sys::HtmlInputElement;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use crate::api;
+use crate::components::charts::Heatmap;
 use crate::components::modals::DeckFormModal;
 use crate::emojis;
 use crate::routes::AppRoute;
+use crate::time::browser_timezone;
 use crate::AppContext;
 
 #[function_component(DeckList)]
@@ -39,6 +43,16 @@ pub fn deck_list() -> Html {
 
     html! {
         <div class={ classes!("max-w-2xl", "h-3/5") }>
+            <ActivitySummary />
+            <div class={ classes!("text-6xl", "lg:text-3xl", "py-2") }>
+                // Whatever is due today, from every deck.
+                <span class={ classes!("px-2") }>
+                    <Link<AppRoute> to={ AppRoute::CombinedRevision }>
+                        { emojis::BELL }
+                    </Link<AppRoute>>
+                </span>
+                <span class={ classes!("px-2") }>{ "Tout réviser" }</span>
+            </div>
             <div class={ classes!("text-6xl", "lg:text-3xl") }>
                 {
                     (*decks).clone().into_iter().map(|deck| {
@@ -51,6 +65,46 @@ pub fn deck_list() -> Html {
     }
 }
 
+#[function_component(ActivitySummary)]
+fn activity_summary() -> Html {
+    let activity = use_state_eq(|| None);
+    {
+        let activity = activity.clone();
+        use_effect_with_deps(
+            move |_| {
+                let query = TimezoneQuery {
+                    tz: browser_timezone(),
+                };
+                let url = format!(
+                    "/api/stats/activity/?{}",
+                    serde_qs::to_string(&query).unwrap()
+                );
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<Activity, _>(fetched_activity) = api::get(&url).await {
+                        activity.set(Some(fetched_activity));
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    if let Some(activity) = (*activity).clone() {
+        html! {
+            <div class={ classes!("pb-8", "text-4xl", "lg:text-xl") }>
+                <Heatmap days={ activity.days } />
+                <div class={ classes!("flex", "justify-between", "pt-2") }>
+                    <span>{ format!("Série : {} j", activity.current_streak) }</span>
+                    <span>{ format!("Record : {} j", activity.longest_streak) }</span>
+                </div>
+            </div>
+        }
+    } else {
+        html! {}
+    }
+}
+
 #[derive(PartialEq, Properties)]
 pub struct DeckListRowProps {
     deck: Deck,
@@ -1,9 +1,10 @@
+use common::models::{LoginOptions, LoginOutcome};
 use serde_json::json;
 use web_sys::{Element, HtmlInputElement};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
-use crate::api::post_vanilla;
+use crate::api::{get, post, post_vanilla};
 use crate::routes::AppRoute;
 
 #[function_component(Login)]
@@ -13,12 +14,32 @@ pub fn login() -> Html {
     let password_node_ref = use_node_ref();
     let button_node_ref = use_node_ref();
     let error = use_state(|| "".to_string());
+    // Set once the password is right but the account also wants a 2FA code.
+    let challenge = use_state_eq(|| None::<String>);
+    let code_node_ref = use_node_ref();
+    let options = use_state_eq(|| None::<LoginOptions>);
+    {
+        let options = options.clone();
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<LoginOptions, _>(fetched) = get("/login/options/").await {
+                        options.set(Some(fetched));
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
 
     let onsubmit = {
         let username_node_ref = username_node_ref.clone();
         let password_node_ref = password_node_ref.clone();
         let button_node_ref = button_node_ref.clone();
+        let history = history.clone();
         let error = error.clone();
+        let challenge = challenge.clone();
 
         Callback::from(move |e: FocusEvent| {
             e.prevent_default();
@@ -30,6 +51,7 @@ pub fn login() -> Html {
 
             let history = history.clone();
             let error = error.clone();
+            let challenge = challenge.clone();
 
             if let (Some(username), Some(password)) = (username, password) {
                 let username = username.value();
@@ -46,8 +68,13 @@ pub fn login() -> Html {
                     "password": password,
                 });
                 wasm_bindgen_futures::spawn_local(async move {
-                    match post_vanilla("/login/", payload).await {
-                        Ok(_) => history.push(AppRoute::Decks),
+                    match post::<LoginOutcome>("/login/", payload).await {
+                        Ok(LoginOutcome::LoggedIn) => history.push(AppRoute::Decks),
+                        Ok(LoginOutcome::TotpRequired { challenge: token }) => {
+                            button.set_class_name("");
+                            error.set("".to_string());
+                            challenge.set(Some(token));
+                        }
                         Err(e) => {
                             button.set_class_name("");
                             error.set(e.to_string());
@@ -57,6 +84,102 @@ pub fn login() -> Html {
             };
         })
     };
+    let onforgot = {
+        let username_node_ref = username_node_ref.clone();
+        let error = error.clone();
+        Callback::from(move |_| {
+            let login = username_node_ref
+                .cast::<HtmlInputElement>()
+                .map(|input| input.value())
+                .unwrap_or_default();
+            if login.trim().is_empty() {
+                error.set("Saisissez votre nom d'utilisateur ou adresse e-mail".to_string());
+                return;
+            }
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match post_vanilla("/password-reset/", json!({ "login": login })).await {
+                    Ok(_) => error.set(
+                        "Si ce compte a une adresse e-mail, un lien vient d'y être envoyé"
+                            .to_string(),
+                    ),
+                    Err(e) => error.set(e.to_string()),
+                }
+            });
+        })
+    };
+    let on_code_submit = {
+        let code_node_ref = code_node_ref.clone();
+        let history = history.clone();
+        let error = error.clone();
+        let challenge = challenge.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            let code = match code_node_ref.cast::<HtmlInputElement>() {
+                Some(input) if !input.value().trim().is_empty() => input.value(),
+                _ => return,
+            };
+            let payload = json!({
+                "challenge": (*challenge).clone(),
+                "code": code,
+            });
+            let history = history.clone();
+            let error = error.clone();
+            let challenge = challenge.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match post::<LoginOutcome>("/login/totp/", payload).await {
+                    Ok(LoginOutcome::LoggedIn) => history.push(AppRoute::Decks),
+                    // Not handed out at this step, start over.
+                    Ok(LoginOutcome::TotpRequired { .. }) => challenge.set(None),
+                    Err(e) => error.set(e.to_string()),
+                }
+            });
+        })
+    };
+    let on_code_cancel = {
+        let challenge = challenge.clone();
+        let error = error.clone();
+        Callback::from(move |_| {
+            error.set("".to_string());
+            challenge.set(None);
+        })
+    };
+
+    if challenge.is_some() {
+        return html! {
+            <div
+                class={
+                    classes!(
+                        "h-screen", "flex", "flex-col", "justify-center", "items-center",
+                        "text-7xl",
+                        "lg:text-3xl",
+                    )
+                }
+            >
+                <form onsubmit={ on_code_submit } class={ classes!("mb-32") }>
+                    <div hidden={ (*error).is_empty() } class={ classes!("py-2") }>
+                        { (*error).clone() }
+                    </div>
+                    <div class={ classes!("py-2") }>
+                        <input
+                            type="text"
+                            name="code"
+                            inputmode="numeric"
+                            autocomplete="one-time-code"
+                            placeholder="Code ou code de secours"
+                            ref={ code_node_ref }
+                        />
+                    </div>
+                    <div class={ classes!("py-2") }>
+                        <button type="submit">{ "Valider" }</button>
+                    </div>
+                    <div class={ classes!("py-2", "text-gray-400") }>
+                        <button type="button" onclick={ on_code_cancel }>{ "Retour" }</button>
+                    </div>
+                </form>
+            </div>
+        };
+    }
 
     html! {
         <div
@@ -97,6 +220,22 @@ pub fn login() -> Html {
                         { "Se connecter" }
                     </button>
                 </div>
+                {
+                    // A full page load, the backend sends it on to the provider.
+                    match options.as_ref().and_then(|options| options.oidc_label.as_ref()) {
+                        Some(label) => html! {
+                            <div class={ classes!("py-2") }>
+                                <a href="/login/oidc/">{ label }</a>
+                            </div>
+                        },
+                        None => html! {},
+                    }
+                }
+                <div class={ classes!("py-2", "text-gray-400") }>
+                    <button type="button" onclick={ onforgot }>
+                        { "Mot de passe oublié ?" }
+                    </button>
+                </div>
             </form>
         </div>
     }
@@ -1,4 +1,7 @@
+pub(crate) mod account;
 pub(crate) mod cards;
 pub(crate) mod decks;
 pub(crate) mod login;
+pub(crate) mod reset_password;
 pub(crate) mod revision;
+pub(crate) mod stats;
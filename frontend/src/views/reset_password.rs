@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use serde_json::json;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::api::post_vanilla;
+use crate::routes::MainRoute;
+
+#[derive(Deserialize)]
+struct ResetQuery {
+    token: String,
+}
+
+#[function_component(ResetPassword)]
+pub fn reset_password() -> Html {
+    let history = use_history().unwrap();
+    let token = use_location()
+        .and_then(|location| location.query::<ResetQuery>().ok())
+        .map(|query| query.token);
+    let password_node_ref = use_node_ref();
+    let confirmation_node_ref = use_node_ref();
+    let error = use_state(|| "".to_string());
+
+    let onsubmit = {
+        let password_node_ref = password_node_ref.clone();
+        let confirmation_node_ref = confirmation_node_ref.clone();
+        let error = error.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            let value = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.value())
+                    .unwrap_or_default()
+            };
+            let password = value(&password_node_ref);
+            if password.is_empty() {
+                return;
+            }
+            if password != value(&confirmation_node_ref) {
+                error.set("Les mots de passe ne correspondent pas".to_string());
+                return;
+            }
+            let payload = json!({
+                "token": token.clone().unwrap_or_default(),
+                "password": password,
+            });
+            let history = history.clone();
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match post_vanilla("/password-reset/confirm/", payload).await {
+                    Ok(_) => history.push(MainRoute::Login),
+                    Err(e) => error.set(e.to_string()),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div
+            class={
+                classes!(
+                    "h-screen", "flex", "flex-col", "justify-center", "items-center",
+                    "text-7xl",
+                    "lg:text-3xl",
+                )
+            }
+        >
+            <form { onsubmit } class={ classes!("mb-32") }>
+                <div hidden={ (*error).is_empty() } class={ classes!("py-2") }>
+                    { (*error).clone() }
+                </div>
+                <div class={ classes!("py-2") }>
+                    <input
+                        type="password"
+                        name="password"
+                        placeholder="Nouveau mot de passe"
+                        ref={ password_node_ref }
+                    />
+                </div>
+                <div class={ classes!("py-2") }>
+                    <input
+                        type="password"
+                        name="confirmation"
+                        placeholder="Confirmation"
+                        ref={ confirmation_node_ref }
+                    />
+                </div>
+                <div class={ classes!("py-2") }>
+                    <button type="submit">{ "Changer le mot de passe" }</button>
+                </div>
+            </form>
+        </div>
+    }
+}
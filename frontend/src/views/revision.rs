@@ -1,16 +1,23 @@
-use std::fmt;
-
-use common::models::{Deck, RevisionCard};
+use common::markup::plain_text;
+use common::models::{Deck, FeedbackPayload, RevisionCard};
+use common::query_params::{CombinedRevisionQuery, RevisionQuery};
+use common::{CardSide, Rating};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use crate::api;
+use crate::components::markup::render_markup;
+use crate::components::media::{play_audio, MediaView};
+use crate::emojis;
+use crate::speech::Voice;
+use crate::time::browser_timezone;
 use crate::AppContext;
 use crate::AppRoute;
 
 #[derive(PartialEq, Properties)]
 pub struct RevisionProps {
-    pub deck_id: i32,
+    // `None` for what's due across decks, see `AppRoute::CombinedRevision`.
+    pub deck_id: Option<i32>,
 }
 
 #[function_component(Revision)]
@@ -18,27 +25,92 @@ pub fn revision(RevisionProps { deck_id }: &RevisionProps) -> Html {
     let card_queue = use_state(|| None);
     let revision_length = use_state(|| 0);
     let flipped = use_state(|| false);
+    // When the current card was put up, to measure how long it took to answer.
+    let shown_at = use_mut_ref(js_sys::Date::now);
+    // ... and when it got flipped, to tell recall time from rating time.
+    let revealed_at = use_mut_ref(|| None::<f64>);
+
+    let location = use_location();
+    // Cram sessions come with their filters in the URL, see `CramModal`.
+    let query = location
+        .as_ref()
+        .and_then(|location| location.query::<RevisionQuery>().ok())
+        .unwrap_or_default();
+    // ... and combined ones with their decks, if not all of them.
+    let deck_ids = location
+        .as_ref()
+        .and_then(|location| location.query::<CombinedRevisionQuery>().ok())
+        .map(|query| query.deck_ids)
+        .unwrap_or_default();
+
+    // Settings of the decks the cards come from, for speech and autoplay.
+    let decks = use_state_eq(Vec::<Deck>::new);
 
     let ctx = use_context::<AppContext>().unwrap();
-    api::get_deck(
-        *deck_id,
-        Box::new(move |fetched_deck: Deck| {
-            ctx.set_title.emit(fetched_deck.name);
-        }),
-    );
+    if let Some(deck_id) = *deck_id {
+        let decks = decks.clone();
+        let cram = query.cram;
+        api::get_deck(
+            deck_id,
+            Box::new(move |fetched_deck: Deck| {
+                if cram {
+                    ctx.set_title
+                        .emit(format!("{} (bachotage)", fetched_deck.name));
+                } else {
+                    ctx.set_title.emit(fetched_deck.name.clone());
+                }
+                decks.set(vec![fetched_deck]);
+            }),
+        );
+    } else {
+        ctx.set_title.emit("Révision".to_string());
+    }
+    let find_deck = |deck_id: i32| decks.iter().find(|deck| deck.id == deck_id);
+    let voice = |deck_id: i32, side: CardSide| match find_deck(deck_id) {
+        Some(deck) => Voice::for_side(deck, side),
+        None => Voice::default(),
+    };
+    let autoplay = |deck_id: i32, side: CardSide| match find_deck(deck_id) {
+        Some(deck) => match side {
+            CardSide::Front => deck.autoplay_front,
+            CardSide::Back => deck.autoplay_back,
+        },
+        None => false,
+    };
 
     {
         let card_queue = card_queue.clone();
         let revision_length = revision_length.clone();
+        let shown_at = shown_at.clone();
+        let decks = decks.clone();
         let deck_id = *deck_id;
+        let query = query.clone();
         use_effect_with_deps(
             move |_| {
                 let card_queue = card_queue.clone();
                 wasm_bindgen_futures::spawn_local(async move {
-                    let url = format!("/api/decks/{}/revision/", deck_id);
+                    let url = match deck_id {
+                        Some(deck_id) => format!(
+                            "/api/decks/{}/revision/?{}",
+                            deck_id,
+                            serde_qs::to_string(&query).unwrap()
+                        ),
+                        None => {
+                            if let Ok::<Vec<Deck>, _>(fetched_decks) = api::get("/api/decks/").await
+                            {
+                                decks.set(fetched_decks);
+                            }
+                            let query = CombinedRevisionQuery {
+                                deck_ids,
+                                tz: browser_timezone(),
+                            };
+                            format!("/api/revision/?{}", serde_qs::to_string(&query).unwrap())
+                        }
+                    };
                     if let Ok::<Vec<RevisionCard>, _>(fetched_cards) = api::get(&url).await {
                         revision_length.set(fetched_cards.len());
                         card_queue.set(Some(fetched_cards));
+                        *shown_at.borrow_mut() = js_sys::Date::now();
                     };
                 });
                 || ()
@@ -49,7 +121,11 @@ pub fn revision(RevisionProps { deck_id }: &RevisionProps) -> Html {
 
     let on_card_click = {
         let flipped = flipped.clone();
-        Callback::from(move |_| flipped.set(true))
+        let revealed_at = revealed_at.clone();
+        Callback::from(move |_| {
+            *revealed_at.borrow_mut() = Some(js_sys::Date::now());
+            flipped.set(true);
+        })
     };
 
     let on_feedback_click = match &*card_queue {
@@ -57,17 +133,39 @@ pub fn revision(RevisionProps { deck_id }: &RevisionProps) -> Html {
             let card_queue = card_queue.clone();
             let cards = cards.clone();
             let flipped = flipped.clone();
+            let shown_at = shown_at.clone();
+            let revealed_at = revealed_at.clone();
+            let cram = query.cram;
 
-            Callback::from(move |feedback: Feedback| {
+            Callback::from(move |rating: Rating| {
                 let mut cards = cards.clone();
                 flipped.set(false);
                 let popped = cards.pop();
                 card_queue.set(Some(cards));
 
+                let now = js_sys::Date::now();
+                let answer_ms = (now - *shown_at.borrow()) as i32;
+                let (reveal_ms, rate_ms) = match revealed_at.borrow_mut().take() {
+                    Some(revealed) => (
+                        Some((revealed - *shown_at.borrow()) as i32),
+                        Some((now - revealed) as i32),
+                    ),
+                    None => (None, None),
+                };
+                *shown_at.borrow_mut() = now;
+
                 if let Some(card) = popped {
                     wasm_bindgen_futures::spawn_local(async move {
                         let url = format!("/api/cards/{}/feedback/", card.id);
-                        let payload = serde_json::Value::String(feedback.to_string());
+                        let payload = serde_json::to_value(FeedbackPayload {
+                            rating,
+                            answer_ms: Some(answer_ms),
+                            reveal_ms,
+                            rate_ms,
+                            cram,
+                            reverse: card.first_side == CardSide::Back,
+                        })
+                        .unwrap();
                         api::post_vanilla(&url, payload).await.ok();
                     });
                 }
@@ -104,10 +202,25 @@ pub fn revision(RevisionProps { deck_id }: &RevisionProps) -> Html {
                             }
                         }
                     }
+                    {
+                        if deck_id.is_none() {
+                            html! {
+                                <div class={ classes!("text-xl", "portrait:text-3xl", "text-gray-400") }>
+                                    { &c.deck_name }
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                     <div class={ classes!("h-[40vh]", "flex", "items-end") }>
                         <RevisionCardDisplay
                             card={ c.clone() }
                             flipped={ *flipped.clone() }
+                            autoplay_first={ autoplay(c.deck_id, c.first_side) }
+                            autoplay_second={ autoplay(c.deck_id, second_side(c.first_side)) }
+                            first_voice={ voice(c.deck_id, c.first_side) }
+                            second_voice={ voice(c.deck_id, second_side(c.first_side)) }
                         />
                     </div>
                     {
@@ -126,9 +239,12 @@ pub fn revision(RevisionProps { deck_id }: &RevisionProps) -> Html {
                     </div>
                 </div>
             },
-            None => html! {
+            None => match *deck_id {
                 // All done!
-                <Redirect<AppRoute> to={AppRoute::DeckDetail { deck_id: *deck_id }}/>
+                Some(deck_id) => {
+                    html! { <Redirect<AppRoute> to={AppRoute::DeckDetail { deck_id }}/> }
+                }
+                None => html! { <Redirect<AppRoute> to={AppRoute::Decks}/> },
             },
         }
     } else {
@@ -136,10 +252,23 @@ pub fn revision(RevisionProps { deck_id }: &RevisionProps) -> Html {
     }
 }
 
+fn second_side(first_side: CardSide) -> CardSide {
+    match first_side {
+        CardSide::Front => CardSide::Back,
+        CardSide::Back => CardSide::Front,
+    }
+}
+
 #[derive(PartialEq, Properties)]
 struct RevisionCardDisplayProps {
     card: RevisionCard,
     flipped: bool,
+    // Whether to play the side's audio when it comes up, per the deck's settings.
+    autoplay_first: bool,
+    autoplay_second: bool,
+    // Language and voice of each side, for speech synthesis and the `lang` attributes.
+    first_voice: Voice,
+    second_voice: Voice,
 }
 
 // TODO probably should pull out a common component to use here and in the card list.
@@ -151,14 +280,88 @@ fn revision_card(props: &RevisionCardDisplayProps) -> Html {
         "cursor-pointer"
     };
 
+    {
+        let card = props.card.clone();
+        let flipped = props.flipped;
+        let autoplay_first = props.autoplay_first;
+        let autoplay_second = props.autoplay_second;
+        use_effect_with_deps(
+            move |_| {
+                if !flipped && autoplay_first {
+                    play_audio(&card.first_media);
+                } else if flipped && autoplay_second {
+                    play_audio(&card.second_media);
+                }
+                || ()
+            },
+            (props.card.id, props.flipped),
+        );
+    }
+
+    let has_audio = props.card.first_media.iter().any(|m| m.is_audio())
+        || (props.flipped && props.card.second_media.iter().any(|m| m.is_audio()));
+    let on_replay_click = {
+        let card = props.card.clone();
+        let flipped = props.flipped;
+        Callback::from(move |_| {
+            if flipped && card.second_media.iter().any(|m| m.is_audio()) {
+                play_audio(&card.second_media);
+            } else {
+                play_audio(&card.first_media);
+            }
+        })
+    };
+
+    let on_speak_click = {
+        let card = props.card.clone();
+        let flipped = props.flipped;
+        let first_voice = props.first_voice.clone();
+        let second_voice = props.second_voice.clone();
+        Callback::from(move |_| {
+            if flipped {
+                second_voice.speak(&plain_text(&card.second));
+            } else {
+                first_voice.speak(&plain_text(&card.first));
+            }
+        })
+    };
+
     html! {
         <div class={ classes!("flex", "flex-col", "items-center", cursor) }>
-            <div class={ "text-center mb-10" }>{ &props.card.first }</div>
+            <MediaView media={ props.card.first_media.clone() } class={ classes!("max-h-[20vh]", "mb-5") } />
+            // Above the click-anywhere overlay of the unflipped card.
+            <div class={ classes!("relative", "z-10", "mb-5") }>
+                <button onclick={ on_speak_click } class={ classes!("px-2") } title={ "Lire à voix haute" }>
+                    { emojis::SPEAKING_HEAD }
+                </button>
+                {
+                    if has_audio {
+                        html! {
+                            <button onclick={ on_replay_click } class={ classes!("px-2") }>
+                                { emojis::SPEAKER }
+                            </button>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+            <div class={ "text-center mb-10" } lang={ props.first_voice.lang.clone() }>
+                { render_markup(&props.card.first) }
+            </div>
             {
                 if props.flipped {
                     html! {
                         // TODO this should always take up a fixed height.
-                        <div class={ "text-center mb-10" }>{ &props.card.second }</div>
+                        <>
+                            <MediaView
+                                media={ props.card.second_media.clone() }
+                                class={ classes!("max-h-[20vh]", "mb-5") }
+                            />
+                            <div class={ "text-center mb-10" } lang={ props.second_voice.lang.clone() }>
+                                { render_markup(&props.card.second) }
+                            </div>
+                        </>
                     }
                 } else {
                     html! {}
@@ -168,38 +371,14 @@ fn revision_card(props: &RevisionCardDisplayProps) -> Html {
     }
 }
 
-#[derive(Clone, PartialEq)]
-pub enum Feedback {
-    Fail,
-    Hard,
-    Good,
-    Easy,
-}
-
-impl fmt::Display for Feedback {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Feedback::Fail => write!(f, "fail"),
-            Feedback::Hard => write!(f, "hard"),
-            Feedback::Good => write!(f, "good"),
-            Feedback::Easy => write!(f, "easy"),
-        }
-    }
-}
-
 #[derive(PartialEq, Properties)]
 struct FeedbackBarProps {
-    onclick: Callback<Feedback>,
+    onclick: Callback<Rating>,
 }
 
 #[function_component(FeedbackBar)]
 fn feedback_bar(FeedbackBarProps { onclick }: &FeedbackBarProps) -> Html {
-    let feedbacks = [
-        Feedback::Fail,
-        Feedback::Hard,
-        Feedback::Good,
-        Feedback::Easy,
-    ];
+    let feedbacks = [Rating::Fail, Rating::Hard, Rating::Good, Rating::Easy];
 
     html! {
         <div class={ classes!("flex", "flex-row") }>
@@ -219,8 +398,8 @@ fn feedback_bar(FeedbackBarProps { onclick }: &FeedbackBarProps) -> Html {
 
 #[derive(PartialEq, Properties)]
 struct FeedbackButtonProps {
-    feedback: Feedback,
-    onclick: Callback<Feedback>,
+    feedback: Rating,
+    onclick: Callback<Rating>,
 }
 
 #[function_component(FeedbackButton)]
@@ -232,10 +411,10 @@ fn feedback_button(props: &FeedbackButtonProps) -> Html {
     };
 
     let color = match props.feedback {
-        Feedback::Fail => "bg-red-500",
-        Feedback::Hard => "bg-orange-500",
-        Feedback::Good => "bg-yellow-500",
-        Feedback::Easy => "bg-green-500",
+        Rating::Fail => "bg-red-500",
+        Rating::Hard => "bg-orange-500",
+        Rating::Good => "bg-yellow-500",
+        Rating::Easy => "bg-green-500",
     };
 
     html! {
@@ -0,0 +1,124 @@
+use common::models::Deck;
+use common::query_params::TimezoneQuery;
+use common::stats::{AnswerTimes, DayCount, Stats};
+use yew::prelude::*;
+
+use crate::api;
+use crate::components::charts::{Bar, BarChart};
+use crate::time::browser_timezone;
+use crate::AppContext;
+
+#[derive(PartialEq, Properties)]
+pub struct StatsDashboardProps {
+    pub deck_id: Option<i32>,
+}
+
+#[function_component(StatsDashboard)]
+pub fn stats_dashboard(StatsDashboardProps { deck_id }: &StatsDashboardProps) -> Html {
+    let deck_id = *deck_id;
+    let stats = use_state_eq(|| None);
+    let ctx = use_context::<AppContext>().unwrap();
+    {
+        let stats = stats.clone();
+        use_effect_with_deps(
+            move |_| {
+                let url = match deck_id {
+                    Some(deck_id) => {
+                        api::get_deck(
+                            deck_id,
+                            Box::new(move |fetched_deck: Deck| {
+                                ctx.set_title
+                                    .emit(format!("{} - Statistiques", fetched_deck.name));
+                            }),
+                        );
+                        format!("/api/decks/{}/stats/", deck_id)
+                    }
+                    None => {
+                        ctx.set_title.emit("Statistiques".to_string());
+                        "/api/stats/".to_string()
+                    }
+                };
+                let query = TimezoneQuery {
+                    tz: browser_timezone(),
+                };
+                let url = format!("{}?{}", url, serde_qs::to_string(&query).unwrap());
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Ok::<Stats, _>(fetched_stats) = api::get(&url).await {
+                        stats.set(Some(fetched_stats));
+                    }
+                });
+                || ()
+            },
+            deck_id,
+        );
+    }
+
+    fn day_bars(days: &[DayCount]) -> Vec<Bar> {
+        days.iter()
+            .map(|day| Bar::new(day.date.format("%d/%m").to_string(), day.count as f64))
+            .collect()
+    }
+
+    if let Some(stats) = (*stats).clone() {
+        let ratings = vec![
+            Bar::new("fail", stats.ratings.fail as f64).color("fill-red-500"),
+            Bar::new("hard", stats.ratings.hard as f64).color("fill-orange-500"),
+            Bar::new("good", stats.ratings.good as f64).color("fill-yellow-500"),
+            Bar::new("easy", stats.ratings.easy as f64).color("fill-green-500"),
+        ];
+        let retention = stats
+            .retention
+            .iter()
+            .map(|bucket| {
+                let percent = if bucket.reviews > 0 {
+                    (100 * bucket.passed / bucket.reviews) as f64
+                } else {
+                    0.0
+                };
+                Bar::new(format!("{} ({})", bucket.label, bucket.reviews), percent)
+            })
+            .collect::<Vec<Bar>>();
+        let seconds = |ms: Option<i64>| match ms {
+            Some(ms) => format!("{:.1} s", ms as f64 / 1000.0),
+            None => "-".to_string(),
+        };
+        let average_answer = seconds(stats.average_answer_ms);
+        let average_reveal = seconds(stats.average_reveal_ms);
+        fn time_bars(times: &[AnswerTimes]) -> Vec<Bar> {
+            times
+                .iter()
+                .map(|t| {
+                    let ms = t.average_answer_ms.unwrap_or(0);
+                    Bar::new(format!("{} ({})", t.label, t.reviews), ms as f64 / 1000.0)
+                })
+                .collect()
+        }
+
+        html! {
+            <div
+                class={
+                    classes!(
+                        "h-[90vh]", "w-2/3", "overflow-y-auto",
+                        "portrait:text-4xl", "text-xl",
+                    )
+                }
+            >
+                <BarChart title={ "Révisions par jour" } bars={ day_bars(&stats.reviews_per_day) } />
+                <BarChart title={ "Réponses" } bars={ ratings } />
+                <BarChart title={ "Rétention par intervalle (%)" } bars={ retention } />
+                <div class={ classes!("py-4") }>
+                    { format!("Temps de réponse moyen : {}", average_answer) }
+                </div>
+                <div class={ classes!("py-4") }>
+                    { format!("Temps moyen avant de retourner la carte : {}", average_reveal) }
+                </div>
+                <BarChart title={ "Temps de réponse par paquet (s)" } bars={ time_bars(&stats.deck_times) } />
+                <BarChart title={ "Cartes les plus lentes (s)" } bars={ time_bars(&stats.card_times) } />
+                <BarChart title={ "Cartes ajoutées par jour" } bars={ day_bars(&stats.cards_added_per_day) } />
+                <BarChart title={ "Cartes à réviser" } bars={ day_bars(&stats.due_forecast) } />
+            </div>
+        }
+    } else {
+        html! {}
+    }
+}